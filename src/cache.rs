@@ -2,76 +2,161 @@ use std::collections::HashMap;
 use std::sync::RwLock;
 use lazy_static::lazy_static;
 use crate::schema::ProjectConfig;
-use crate::helpers;
-use std::fs::read_to_string;
+use crate::resources;
+use crate::project_name::ProjectName;
+use crate::store::{self, Stamp};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// A cached project config, with the stamp of the stored config it matches.
+struct CachedConfig {
+    config: Arc<ProjectConfig>,
+    stamp: Option<Stamp>,
+}
 
 lazy_static! {
-    static ref PROJECT_CACHE: RwLock<HashMap<String, Arc<ProjectConfig>>> = 
+    static ref PROJECT_CACHE: RwLock<HashMap<ProjectName, CachedConfig>> =
         RwLock::new(HashMap::new());
 }
 
-fn get_cached_config(project_name: &str) -> Option<Arc<ProjectConfig>> {
+fn get_cached_config(project_name: &ProjectName) -> Option<Arc<ProjectConfig>> {
     let cache = PROJECT_CACHE.read().unwrap();
-    cache.get(project_name).cloned()
+    cache.get(project_name).map(|cached| Arc::clone(&cached.config))
 }
 
 
-pub fn cache_config(project_name: String, config: ProjectConfig) {
+/// Caches a config ready to serve, i.e. with its routes built, as just saved
+/// to the store.
+pub fn cache_config(project_name: &ProjectName, config: ProjectConfig) {
+    let stamp = store::store().stamp(project_name);
+    let mut cache = PROJECT_CACHE.write().unwrap();
+    cache.insert(project_name.clone(), CachedConfig { config: Arc::new(config), stamp });
+}
+
+/// Removes a project from the cache, so that it is read from the store again.
+pub fn invalidate_cache(project_name: &ProjectName) {
     let mut cache = PROJECT_CACHE.write().unwrap();
-    cache.insert(project_name, Arc::new(config));
+    cache.remove(project_name);
 }
 
-// pub fn invalidate_cache(project_name: &str) {
-//     let mut cache = PROJECT_CACHE.write().unwrap();
-//     cache.remove(project_name);
-// }
+/// Removes every project from the cache.
+pub fn flush_cache() {
+    PROJECT_CACHE.write().unwrap().clear();
+}
 
-fn load_file_to_cache(project_name: &str) -> Result<Arc<ProjectConfig>, String> {
-    let config_path = helpers::get_project_config_file_path(project_name);
-    if !config_path.exists() {
-        return Err("Project does not exist.".to_string());
-    }
-    let content = read_to_string(config_path)
-        .map_err(|e| format!("Invalid project configuration file: {}", e))?;
+fn load_to_cache(project_name: &ProjectName) -> Result<Arc<ProjectConfig>, String> {
+    let store = store::store();
+    // Taken before reading, so that a write during the read is noticed later.
+    let stamp = store.stamp(project_name);
+    let content = store.load(project_name)
+        .map_err(|e| format!("Invalid project configuration file: {}", e))?
+        .ok_or_else(|| "Project does not exist.".to_string())?;
 
     let mut config: ProjectConfig = serde_json::from_str(&content)
         .map_err(|e| format!("Invalid project configuration format: {}", e))?;
+    config.validate()
+        .map_err(|e| format!("Invalid project configuration format: {}", e))?;
 
-    // Build condition map for each endpoint
-    for endpoint in config.endpoints.values_mut() {
-        if endpoint.condition_map.is_empty() {
-            endpoint.build_condition_map();
-        }
-    }
+    config.build_routes();
 
-    // Cache the config and return the Arc 
+    // Cache the config and return the Arc
     let config_arc = Arc::new(config);
-    cache_config(project_name.to_string(), (*config_arc).clone());
+    let mut cache = PROJECT_CACHE.write().unwrap();
+    cache.insert(project_name.clone(), CachedConfig { config: Arc::clone(&config_arc), stamp });
     Ok(config_arc)
-} 
+}
 
-pub fn get_or_else_load_cached_config(project_name: &str) -> Result<Arc<ProjectConfig>, String> {
+/// Returns a project's config, from the cache or else read from the store. A
+/// cache hit only shares the cached config.
+pub fn get_or_else_load_cached_config(project_name: &ProjectName) -> Result<Arc<ProjectConfig>, String> {
     match get_cached_config(project_name) {
-        Some(config) => {
-            // Create a mutable clone of the config
-            let mut config_clone = (*config).clone();
-            // Build condition maps for endpoints
-            for endpoint in config_clone.endpoints.values_mut() {
-                if endpoint.condition_map.is_empty() {
-                    endpoint.build_condition_map();
+        Some(config) => Ok(config),
+        None => load_to_cache(project_name),
+    }
+}
+
+/// Reloads the cached projects whose configs changed in the store, e.g. files
+/// edited in an IDE or by a git checkout, and evicts those that were removed
+/// or no longer hold a valid config. Scenario states are kept; the resources
+/// return to their seed data if it changed.
+pub fn reload_changed_configs() {
+    let store = store::store();
+    let cached: Vec<(ProjectName, Option<Stamp>, Arc<ProjectConfig>)> = PROJECT_CACHE
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(project_name, cached)| (project_name.clone(), cached.stamp, Arc::clone(&cached.config)))
+        .collect();
+    // Stamped without holding the cache lock, which every mock request
+    // needs, as a stamp may take a query or a file read.
+    for (project_name, stamp, old_config) in cached {
+        if store.stamp(&project_name) == stamp {
+            continue;
+        }
+        invalidate_cache(&project_name);
+        match load_to_cache(&project_name) {
+            Ok(config) => {
+                let seed_data = |config: &ProjectConfig| serde_json::to_value(&config.resources).ok();
+                if seed_data(&config) != seed_data(&old_config) {
+                    resources::reset_project(&project_name);
                 }
             }
-            // Cache the updated config and return it
-            let updated_config = Arc::new(config_clone);
-            cache_config(project_name.to_string(), (*updated_config).clone());
-            Ok(updated_config)
-        },
-        None => {
-            match load_file_to_cache(project_name) {
-                Ok(config) => Ok(config),
-                Err(e) => Err(e),
-            }
+            Err(e) => eprintln!("Evicted project {} from the cache: {}", project_name, e),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Starts a thread that checks the cached projects for changes in the store
+/// at the given interval.
+pub fn watch(interval: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        reload_changed_configs();
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reload_changed_configs() {
+        let test_dir = TempDir::new().unwrap();
+        fs::create_dir_all(test_dir.path().join("projects")).unwrap();
+        temp_env::with_var("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap()), || {
+            let project = &ProjectName::parse("cache-test-reload").unwrap();
+            let path = helpers::get_project_config_file_path(project);
+            fs::write(&path, r#"{"description": "v1", "endpoints": {}}"#).unwrap();
+            let first = get_or_else_load_cached_config(project).unwrap();
+            assert!(Arc::ptr_eq(&first, &get_or_else_load_cached_config(project).unwrap()));
+
+            fs::write(&path, r#"{"description": "version 2", "endpoints": {}}"#).unwrap();
+            reload_changed_configs();
+            assert_eq!(get_cached_config(project).unwrap().description, "version 2");
+
+            fs::write(&path, r#"{"description": "broken"#).unwrap();
+            reload_changed_configs();
+            assert!(get_cached_config(project).is_none());
+
+            fs::write(&path, r#"{"description": "v3", "endpoints": {}}"#).unwrap();
+            assert_eq!(get_or_else_load_cached_config(project).unwrap().description, "v3");
+            fs::remove_file(&path).unwrap();
+            reload_changed_configs();
+            assert!(get_cached_config(project).is_none());
+        });
+    }
+
+    #[test]
+    fn test_invalidate_cache() {
+        let project = &ProjectName::parse("cache-test-invalidate").unwrap();
+        cache_config(project, serde_json::from_str(r#"{"description": "x", "endpoints": {}}"#).unwrap());
+        assert!(get_cached_config(project).is_some());
+        invalidate_cache(project);
+        assert!(get_cached_config(project).is_none());
+    }
+}
@@ -0,0 +1,142 @@
+//! Minimal date/time helpers, so that dates can be compared without
+//! pulling in a date library.
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Proleptic Gregorian date of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Parses a fixed number of ASCII digits.
+fn digits(s: &str, range: std::ops::Range<usize>) -> Option<i64> {
+    let part = s.get(range)?;
+    if part.bytes().all(|b| b.is_ascii_digit()) {
+        part.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Parses an ISO 8601 date or date-time into milliseconds since the Unix epoch.
+/// Accepts `YYYY-MM-DD`, optionally followed by `T` (or a space) and
+/// `HH:MM[:SS[.fff]]` with an optional `Z` or `±HH:MM` offset. Times without
+/// an offset are taken as UTC.
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if s.get(4..5) != Some("-") || s.get(7..8) != Some("-") {
+        return None;
+    }
+    let (year, month, day) = (digits(s, 0..4)?, digits(s, 5..7)?, digits(s, 8..10)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut millis = days_from_civil(year, month, day) * 86_400_000;
+    let rest = s.get(10..)?;
+    if rest.is_empty() {
+        return Some(millis);
+    }
+    let time = rest.strip_prefix('T').or_else(|| rest.strip_prefix('t')).or_else(|| rest.strip_prefix(' '))?;
+    if time.get(2..3) != Some(":") {
+        return None;
+    }
+    let (hour, minute) = (digits(time, 0..2)?, digits(time, 3..5)?);
+    let mut rest = time.get(5..)?;
+    let mut second = 0;
+    if let Some(after_colon) = rest.strip_prefix(':') {
+        second = digits(after_colon, 0..2)?;
+        rest = after_colon.get(2..)?;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    millis += ((hour * 60 + minute) * 60 + second) * 1000;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(|b| b.is_ascii_digit()).count();
+        if len == 0 {
+            return None;
+        }
+        let padded = format!("{:0<3}", &fraction[..len.min(3)]);
+        millis += padded.parse::<i64>().ok()?;
+        rest = &fraction[len..];
+    }
+    match rest {
+        "" | "Z" | "z" => Some(millis),
+        offset => {
+            let sign = match offset.get(0..1)? {
+                "+" => 1,
+                "-" => -1,
+                _ => return None,
+            };
+            let offset = offset[1..].replace(':', "");
+            if offset.len() != 4 {
+                return None;
+            }
+            let minutes = digits(&offset, 0..2)? * 60 + digits(&offset, 2..4)?;
+            Some(millis - sign * minutes * 60_000)
+        }
+    }
+}
+
+/// Formats milliseconds since the Unix epoch as an ISO 8601 UTC date-time,
+/// e.g. `2024-03-01T08:30:00.000Z`.
+pub fn format_timestamp(millis: i64) -> String {
+    let (days, millis_of_day) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    let seconds = millis_of_day / 1000;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60, millis_of_day % 1000
+    )
+}
+
+/// Milliseconds since the Unix epoch of the current time.
+pub fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(parse_timestamp("2024-03-01T00:00:00Z"), Some(1_709_251_200_000));
+        assert_eq!(parse_timestamp("2024-03-01T01:00:00+01:00"), Some(1_709_251_200_000));
+        assert_eq!(parse_timestamp("2024-03-01 00:00:00.5"), Some(1_709_251_200_500));
+        assert_eq!(parse_timestamp("2024-13-01"), None);
+        assert_eq!(parse_timestamp("20240301"), None);
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_timestamp(1_709_251_200_500), "2024-03-01T00:00:00.500Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59.999Z");
+        for millis in [951_782_400_000, 4_107_542_399_999] {
+            assert_eq!(parse_timestamp(&format_timestamp(millis)), Some(millis));
+        }
+    }
+}
@@ -1,68 +1,76 @@
-use crate::{helpers::{self, get_project_config_file_path}, llm::compose_config, schema::{self, EndpointKey, QueryParam}, web_server::{self, types::Method}};
+use crate::{datetime, helpers, llm::compose_config, matcher, openapi, project_name::ProjectName, proxy, random::Rng, resources, schema::{self, HeaderRule, QueryParam}, state, store, template, validation, web_server::{self, types::Method}};
 use serde_json::Value;
-use std::{collections::HashMap, fs, fs::read_to_string};
-use web_server::types::{Nested, Request, Response};
+use std::{borrow::Cow, collections::HashMap, time::Duration};
+use web_server::types::{Fault, Nested, NestedValue, Request, Response};
 use crate::cache;
 
 
 pub fn get_config() -> impl Fn(Request) -> Response {
     |request: Request| {
-        let file = helpers::config_file_path_from_request(&request);
-
-        if file.exists() {
-            let content = read_to_string(file).unwrap();
-            let mut headers = HashMap::new();
-            headers.insert(
-                String::from("Content-Type"),
-                String::from("application/json"),
-            );
-            Response::ok(content, Some(headers))
-        } else {
-            let mut body = Nested::new();
-            body.insert_string("error".to_string(), "Project does not exist.".to_string());
-            Response::json(404, body, None)
+        let project_name = match helpers::project_name_from_request(&request) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
+        };
+        match store::store().load(&project_name) {
+            Ok(Some(content)) => {
+                let mut headers = HashMap::new();
+                headers.insert(
+                    String::from("Content-Type"),
+                    String::from("application/json"),
+                );
+                Response::ok(content, Some(headers))
+            }
+            Ok(None) => {
+                let mut body = Nested::new();
+                body.insert_string("error".to_string(), "Project does not exist.".to_string());
+                Response::json(404, body, None)
+            }
+            Err(e) => store_error(e),
         }
     }
 }
 
 
-/// Returns a closure that saves a project's config.
+/// Returns a closure that saves a project's config. The config is parsed
+/// and checked before the store is touched, so that an invalid config never
+/// replaces a working one.
 pub fn save_config() -> impl Fn(Request) -> Response {
   |request: Request| {
-    let file_path = helpers::config_file_path_from_request(&request);
+    let project_name = match helpers::project_name_from_request(&request) {
+      Ok(project_name) => project_name,
+      Err(e) => return invalid_project_name(e),
+    };
+    let store = store::store();
+    let exists = match store.exists(&project_name) {
+      Ok(exists) => exists,
+      Err(e) => return store_error(e),
+    };
 
     if request.method == Method::Post {
       let mut body = Nested::new();
-      if file_path.exists() {
+      if exists {
         body.insert_string("error".to_string(), "Project already exists.".to_string());
         return Response::json(400, body, None);
       }
-    } else if request.method == Method::Put && !file_path.exists() {
+    } else if request.method == Method::Put && !exists {
       let mut body = Nested::new();
       body.insert_string("error".to_string(), "Project does not exist.".to_string());
       return Response::json(400, body, None);
     }
 
-    // Write empty string if body is empty/missing
-    let content = request.body.as_str();
-    // if content is an empty string, return
-    match fs::write(file_path, content) {
+    let config = match validation::parse_config(&request.body) {
+      Ok(config) => config,
+      Err(e) => return invalid_config_response(&e),
+    };
+    match store.save(&project_name, &request.body) {
         Ok(_) => {
-            // Invalidate cache when config is updated
-            // cache::invalidate_cache(&request.params["name"]);
-            let project_name = helpers::project_name_from_request(&request);
-            match cache::get_or_else_load_cached_config(&project_name) {
-                Ok(_) => {
-                    let mut body = Nested::new();
-                    body.insert_string("result".to_string(), "ok".to_string());
-                    return Response::json(200, body, None)
-                },
-                Err(e) => {
-                    let mut body = Nested::new();
-                    body.insert_string("error".to_string(), format!("Failed to save config: {}", e));
-                    return Response::json(500, body, None)
-                }
-            };
+            // A new config starts its scenarios, sequences and resources over.
+            state::reset_project(&project_name);
+            resources::reset_project(&project_name);
+            cache::cache_config(&project_name, config);
+            let mut body = Nested::new();
+            body.insert_string("result".to_string(), "ok".to_string());
+            Response::json(200, body, None)
         }
         Err(e) => {
             eprintln!("Failed to write config: {}", e);
@@ -75,18 +83,176 @@ pub fn save_config() -> impl Fn(Request) -> Response {
 }
 
 
-pub fn build_config_with_llm() -> impl Fn(Request) -> Response {
+/// Returns a closure that lists the projects with their description, number
+/// of endpoints and modification time, e.g. `{"projects": [{"name": "shop",
+/// "description": "...", "endpoints": 3, "modified": "2024-03-01T08:30:00.000Z"}]}`.
+/// A project whose config cannot be read is listed with its error.
+pub fn list_projects() -> impl Fn(Request) -> Response {
+    |_request: Request| {
+        let store = store::store();
+        let project_names = match store.list() {
+            Ok(project_names) => project_names,
+            Err(e) => return store_error(e),
+        };
+        let projects: Vec<Value> = project_names
+            .iter()
+            .map(|project_name| {
+                let modified = store.stamp(project_name).map(|stamp| {
+                    let millis = stamp.modified.duration_since(std::time::UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as i64);
+                    datetime::format_timestamp(millis)
+                });
+                let mut project = serde_json::json!({"name": &project_name[..], "modified": modified});
+                let config = store.load(project_name).and_then(|content| {
+                    let content = content.ok_or_else(|| "Project does not exist.".to_string())?;
+                    serde_json::from_str::<Value>(&content).map_err(|e| format!("Invalid project configuration format: {}", e))
+                });
+                match config {
+                    Ok(config) => {
+                        project["description"] = config.get("description").cloned().unwrap_or(Value::Null);
+                        project["endpoints"] = config.get("endpoints").and_then(Value::as_object).map_or(0, |endpoints| endpoints.len()).into();
+                    }
+                    Err(e) => project["error"] = Value::String(e),
+                }
+                project
+            })
+            .collect();
+        let mut headers = HashMap::new();
+        headers.insert(String::from("Content-Type"), String::from("application/json"));
+        Response::ok(serde_json::json!({"projects": projects}).to_string(), Some(headers))
+    }
+}
+
+
+/// Returns a closure that deletes a project, with its cached config, its
+/// scenario states and its resources.
+pub fn delete_project() -> impl Fn(Request) -> Response {
     |request: Request| {
-        let project_name = match request.params.get("name") {
-            Some(name) => name,
-            None => {
-                let mut body = Nested::new();
-                body.insert_string("error".to_string(), "Project name missing.".to_string());
-                return Response::json(400, body, None);
-            }
+        let project_name = match helpers::project_name_from_request(&request) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
         };
-        let config_file_path = get_project_config_file_path(project_name);
+        let mut body = Nested::new();
+        match store::store().delete(&project_name) {
+            Ok(true) => {}
+            Ok(false) => {
+                body.insert_string("error".to_string(), "Project does not exist.".to_string());
+                return Response::json(404, body, None);
+            }
+            Err(e) => return store_error(e),
+        }
+        forget_project(&project_name);
+        body.insert_string("result".to_string(), "ok".to_string());
+        Response::json(200, body, None)
+    }
+}
+
+
+/// Returns a closure that copies a project under the name given by a body
+/// like `{"name": "shop-copy"}`. The copy starts with fresh scenarios and
+/// resources.
+pub fn clone_project() -> impl Fn(Request) -> Response {
+    |request: Request| copy_project(&request, false)
+}
+
+
+/// Returns a closure that renames a project to the name given by a body
+/// like `{"name": "shop-v2"}`. The project starts over with fresh scenarios
+/// and resources.
+pub fn rename_project() -> impl Fn(Request) -> Response {
+    |request: Request| copy_project(&request, true)
+}
+
+
+/// Copies a project to the name in the request body, and removes the
+/// original project if asked to.
+fn copy_project(request: &Request, remove_original: bool) -> Response {
+    let project_name = match helpers::project_name_from_request(request) {
+        Ok(project_name) => project_name,
+        Err(e) => return invalid_project_name(e),
+    };
+    let mut body = Nested::new();
+    let new_name = serde_json::from_str::<Value>(&request.body)
+        .ok()
+        .and_then(|body| body.get("name").and_then(Value::as_str).map(str::to_string));
+    let new_name = match new_name.map(|new_name| ProjectName::parse(&new_name)) {
+        Some(Ok(new_name)) => new_name,
+        Some(Err(e)) => return invalid_project_name(e),
+        None => {
+            body.insert_string("error".to_string(), "Expected a body like {\"name\": \"...\"}.".to_string());
+            return Response::json(400, body, None);
+        }
+    };
+
+    let store = store::store();
+    let content = match store.load(&project_name) {
+        Ok(Some(content)) => content,
+        Ok(None) => {
+            body.insert_string("error".to_string(), "Project does not exist.".to_string());
+            return Response::json(404, body, None);
+        }
+        Err(e) => return store_error(e),
+    };
+    // Either step fails on a taken name instead of replacing that project.
+    let copied = match remove_original {
+        true => store.rename(&project_name, &new_name),
+        false => store.create(&new_name, &content),
+    };
+    match copied {
+        Ok(true) => {}
+        Ok(false) => {
+            body.insert_string("error".to_string(), "Project already exists.".to_string());
+            return Response::json(400, body, None);
+        }
+        Err(e) => {
+            eprintln!("Failed to write config: {}", e);
+            body.insert_string("error".to_string(), format!("Failed to save config: {}", e));
+            return Response::json(500, body, None);
+        }
+    }
+    forget_project(&new_name);
+    if remove_original {
+        forget_project(&project_name);
+    }
+    body.insert_string("result".to_string(), "ok".to_string());
+    body.insert_string("name".to_string(), new_name.to_string());
+    Response::json(200, body, None)
+}
+
+
+/// Drops everything the server keeps about a project besides its config.
+fn forget_project(project_name: &ProjectName) {
+    cache::invalidate_cache(project_name);
+    state::reset_project(project_name);
+    resources::reset_project(project_name);
+}
+
 
+/// Returns a 400 response that locates the error of an invalid config, e.g.
+/// `{"error": "...", "errors": [{"pointer": "/endpoints/~1a/when/0/method",
+/// "message": "...", "line": 5, "column": 18}]}`.
+fn invalid_config_response(error: &validation::ConfigError) -> Response {
+    let mut located = Nested::new();
+    located.insert_string("pointer".to_string(), error.pointer.clone());
+    located.insert_string("message".to_string(), error.message.clone());
+    located.insert("line".to_string(), NestedValue::Int(error.line as i32));
+    located.insert("column".to_string(), NestedValue::Int(error.column as i32));
+    let location = if error.pointer.is_empty() { String::new() } else { format!("{}: ", error.pointer) };
+    let mut body = Nested::new();
+    body.insert_string(
+        "error".to_string(),
+        format!("Invalid project configuration: {}{} at line {} column {}", location, error.message, error.line, error.column),
+    );
+    body.insert("errors".to_string(), NestedValue::Array(vec![NestedValue::Map(located)]));
+    Response::json(400, body, None)
+}
+
+
+pub fn build_config_with_llm() -> impl Fn(Request) -> Response {
+    |request: Request| {
+        let project_name = match helpers::project_name_from_request(&request) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
+        };
         let config = match compose_config(&request.body, &project_name) {
             Ok(cfg) => cfg,
             Err(e) => {
@@ -97,177 +263,440 @@ pub fn build_config_with_llm() -> impl Fn(Request) -> Response {
             }
         };
 
-        let project_config: schema::ProjectConfig = match serde_json::from_str(&config) {
-            Ok(val) => val,
+        save_composed_config(&project_name, &config)
+    }
+}
+
+
+/// Checks a config composed by the LLM like a submitted one, saves it and
+/// returns it.
+fn save_composed_config(project_name: &ProjectName, config: &str) -> Response {
+    let project_config = match validation::parse_config(config) {
+        Ok(project_config) => project_config,
+        Err(e) => {
+            eprintln!("Invalid configuration received from LLM: {:?}", e);
+            return invalid_config_response(&e);
+        }
+    };
+
+    let config_value: Nested = match serde_json::to_value(&project_config) {
+        Ok(value) => {
+            match serde_json::from_value(value) {
+                Ok(nested) => nested,
+                Err(e) => {
+                    eprintln!("Failed to convert ProjectConfig to Nested: {}", e);
+                    let mut body = Nested::new();
+                    body.insert_string("error".to_string(), "Internal server error".to_string());
+                    return Response::json(500, body, None);
+                }
+            }
+        },
+        Err(e) => {
+            eprintln!("Serialization error (ProjectConfig to serde_json::Value): {}", e);
+            let mut body = Nested::new();
+            body.insert_string("error".to_string(), "Internal server error".to_string());
+            return Response::json(500, body, None);
+        }
+    };
+
+    if let Err(e) = store::store().save(project_name, config) {
+        eprintln!("Failed to write config file: {}", e);
+        let mut body = Nested::new();
+        body.insert_string("error".to_string(), format!("Failed to save config: {}", e));
+        return Response::json(500, body, None);
+    }
+    state::reset_project(project_name);
+    resources::reset_project(project_name);
+    cache::cache_config(project_name, project_config);
+
+    Response::json(200, config_value, None)
+}
+
+
+/// Returns a closure that describes a project as an OpenAPI 3.1 document.
+pub fn export_openapi() -> impl Fn(Request) -> Response {
+    |request: Request| {
+        let project_name = match helpers::project_name_from_request(&request) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
+        };
+        let mut body = Nested::new();
+        match store::store().exists(&project_name) {
+            Ok(true) => {}
+            Ok(false) => {
+                body.insert_string("error".to_string(), "Project does not exist.".to_string());
+                return Response::json(404, body, None);
+            }
+            Err(e) => return store_error(e),
+        }
+        match cache::get_or_else_load_cached_config(&project_name) {
+            Ok(config) => {
+                let document = serde_json::to_string_pretty(&openapi::export(&config)).unwrap();
+                let mut headers = HashMap::new();
+                headers.insert(String::from("Content-Type"), String::from("application/json"));
+                Response::ok(document, Some(headers))
+            }
             Err(e) => {
-                eprintln!("Deserialization error (schema validation): {}", e);
-                let mut body = Nested::new();
-                body.insert_string("error".to_string(), "Configuration json received from LLM is not compatible with the schema.".to_string());
-                return Response::json(400, body, None);
+                body.insert_string("error".to_string(), e);
+                Response::json(500, body, None)
             }
+        }
+    }
+}
+
+
+/// Returns a closure that creates a project from an OpenAPI 3 document
+/// (JSON or YAML) in the request body, and returns its config.
+pub fn import_openapi() -> impl Fn(Request) -> Response {
+    |request: Request| {
+        let project_name = match helpers::project_name_from_request(&request) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
         };
+        let store = store::store();
+        let mut body = Nested::new();
+        match store.exists(&project_name) {
+            Ok(false) => {}
+            Ok(true) => {
+                body.insert_string("error".to_string(), "Project already exists.".to_string());
+                return Response::json(400, body, None);
+            }
+            Err(e) => return store_error(e),
+        }
 
-        let config_value: Nested = match serde_json::to_value(&project_config) {
-            Ok(value) => {
-                match serde_json::from_value(value) {
-                    Ok(nested) => nested,
-                    Err(e) => {
-                        eprintln!("Failed to convert ProjectConfig to Nested: {}", e);
-                        let mut body = Nested::new();
-                        body.insert_string("error".to_string(), "Internal server error".to_string());
-                        return Response::json(500, body, None);
-                    }
-                }
-            },
+        let config = match openapi::import(&request.body) {
+            Ok(config) => config,
             Err(e) => {
-                eprintln!("Serialization error (ProjectConfig to serde_json::Value): {}", e);
-                let mut body = Nested::new();
-                body.insert_string("error".to_string(), "Internal server error".to_string());
+                body.insert_string("error".to_string(), e);
+                return Response::json(400, body, None);
+            }
+        };
+        let content = match serde_json::to_string_pretty(&config) {
+            Ok(content) => content,
+            Err(e) => {
+                body.insert_string("error".to_string(), format!("Failed to save config: {}", e));
                 return Response::json(500, body, None);
             }
         };
-
-        if let Err(e) = fs::write(config_file_path, config) {
-            eprintln!("Failed to write config file: {}", e);
-            let mut body = Nested::new();
+        if let Err(e) = store.save(&project_name, &content) {
+            eprintln!("Failed to write config: {}", e);
             body.insert_string("error".to_string(), format!("Failed to save config: {}", e));
             return Response::json(500, body, None);
         }
+        state::reset_project(&project_name);
+        resources::reset_project(&project_name);
+        cache::cache_config(&project_name, config);
 
-        Response::json(200, config_value, None)
+        let mut headers = HashMap::new();
+        headers.insert(String::from("Content-Type"), String::from("application/json"));
+        Response::ok(content, Some(headers))
     }
 }
 
 
-/// Creates an EndpointKey from a request's method, queries, and body
-fn create_endpoint_key(
-    method: &Method,
-    request_queries: &HashMap<String, String>,
-    request_body: &str,
-) -> EndpointKey {
-    let queries = (!request_queries.is_empty())
-        .then(|| {
-            request_queries.iter().map(|(k, v)| {
-                // Use "is" as the default operator to match the behavior in build_condition_map
-                // when no specific operator is provided in the configuration
-                (k.clone(), QueryParam {operator: "is".into(), value: v.clone()})
-            }).collect::<HashMap<String, QueryParam>>()
-        }).or_else(|| {
-            None
-        });
-    let body = match request_body.is_empty() {
-        true => None,
-        false => serde_json::from_str(request_body).ok()
-    };
+/// Returns the 500 response to a request the config store failed to serve.
+fn store_error(error: String) -> Response {
+    eprintln!("Config store error: {}", error);
+    let mut body = Nested::new();
+    body.insert_string("error".to_string(), error);
+    Response::json(500, body, None)
+}
+
+
+/// Returns the 400 response to a request whose project name is invalid.
+fn invalid_project_name(error: String) -> Response {
+    let mut body = Nested::new();
+    body.insert_string("error".to_string(), error);
+    Response::json(400, body, None)
+}
 
-    EndpointKey { method: method.to_string().to_uppercase(), queries, body }
+
+/// Returns a closure that removes a project, or every project, from the
+/// config cache, so that the configs are read from the store again.
+pub fn flush_cache() -> impl Fn(Request) -> Response {
+    |request: Request| {
+        if request.params.contains_key("name") {
+            match helpers::project_name_from_request(&request) {
+                Ok(project_name) => cache::invalidate_cache(&project_name),
+                Err(e) => return invalid_project_name(e),
+            }
+        } else {
+            cache::flush_cache();
+        }
+        let mut body = Nested::new();
+        body.insert_string("result".to_string(), "ok".to_string());
+        Response::json(200, body, None)
+    }
+}
+
+
+/// Returns a closure that returns every resource of a project to its seed data.
+pub fn reset_resources() -> impl Fn(Request) -> Response {
+    |request: Request| {
+        let project_name = match helpers::project_name_from_request(&request) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
+        };
+        resources::reset_project(&project_name);
+        let mut body = Nested::new();
+        body.insert_string("result".to_string(), "ok".to_string());
+        Response::json(200, body, None)
+    }
+}
+
+
+/// Returns a closure that lists the current state of a project's scenarios.
+/// Scenarios still in the initial state are not listed.
+pub fn get_scenarios() -> impl Fn(Request) -> Response {
+    |request: Request| {
+        let project_name = match helpers::project_name_from_request(&request) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
+        };
+        let mut scenarios = Nested::new();
+        for (scenario, state) in state::scenario_states(&project_name) {
+            scenarios.insert_string(scenario, state);
+        }
+        let mut body = Nested::new();
+        body.insert("scenarios".to_string(), NestedValue::Map(scenarios));
+        Response::json(200, body, None)
+    }
+}
+
+
+/// Returns a closure that sets the state of a scenario from a body like `{"state": "LoggedIn"}`.
+pub fn set_scenario() -> impl Fn(Request) -> Response {
+    |request: Request| {
+        let project_name = match helpers::project_name_from_request(&request) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
+        };
+        let scenario = &request.params["scenario"];
+        let new_state = serde_json::from_str::<Value>(&request.body)
+            .ok()
+            .and_then(|body| body.get("state").and_then(Value::as_str).map(str::to_string));
+        let mut body = Nested::new();
+        match new_state {
+            Some(new_state) => {
+                state::set_scenario_state(&project_name, scenario, &new_state);
+                body.insert_string("result".to_string(), "ok".to_string());
+                Response::json(200, body, None)
+            }
+            None => {
+                body.insert_string("error".to_string(), "Expected a body like {\"state\": \"...\"}.".to_string());
+                Response::json(400, body, None)
+            }
+        }
+    }
+}
+
+
+/// Returns a closure that resets a project's scenarios and sequences, or a
+/// single scenario if the path names one.
+pub fn reset_scenarios() -> impl Fn(Request) -> Response {
+    |request: Request| {
+        let project_name = match helpers::project_name_from_request(&request) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
+        };
+        match request.params.get("scenario") {
+            Some(scenario) => state::reset_scenario(&project_name, scenario),
+            None => state::reset_project(&project_name),
+        }
+        let mut body = Nested::new();
+        body.insert_string("result".to_string(), "ok".to_string());
+        Response::json(200, body, None)
+    }
 }
 
 
 // Updated mock_request that uses the helper function to avoid code repetition.
 pub fn mock_request() -> impl Fn(Request) -> Response {
     |request: Request| {
-        let project_name = request.matches.get(0).unwrap();
-        let path = request.matches.get(1).unwrap();
+        // The route captures the raw project name and endpoint path.
+        let project_name = match ProjectName::parse(&web_server::percent_decode(request.matches.first().unwrap(), false)) {
+            Ok(project_name) => project_name,
+            Err(e) => return invalid_project_name(e),
+        };
+        let project_name = &project_name;
+        let path = &web_server::percent_decode_path(request.matches.get(1).unwrap());
         let request_method = request.method.clone();
 
         // Retrieve the project configuration, or return an error response.
         let project_config = 
             match cache::get_or_else_load_cached_config(project_name) {
                 Ok(config) => config,
-                Err(e) => return Response {
-                    status: 400,
-                    body: e,
-                    headers: HashMap::new(),
-                },
+                Err(e) => return Response::new(400, e, HashMap::new()),
             };
 
-        if let Some(endpoint) = project_config.endpoints.get(path) {
+        let mut request = request;
+        if let Some((key, endpoint, path_params)) = project_config.find_endpoint(path) {
+            // Expose the values captured by a templated key (e.g. "/users/:id").
+            request.params.extend(path_params);
+            let scope = MatchScope { project: project_name, endpoint: key, seed: project_config.seed };
             // Try matching using the request's actual method.
-            if let Some(response) = try_match_conditions(&request, endpoint, &request_method) {
+            if let Some(response) = try_match_conditions(&request, endpoint, &request_method, &scope) {
                 return response;
             }
             // If the request is POST and no match was found,
             // fall back to checking GET conditions to support shared configuration.
-            if request_method == Method::Post {
-                if let Some(response) = try_match_conditions(&request, endpoint, &Method::Get) {
+            // HEAD is answered like GET too; the server strips the body.
+            if request_method == Method::Post || request_method == Method::Head {
+                if let Some(response) = try_match_conditions(&request, endpoint, &Method::Get, &scope) {
                     return response;
                 }
             }
+            // The endpoint's own fallback overrides everything that follows.
+            if let Some(fallback) = &endpoint.fallback {
+                return fallback_response(fallback, project_name, path, &request, project_config.seed)
+                    .unwrap_or_else(not_implemented);
+            }
         }
 
-        // Return a "Not implemented" response if no matching endpoint or condition is found.
-        Response {
-            status: 406,
-            body: "Not implemented.".to_string(),
-            headers: HashMap::new(),
+        // Collections declared in "resources" serve what the endpoints leave unanswered.
+        if let Some((key, resource, id)) = project_config.find_resource(path) {
+            return resources::handle(project_name, key, resource, id.as_deref(), &request);
+        }
+
+        // Unconfigured OPTIONS requests list the methods the endpoint declares.
+        if request_method == Method::Options {
+            if let Some((_, endpoint, _)) = project_config.find_endpoint(path) {
+                return options_response(endpoint);
+            }
+        }
+
+        // Anything else goes to the project's fallback or backend, if it has one.
+        if let Some(fallback) = &project_config.fallback {
+            if let Some(response) = fallback_response(fallback, project_name, path, &request, project_config.seed) {
+                return response;
+            }
+        } else if let Some(proxy_config) = &project_config.proxy {
+            return proxy_request(project_name, path, proxy_config, &request);
         }
+
+        not_implemented()
     }
 }
 
+/// Return a "Not implemented" response if no matching endpoint or condition is found.
+fn not_implemented() -> Response {
+    Response::new(406, "Not implemented.".to_string(), HashMap::new())
+}
+
+/// Answers a request with a fallback; None stands for "notImplemented".
+fn fallback_response(fallback: &schema::Fallback, project_name: &ProjectName, path: &str, request: &Request, seed: Option<u64>) -> Option<Response> {
+    match fallback {
+        schema::Fallback::Proxy(proxy_config) => Some(proxy_request(project_name, path, proxy_config, request)),
+        schema::Fallback::Response(response) => Some(condition_response(request, response, 0, seed)),
+        schema::Fallback::NotImplemented => None,
+    }
+}
+
+/// Forwards a request to the project's upstream and records the exchange
+/// if the project asks for it. Upstream failures are answered with 502.
+fn proxy_request(project_name: &ProjectName, path: &str, proxy_config: &schema::ProxyConfig, request: &Request) -> Response {
+    let raw_path = request.matches.get(1).map_or(path, String::as_str);
+    let response = match proxy::forward(proxy_config, raw_path, request) {
+        Ok(response) => response,
+        Err(e) => {
+            let mut body = Nested::new();
+            body.insert_string("error".to_string(), format!("Upstream request failed: {}", e));
+            return Response::json(502, body, None);
+        }
+    };
+    if proxy_config.record {
+        let recorded = proxy::recorded_condition(request, &response)
+            .ok_or_else(|| "the request cannot be matched exactly".to_string())
+            .and_then(|condition| proxy::record(project_name, path, condition));
+        if let Err(e) = recorded {
+            eprintln!("Failed to record {} {}: {}", request.method.to_string(), path, e);
+        }
+    }
+    response
+}
+
+/// Builds the automatic answer to an OPTIONS request from the methods
+/// declared in the endpoint's conditions.
+fn options_response(endpoint: &schema::Endpoint) -> Response {
+    let declared: Vec<String> = endpoint.conditions
+        .iter()
+        .map(|condition| condition.method.to_uppercase())
+        .collect();
+    let allowed: Vec<String> = Method::ALL
+        .iter()
+        .map(|method| method.to_string())
+        .filter(|method| {
+            declared.contains(method)
+                || (method == "HEAD" && declared.iter().any(|m| m == "GET"))
+                || method == "OPTIONS"
+        })
+        .collect();
+    let mut headers = HashMap::new();
+    headers.insert("Allow".to_string(), allowed.join(", "));
+    Response::new(204, String::new(), headers)
+}
+
 /// Check if the request matches the condition.
 fn check_condition(request: &Request, condition: &schema::WhenCondition, strict: bool) -> bool {
-    //! DO NOT MODIFY THIS FUNCTION
+    if !check_condition_without_schema(request, condition, strict) {
+        return false;
+    }
+    match &condition.request.schema {
+        Some(schema) => matcher::validate_body(schema, &request.body).is_empty(),
+        None => true,
+    }
+}
+
+
+/// Check if the request matches everything of the condition but its body schema.
+fn check_condition_without_schema(request: &Request, condition: &schema::WhenCondition, strict: bool) -> bool {
     let cond_req = &condition.request;
-    if !check_queries(&request.queries, &cond_req.queries) {
+    // An "is" rule with a list compares every value of a repeated query, so
+    // it is checked apart from the rules on the first value.
+    let (value_lists, queries) = split_value_lists(&cond_req.queries);
+    if !check_queries(&request.queries, &queries) {
+        return false;
+    }
+    if !value_lists.iter().all(|(name, rule)| matcher::check_value_list(rule, request.query_values.get(*name))) {
         return false;
     }
     if !check_headers(&request.headers, &cond_req.headers) {
         return false;
     }
-    if !check_body(&request.body, &cond_req.body, strict) {
+    if !check_params(&request.params, &cond_req.params) {
         return false;
     }
-    return true;
+    // "bodyMatch": "partial" relaxes the comparison for this condition.
+    let strict = strict && cond_req.body_match.is_exact();
+    // Body rules, a schema or form and text rules alone accept any body that satisfies them.
+    let body_checked_elsewhere = cond_req.body_rules.is_some() || cond_req.schema.is_some()
+        || cond_req.form.is_some() || cond_req.files.is_some() || cond_req.text.is_some();
+    if (cond_req.body.is_some() || !body_checked_elsewhere) && !check_body(&request.body, &cond_req.body, strict) {
+        return false;
+    }
+    if !check_body_rules(&request.body, &cond_req.body_rules) {
+        return false;
+    }
+    if !check_form(request, &cond_req.form, &cond_req.files) {
+        return false;
+    }
+    if !check_text(request, &cond_req.text) {
+        return false;
+    }
+    true
 }
 
 
 /// Check if the request queries match the condition's request queries.
 fn check_queries(request_queries: &HashMap<String, String>, queries_from_cond_req: &Option<HashMap<String, schema::QueryParam>>) -> bool {
-    //! DO NOT MODIFY THIS FUNCTION
     match queries_from_cond_req {
         Some(cond_req_queries) => {
-            if request_queries.is_empty() {
-                return cond_req_queries.is_empty();
-            }
-            for (expected_query_name, expected_query_param) in cond_req_queries {
-                if request_queries.is_empty() {
-                    return false;
-                }
+            // Every expected query must satisfy its rule; a missing query
+            // only satisfies "absent".
+            cond_req_queries.iter().all(|(expected_query_name, expected_query_param)| {
                 let actual_query_value = request_queries.get(expected_query_name);
-                if actual_query_value.is_none() {
-                    return false; // Expected query param is missing
-                }
-                let actual_query_value = actual_query_value.unwrap();
-
-                match expected_query_param.operator.as_str() {
-                    "is" => {
-                        if actual_query_value != &expected_query_param.value {
-                            return false;
-                        }
-                    }
-                    "is!" => {
-                        if actual_query_value == &expected_query_param.value {
-                            return false;
-                        }
-                    }
-                    "contains" => {
-                        if !actual_query_value.contains(&expected_query_param.value) {
-                            return false;
-                        }
-                    }
-                    "contains!" => {
-                        if actual_query_value.contains(&expected_query_param.value) {
-                            return false;
-                        }
-                    }
-                    op => {
-                        eprintln!("Warning: Unknown query operator '{}'", op); // Optional: Log unknown operator
-                        return false; // Or decide to ignore and treat as non-matching
-                    }
-                }
-            }
-            return true; // All expected queries matched
+                matcher::check_rule(expected_query_param, actual_query_value.map(String::as_str))
+            })
         }
         None => {
             return request_queries.is_empty();
@@ -276,22 +705,48 @@ fn check_queries(request_queries: &HashMap<String, String>, queries_from_cond_re
 }
 
 
+/// The query rules of a condition, by query name.
+type QueryRules = Option<HashMap<String, QueryParam>>;
+
+/// Separates the query rules with a list of values from the others, which
+/// are left as they are unless there are such rules.
+fn split_value_lists(queries: &QueryRules) -> (Vec<(&String, &QueryParam)>, Cow<'_, QueryRules>) {
+    let value_lists: Vec<(&String, &QueryParam)> = queries.iter().flatten()
+        .filter(|(_, rule)| matcher::is_value_list(rule))
+        .collect();
+    if value_lists.is_empty() {
+        return (value_lists, Cow::Borrowed(queries));
+    }
+    let others = queries.iter().flatten()
+        .filter(|(_, rule)| !matcher::is_value_list(rule))
+        .map(|(name, rule)| (name.clone(), rule.clone()))
+        .collect();
+    (value_lists, Cow::Owned(Some(others)))
+}
+
+
+/// Check if the path params captured by the endpoint key match the condition's rules.
+fn check_params(request_params: &HashMap<String, String>, params_from_cond_req: &Option<HashMap<String, schema::QueryParam>>) -> bool {
+    match params_from_cond_req {
+        // Params follow the same operator rules as queries.
+        Some(_) => check_queries(request_params, params_from_cond_req),
+        None => true,
+    }
+}
+
+
 /// Check if the request headers match the condition's request headers.
-fn check_headers(request_headers: &HashMap<String, String>, headers_from_cond_req: &Option<HashMap<String, String>>) -> bool {
-    //! DO NOT MODIFY THIS FUNCTION
+/// Header names are compared case-insensitively.
+fn check_headers(request_headers: &HashMap<String, String>, headers_from_cond_req: &Option<HashMap<String, HeaderRule>>) -> bool {
     match headers_from_cond_req {
         Some(cond_req_headers) => {
-            for (expected_header_name, expected_header_value) in cond_req_headers {
-                let actual_header_value = request_headers.get(expected_header_name);
-                if actual_header_value.is_none() {
-                    return false; // Expected header is missing
+            cond_req_headers.iter().all(|(expected_header_name, expected_header_rule)| {
+                let actual_header_value = web_server::header_value(request_headers, expected_header_name);
+                match expected_header_rule {
+                    HeaderRule::Exact(expected_header_value) => actual_header_value == Some(expected_header_value.as_str()),
+                    HeaderRule::Rule(rule) => matcher::check_rule(rule, actual_header_value),
                 }
-                let actual_header_value = actual_header_value.unwrap();
-                if actual_header_value != expected_header_value {
-                    return false; // Header value does not match
-                }
-            }
-            return true; // All expected headers matched
+            })
         }
         None => {
             return true; // allow for any content in headers
@@ -302,7 +757,6 @@ fn check_headers(request_headers: &HashMap<String, String>, headers_from_cond_re
 
 /// Check if the request body matches the condition's request body.
 fn check_body(request_body: &String, body_from_cond_req: &Option<Value>, strict: bool) -> bool {
-    //! DO NOT MODIFY THIS FUNCTION
     match body_from_cond_req {
         None => request_body.is_empty(),
         Some(expected_body) => {
@@ -311,15 +765,8 @@ fn check_body(request_body: &String, body_from_cond_req: &Option<Value>, strict:
                     if strict {
                         &actual_body_json == expected_body
                     } else {
-                        // In non-strict mode, only check if the expected fields exist
-                        if let Value::Object(expected_obj) = expected_body {
-                            if let Value::Object(actual_obj) = actual_body_json {
-                                return expected_obj.iter().all(|(k, v)| 
-                                    actual_obj.get(k).map_or(false, |av| av == v)
-                                );
-                            }
-                        }
-                        false
+                        // In non-strict mode, the request body only has to contain the expected body
+                        matcher::json_contains(&actual_body_json, expected_body)
                     }
                 },
                 Err(_) => false,
@@ -329,52 +776,211 @@ fn check_body(request_body: &String, body_from_cond_req: &Option<Value>, strict:
 }
 
 
+/// Check if the fields and files of a form body match the condition's form rules.
+/// The body must be "application/x-www-form-urlencoded" or "multipart/form-data";
+/// files can only be uploaded with the latter.
+fn check_form(request: &Request, form_from_cond_req: &Option<HashMap<String, QueryParam>>, files_from_cond_req: &Option<HashMap<String, schema::FileRule>>) -> bool {
+    if form_from_cond_req.is_none() && files_from_cond_req.is_none() {
+        return true;
+    }
+    let content_type = web_server::header_value(&request.headers, "Content-Type").unwrap_or_default();
+    let (fields, parts) = match matcher::media_type(content_type).as_str() {
+        "application/x-www-form-urlencoded" => (web_server::extract_queries(&request.body), Vec::new()),
+        "multipart/form-data" => match matcher::parse_multipart(content_type, &request.raw_body) {
+            Ok(parts) => (matcher::multipart_fields(&parts), parts),
+            Err(_) => return false,
+        },
+        _ => return false,
+    };
+    // As for queries, the first value of a repeated field is matched.
+    let fields_match = form_from_cond_req.iter().flatten().all(|(name, rule)| {
+        let actual_value = fields.get(name).and_then(|values| values.first());
+        matcher::check_rule(rule, actual_value.map(String::as_str))
+    });
+    fields_match && files_from_cond_req.iter().flatten().all(|(name, rule)| {
+        let file = parts.iter().find(|part| &part.name == name && part.filename.is_some());
+        matcher::check_file_rule(rule, file)
+    })
+}
+
+
+/// Check if a "text/*" or JSON request body matches the condition's text rule.
+fn check_text(request: &Request, text_from_cond_req: &Option<QueryParam>) -> bool {
+    match text_from_cond_req {
+        Some(rule) => {
+            let content_type = web_server::header_value(&request.headers, "Content-Type").unwrap_or_default();
+            matcher::is_text_media_type(&matcher::media_type(content_type)) && matcher::check_rule(rule, Some(&request.body))
+        }
+        None => true,
+    }
+}
+
+
+/// Check if the JSON request body satisfies every body rule of the condition.
+fn check_body_rules(request_body: &str, body_rules_from_cond_req: &Option<Vec<schema::BodyRule>>) -> bool {
+    match body_rules_from_cond_req {
+        Some(body_rules) => match serde_json::from_str::<Value>(request_body) {
+            Ok(actual_body_json) => body_rules.iter().all(|rule| matcher::check_body_rule(rule, &actual_body_json)),
+            Err(_) => false,
+        },
+        None => true,
+    }
+}
+
+
+/// Where a request is being matched: the project and endpoint key that own
+/// the scenario states and sequence counters, and the project seed.
+struct MatchScope<'a> {
+    project: &'a str,
+    endpoint: &'a str,
+    seed: Option<u64>,
+}
+
 // Helper function to try matching conditions for a given method.
 // This factors out repeated logic from the previous version of mock_request.
-fn try_match_conditions(request: &Request, endpoint: &schema::Endpoint, method: &Method) -> Option<Response> {
-    // Create an endpoint key based on method, query parameters, and body.
-    let key = create_endpoint_key(method, &request.queries, &request.body);
-    // Check for an exact match in the condition_map.
-    if let Some((response, delay)) = endpoint.condition_map.get(&key) {
-        if *delay > 0 {
-            std::thread::sleep(std::time::Duration::from_millis(*delay));
-        }
-        let body = response.body
-            .as_ref()
-            .map(|v| if let Value::String(s) = v { s.clone() } else { v.to_string() })
-            .unwrap_or("null".to_string());
-        return Some(Response {
-            status: response.status,
-            body,
-            headers: response.headers.clone(),
-        });
-    }
-    // Sequentially check each condition configuration.
-    for condition in &endpoint.conditions {
+fn try_match_conditions(request: &Request, endpoint: &schema::Endpoint, method: &Method, scope: &MatchScope) -> Option<Response> {
+    // Check each condition in order, so that the first matching one wins,
+    // even if a later one matches the request exactly. A condition whose
+    // body fails its schema answers with its "onInvalid" response, but only
+    // if no other condition matches.
+    let mut invalid_response = None;
+    for (index, condition) in endpoint.conditions.iter().enumerate() {
         if condition.method.to_uppercase() != method.to_string().to_uppercase() {
             continue;
         }
         if check_condition(request, condition, true) {
-            if condition.delay > 0 {
-                std::thread::sleep(std::time::Duration::from_millis(condition.delay));
+            if let Some(response) = fire_condition(request, index, condition, scope) {
+                return Some(response);
             }
-            let body = condition.response.body
-                .as_ref()
-                .map(|v| if let Value::String(s) = v { s.clone() } else { v.to_string() })
-                .unwrap_or("null".to_string());
-            return Some(Response {
-                status: condition.response.status,
-                body,
-                headers: condition.response.headers.clone(),
-            });
+        }
+        if invalid_response.is_none() {
+            invalid_response = invalid_body_response(request, condition, scope);
         }
     }
-    None
+    invalid_response
+}
+
+/// Builds the "onInvalid" response of a condition whose body schema the
+/// request fails, listing the validation errors.
+fn invalid_body_response(request: &Request, condition: &schema::WhenCondition, scope: &MatchScope) -> Option<Response> {
+    let on_invalid = condition.request.on_invalid.as_ref()?;
+    let schema = condition.request.schema.as_ref()?;
+    if !check_condition_without_schema(request, condition, true) || !in_required_state(condition, scope) {
+        return None;
+    }
+    let errors = matcher::validate_body(schema, &request.body);
+    if errors.is_empty() {
+        return None;
+    }
+    let mut headers = on_invalid.headers.clone();
+    if web_server::header_value(&headers, "Content-Type").is_none() {
+        headers.insert("Content-Type".to_string(), "application/json".to_string());
+    }
+    let body = serde_json::json!({ "errors": errors }).to_string();
+    Some(Response::new(on_invalid.status, body, headers))
+}
+
+/// Check if the condition's scenario is in the state the condition requires.
+fn in_required_state(condition: &schema::WhenCondition, scope: &MatchScope) -> bool {
+    match (&condition.scenario, &condition.required_state) {
+        (Some(scenario), Some(required_state)) => &state::scenario_state(scope.project, scenario) == required_state,
+        _ => true,
+    }
+}
+
+/// Fires a condition whose request matched: takes its scenario transition
+/// and answers with its response, the next response of its sequence or
+/// one of its weighted responses.
+/// Returns None if the scenario is not in the required state.
+fn fire_condition(request: &Request, index: usize, condition: &schema::WhenCondition, scope: &MatchScope) -> Option<Response> {
+    if let Some(scenario) = &condition.scenario {
+        let required_state = condition.required_state.as_deref();
+        if !state::transition(scope.project, scenario, required_state, condition.new_state.as_deref()) {
+            return None;
+        }
+    }
+    let counter = format!("/endpoints/{}/when/{}", scope.endpoint, index);
+    let response = match (&condition.sequence, &condition.responses) {
+        (Some(sequence), _) => sequence.response_at(state::next_call(scope.project, &counter))?,
+        (_, Some(choices)) => {
+            let call = state::next_call(scope.project, &counter);
+            // With a project seed the n-th call always rolls the same number.
+            let mut rng = match scope.seed {
+                Some(seed) => Rng::derived(seed, &[&counter, &call.to_string()]),
+                None => Rng::from_entropy(),
+            };
+            schema::WeightedResponse::pick(choices, call, rng.next_f64())?
+        }
+        _ => condition.response.as_ref()?,
+    };
+    // Like the generated values, the delays and faults of a seeded project
+    // are reproducible: the n-th call of a request always gets the same ones.
+    let mut rng = match scope.seed {
+        Some(seed) if condition.delay_distribution.is_some() || condition.fault.is_some() => {
+            let call = state::next_call(scope.project, &format!("{}/delay-and-fault", counter));
+            let method = request.method.to_string();
+            Rng::derived(seed, &[&method, &request.raw_url, &request.body, &counter, &call.to_string()])
+        }
+        _ => Rng::from_entropy(),
+    };
+    let delay = condition.delay + condition.delay_distribution.as_ref().map_or(0, |d| random_delay(d, &mut rng));
+    let fault = condition.fault.as_ref()
+        .filter(|fault| rng.next_f64() < fault.probability.unwrap_or(1.0))
+        .map(|fault| match fault.kind {
+            schema::FaultKind::Reset => Fault::Reset,
+            schema::FaultKind::Close => Fault::Close,
+            schema::FaultKind::Truncate { bytes } => Fault::Truncate(bytes),
+            schema::FaultKind::ContentLength { offset } => Fault::ContentLength(offset),
+            schema::FaultKind::Trickle { bytes_per_second } => Fault::Trickle(bytes_per_second),
+            schema::FaultKind::Malformed => Fault::Malformed,
+        });
+    Some(condition_response(request, response, delay, scope.seed).with_fault(fault))
+}
+
+/// Draws a delay in milliseconds from a distribution; negative draws count as 0.
+fn random_delay(distribution: &schema::DelayDistribution, rng: &mut Rng) -> u64 {
+    let millis = match *distribution {
+        schema::DelayDistribution::Uniform { min, max } => return rng.range(min as i64, max as i64) as u64,
+        schema::DelayDistribution::Normal { mean, std_dev } => mean + std_dev * rng.normal(),
+        schema::DelayDistribution::LongTail { median, sigma } => median * (sigma * rng.normal()).exp(),
+    };
+    millis.round().max(0.0) as u64
+}
+
+/// Builds the response of a matched condition, rendering it as a template
+/// of the request data if the condition asks for it. With a project seed,
+/// generated values only depend on the request.
+fn condition_response(request: &Request, response: &schema::ResponseConfig, delay: u64, seed: Option<u64>) -> Response {
+    let mut body = response.body.clone();
+    let mut headers = response.headers.clone();
+    if response.template {
+        let context = template::Context::from_request(request);
+        let context = match seed {
+            Some(seed) => context.with_seed(seed),
+            None => context,
+        };
+        body = match body.map(|body| template::render_value(&body, &context)).transpose() {
+            Ok(body) => body,
+            Err(e) => {
+                let mut error = Nested::new();
+                error.insert_string("error".to_string(), format!("Failed to render template: {}", e));
+                return Response::json(500, error, None);
+            }
+        };
+        headers.values_mut().for_each(|value| *value = template::render_str(value, &context));
+    }
+    let body = body
+        .map(|v| if let Value::String(s) = v { s } else { v.to_string() })
+        .unwrap_or("null".to_string());
+    // The server waits out the delay on a timer before writing the response.
+    Response::new(response.status, body, headers)
+        .with_delay(Duration::from_millis(delay))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::schema::Operator;
     use crate::web_server::types::Request;
     use std::collections::HashMap;
     use std::fs;
@@ -393,8 +999,11 @@ mod tests {
             path: path.to_string(),
             version: "1.1".to_string(),
             headers: HashMap::new(),
+            raw_body: body.as_deref().unwrap_or_default().as_bytes().to_vec(),
             body: body.unwrap_or_default(),
+            raw_url: path.to_string(),
             queries: HashMap::new(),
+            query_values: HashMap::new(),
             params: HashMap::new(),
             matches: Vec::new(),
         }
@@ -434,7 +1043,7 @@ mod tests {
     #[test]
     fn test_save_config_post_new_project() {
         let test_dir = setup_test_dir();
-        let config_data = r#"{"test": "data"}"#;
+        let config_data = r#"{"description": "test", "endpoints": {}}"#;
 
         let mut request = create_test_request(&Method::Post, "/projects/test", Some(config_data.to_string()));
         request.params.insert("name".to_string(), "test".to_string());
@@ -473,17 +1082,33 @@ mod tests {
 
     #[test]
     fn test_save_config_put_nonexistent_project() {
+        let test_dir = setup_test_dir();
+        let mut request = create_test_request(&Method::Put, "/projects/test", Some(r#"{"new": "data"}"#.to_string()));
+        request.params.insert("name".to_string(), "nonexistent".to_string());
+
+        temp_env::with_var("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap()), || {
+            let handler = save_config();
+            let response = handler(request);
+
+            assert_eq!(response.status, 400);
+            assert!(response.body.contains("Project does not exist"));
+        });
+    }
+
+    #[test]
+    fn test_save_config_absolute_path_as_name() {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let mut request = create_test_request(&Method::Put, "/projects/test", Some(r#"{"new": "data"}"#.to_string()));
+        let mut request = create_test_request(&Method::Put, "/projects/test", Some(r#"{"description": "x", "endpoints": {}}"#.to_string()));
         request.params.insert("name".to_string(), path.to_str().unwrap().to_string());
 
         let handler = save_config();
         let response = handler(request);
 
         assert_eq!(response.status, 400);
-        assert!(response.body.contains("Project does not exist"));
+        assert!(response.body.contains("Invalid project name"));
+        assert_eq!(fs::read_to_string(path).unwrap(), "");
     }
 
     #[test]
@@ -492,7 +1117,7 @@ mod tests {
         let project_path = test_dir.path().join("projects").join("test.json");
         fs::write(&project_path, r#"{"existing": "data"}"#).unwrap();
 
-        let new_data = r#"{"new": "data"}"#;
+        let new_data = r#"{"description": "new", "endpoints": {}}"#;
         let mut request = create_test_request(&Method::Put, "/projects/test", Some(new_data.to_string()));
         request.params.insert("name".to_string(), "test".to_string());
 
@@ -506,6 +1131,59 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_save_config_invalid_keeps_project() {
+        let test_dir = setup_test_dir();
+        let project_path = test_dir.path().join("projects").join("test-invalid.json");
+        let old_data = r#"{"description": "old", "endpoints": {}}"#;
+        fs::write(&project_path, old_data).unwrap();
+
+        let invalid = [
+            ("", "", "the configuration is empty"),
+            (r#"{"description": "new"}"#, "", "missing field `endpoints`"),
+            ("{\n  \"description\": \"new\",\n  \"endpoints\": {\"/a\": {\"when\": [\n    {\"method\": \"GET\", \"request\": {}, \"response\": {\"status\": 42, \"headers\": {}}}\n  ]}}\n}",
+                "/endpoints/~1a/when/0/response/status", "expected a status code from 100 to 599"),
+        ];
+        temp_env::with_var("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap()), || {
+            for (config_data, pointer, message) in invalid {
+                let mut request = create_test_request(&Method::Put, "/projects/test-invalid", Some(config_data.to_string()));
+                request.params.insert("name".to_string(), "test-invalid".to_string());
+                let response = save_config()(request);
+
+                assert_eq!(response.status, 400);
+                let body: Value = serde_json::from_str(&response.body).unwrap();
+                assert_eq!(body["errors"][0]["pointer"], pointer);
+                assert_eq!(body["errors"][0]["message"], message);
+                assert_eq!(fs::read_to_string(&project_path).unwrap(), old_data);
+            }
+        });
+        let entries: Vec<_> = fs::read_dir(test_dir.path().join("projects")).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_save_composed_config_checks_config() {
+        let test_dir = setup_test_dir();
+        let project_path = test_dir.path().join("projects").join("test-llm-checked.json");
+        temp_env::with_var("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap()), || {
+            let project_name = ProjectName::parse("test-llm-checked").unwrap();
+            let invalid = r#"{"description": "llm", "endpoints": {"/a": {"when": [
+                {"method": "GET", "request": {}, "response": {"status": 700, "headers": {}, "body": "{{ request.path }}"}}
+            ]}}}"#;
+            let response = save_composed_config(&project_name, invalid);
+            assert_eq!(response.status, 400);
+            let body: Value = serde_json::from_str(&response.body).unwrap();
+            assert_eq!(body["errors"][0]["pointer"], "/endpoints/~1a/when/0/response/status");
+            assert_eq!(body["errors"][0]["line"], 2);
+            assert!(!project_path.exists());
+
+            let valid = invalid.replace("700", "200");
+            assert_eq!(save_composed_config(&project_name, &valid).status, 200);
+            assert_eq!(fs::read_to_string(&project_path).unwrap(), valid);
+            assert_eq!(cache::get_or_else_load_cached_config(&project_name).unwrap().endpoints["/a"].conditions.len(), 1);
+        });
+    }
+
     #[test]
     fn test_mock_request_project_not_found() {
         let test_dir = setup_test_dir();
@@ -565,14 +1243,30 @@ mod tests {
             request: schema::RequestConfig {
                 queries: None,
                 headers: None,
+                params: None,
                 body: None,
+                body_match: schema::BodyMatch::Exact,
+                body_rules: None,
+                schema: None,
+                on_invalid: None,
+                form: None,
+                files: None,
+                text: None,
             },
-            response: schema::ResponseConfig {
+            response: Some(schema::ResponseConfig {
                 status: 200,
                 headers: HashMap::new(),
                 body: None,
-            },
+                template: false,
+            }),
+            sequence: None,
+            responses: None,
+            scenario: None,
+            required_state: None,
+            new_state: None,
             delay: 0,
+            delay_distribution: None,
+            fault: None,
         };
 
         let request = create_test_request(&Method::Get, "/test", None);
@@ -586,45 +1280,467 @@ mod tests {
 
         let mut cond_req_queries = HashMap::new();
         cond_req_queries.insert("filter".to_string(), schema::QueryParam {
-            operator: "is".to_string(),
-            value: "active".to_string(),
+            operator: Operator::Is,
+            value: Value::String("active".to_string()),
+            ignore_case: false,
         });
 
         let condition_request = schema::RequestConfig {
             queries: Some(cond_req_queries),
             headers: None,
+            params: None,
             body: None,
+            body_match: schema::BodyMatch::Exact,
+            body_rules: None,
+            schema: None,
+            on_invalid: None,
+            form: None,
+            files: None,
+            text: None,
         };
 
         assert!(check_queries(&request_queries, &condition_request.queries));
     }
 
+    #[test]
+    fn test_check_queries_absent_and_missing() {
+        let mut cond_req_queries = HashMap::new();
+        cond_req_queries.insert("debug".to_string(), schema::QueryParam {
+            operator: Operator::Absent,
+            value: Value::Null,
+            ignore_case: false,
+        });
+        let cond_req_queries = Some(cond_req_queries);
+
+        assert!(check_queries(&HashMap::new(), &cond_req_queries));
+        let mut request_queries = HashMap::new();
+        request_queries.insert("debug".to_string(), "1".to_string());
+        assert!(!check_queries(&request_queries, &cond_req_queries));
+    }
+
     #[test]
     fn test_check_headers_matching() {
         let mut request_headers = HashMap::new();
         request_headers.insert("content-type".to_string(), "application/json".to_string());
 
         let mut cond_req_headers = HashMap::new();
-        cond_req_headers.insert("content-type".to_string(), "application/json".to_string());
+        cond_req_headers.insert("content-type".to_string(), HeaderRule::Exact("application/json".to_string()));
 
         let condition_request = schema::RequestConfig {
             queries: None,
             headers: Some(cond_req_headers),
+            params: None,
             body: None,
+            body_match: schema::BodyMatch::Exact,
+            body_rules: None,
+            schema: None,
+            on_invalid: None,
+            form: None,
+            files: None,
+            text: None,
         };
 
         assert!(check_headers(&request_headers, &condition_request.headers));
     }
 
+    #[test]
+    fn test_check_headers_case_insensitive_rules() {
+        let mut request_headers = HashMap::new();
+        request_headers.insert("Authorization".to_string(), "Bearer abc.def".to_string());
+        request_headers.insert("content-type".to_string(), "application/json; charset=utf-8".to_string());
+
+        let cond_req_headers: HashMap<String, HeaderRule> = serde_json::from_str(r#"{
+            "authorization": {"operator": "matches", "value": "^Bearer .+$"},
+            "Content-Type": {"operator": "contains", "value": "JSON", "ignoreCase": true},
+            "x-debug": {"operator": "absent"}
+        }"#).unwrap();
+        assert!(check_headers(&request_headers, &Some(cond_req_headers.clone())));
+
+        request_headers.insert("X-Debug".to_string(), "1".to_string());
+        assert!(!check_headers(&request_headers, &Some(cond_req_headers)));
+    }
+
     #[test]
     fn test_check_body_strict_matching() {
         let request_body = r#"{"key": "value"}"#.to_string();
         let condition_request = schema::RequestConfig {
             queries: None,
             headers: None,
+            params: None,
             body: Some(serde_json::json!({"key": "value"})),
+            body_match: schema::BodyMatch::Exact,
+            body_rules: None,
+            schema: None,
+            on_invalid: None,
+            form: None,
+            files: None,
+            text: None,
         };
 
         assert!(check_body(&request_body, &condition_request.body, true));
     }
+
+    #[test]
+    fn test_check_condition_partial_body_and_body_rules() {
+        let condition: schema::WhenCondition = serde_json::from_str(r#"{
+            "method": "POST",
+            "request": {
+                "body": {"user": {"role": "admin"}, "tags": ["vip"]},
+                "bodyMatch": "partial",
+                "bodyRules": [
+                    {"path": "$.user.address.zip", "operator": "matches", "value": "^\\d{5}$"},
+                    {"path": "$.items[*].qty", "operator": "gt", "value": 3}
+                ]
+            },
+            "response": {"status": 200, "headers": {}, "body": "ok"}
+        }"#).unwrap();
+        let mut request = create_test_request(&Method::Post, "/orders", Some(r#"{
+                "user": {"role": "admin", "name": "Ann", "address": {"zip": "10115"}},
+                "tags": ["new", "vip"],
+                "items": [{"qty": 1}, {"qty": 4}]
+            }"#.to_string()));
+        assert!(check_condition(&request, &condition, true));
+
+        request.body = request.body.replace("10115", "1011");
+        assert!(!check_condition(&request, &condition, true));
+    }
+
+    #[test]
+    fn test_check_condition_body_rules_without_body() {
+        let condition: schema::WhenCondition = serde_json::from_str(r#"{
+            "method": "POST",
+            "request": {"bodyRules": [{"path": "$.tags", "operator": "contains", "value": "vip"}]},
+            "response": {"status": 200, "headers": {}, "body": "ok"}
+        }"#).unwrap();
+        let mut request = create_test_request(&Method::Post, "/orders", Some(r#"{"tags": ["vip"], "total": 3}"#.to_string()));
+        assert!(check_condition(&request, &condition, true));
+
+        request.body = String::new();
+        assert!(!check_condition(&request, &condition, true));
+    }
+
+    #[test]
+    fn test_try_match_conditions_body_schema() {
+        let endpoint: schema::Endpoint = serde_json::from_str(r#"{"when": [
+            {
+                "method": "POST",
+                "request": {
+                    "schema": {
+                        "type": "object",
+                        "required": ["name"],
+                        "properties": {"name": {"type": "string"}, "age": {"type": "integer", "minimum": 0}}
+                    },
+                    "onInvalid": {"status": 422}
+                },
+                "response": {"status": 201, "headers": {}, "body": "created"}
+            },
+            {
+                "method": "POST",
+                "request": {"body": {"legacy": true}},
+                "response": {"status": 200, "headers": {}, "body": "legacy"}
+            }
+        ]}"#).unwrap();
+        let scope = MatchScope { project: "handlers-test-body-schema", endpoint: "/users", seed: None };
+
+        let request = create_test_request(&Method::Post, "/users", Some(r#"{"name": "Ann", "age": 3}"#.to_string()));
+        assert_eq!(try_match_conditions(&request, &endpoint, &Method::Post, &scope).unwrap().status, 201);
+
+        let request = create_test_request(&Method::Post, "/users", Some(r#"{"legacy": true}"#.to_string()));
+        assert_eq!(try_match_conditions(&request, &endpoint, &Method::Post, &scope).unwrap().status, 200);
+
+        let request = create_test_request(&Method::Post, "/users", Some(r#"{"age": "3"}"#.to_string()));
+        let response = try_match_conditions(&request, &endpoint, &Method::Post, &scope).unwrap();
+        assert_eq!(response.status, 422);
+        assert_eq!(response.headers.get("Content-Type").unwrap(), "application/json");
+        let body: Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(body, serde_json::json!({"errors": [
+            {"path": "/name", "message": "is required"},
+            {"path": "/age", "message": "expected integer, got string"}
+        ]}));
+    }
+
+    #[test]
+    fn test_try_match_conditions_first_match_wins() {
+        let endpoint: schema::Endpoint = serde_json::from_str(r#"{"when": [
+            {
+                "method": "GET",
+                "request": {"queries": {"id": {"operator": "matches", "value": ".*"}}},
+                "response": {"status": 200, "headers": {}, "body": "any id"}
+            },
+            {
+                "method": "GET",
+                "request": {"queries": {"id": {"operator": "is", "value": "7"}}},
+                "response": {"status": 200, "headers": {}, "body": "id 7"}
+            }
+        ]}"#).unwrap();
+        let scope = MatchScope { project: "handlers-test-first-match", endpoint: "/items", seed: None };
+        let mut request = create_test_request(&Method::Get, "/items?id=7", None);
+        request.queries.insert("id".to_string(), "7".to_string());
+        request.query_values.insert("id".to_string(), vec!["7".to_string()]);
+        let response = try_match_conditions(&request, &endpoint, &Method::Get, &scope).unwrap();
+        assert_eq!(response.body, "any id");
+    }
+
+    #[test]
+    fn test_try_match_conditions_sequences() {
+        let endpoint: schema::Endpoint = serde_json::from_str(r#"{"when": [
+            {
+                "method": "GET",
+                "request": {},
+                "sequence": {"responses": [
+                    {"status": 202, "headers": {}, "body": "pending"},
+                    {"status": 200, "headers": {}, "body": "done"}
+                ]}
+            },
+            {
+                "method": "POST",
+                "request": {},
+                "sequence": {"mode": "cycle", "responses": [
+                    {"status": 200, "headers": {}},
+                    {"status": 503, "headers": {}}
+                ]}
+            }
+        ]}"#).unwrap();
+        let scope = MatchScope { project: "handlers-test-sequences", endpoint: "/jobs/1", seed: None };
+        let status = |method: &Method| {
+            let request = create_test_request(method, "/jobs/1", None);
+            try_match_conditions(&request, &endpoint, method, &scope).unwrap().status
+        };
+
+        // "stick" keeps the last response, "cycle" starts over.
+        assert_eq!([status(&Method::Get), status(&Method::Get), status(&Method::Get)], [202, 200, 200]);
+        assert_eq!([status(&Method::Post), status(&Method::Post), status(&Method::Post)], [200, 503, 200]);
+        state::reset_project(scope.project);
+        assert_eq!(status(&Method::Get), 202);
+    }
+
+    #[test]
+    fn test_try_match_conditions_weighted_responses() {
+        let endpoint: schema::Endpoint = serde_json::from_str(r#"{"when": [
+            {
+                "method": "GET",
+                "request": {},
+                "responses": [
+                    {"every": 3, "response": {"status": 503, "headers": {}}},
+                    {"weight": 0, "response": {"status": 500, "headers": {}}},
+                    {"response": {"status": 200, "headers": {}}}
+                ]
+            },
+            {
+                "method": "POST",
+                "request": {},
+                "responses": [
+                    {"weight": 1, "response": {"status": 200, "headers": {}}},
+                    {"weight": 1, "response": {"status": 500, "headers": {}}}
+                ]
+            }
+        ]}"#).unwrap();
+        let scope = MatchScope { project: "handlers-test-weighted", endpoint: "/flaky", seed: Some(7) };
+        let statuses = |method: &Method, calls: usize| -> Vec<u16> {
+            (0..calls).map(|_| {
+                let request = create_test_request(method, "/flaky", None);
+                try_match_conditions(&request, &endpoint, method, &scope).unwrap().status
+            }).collect()
+        };
+
+        // Every 3rd call fails; a zero weight is never picked.
+        assert_eq!(statuses(&Method::Get, 6), vec![200, 200, 503, 200, 200, 503]);
+
+        // With a seed the same calls pick the same responses.
+        let first_run = statuses(&Method::Post, 40);
+        assert!(first_run.contains(&200) && first_run.contains(&500));
+        state::reset_project(scope.project);
+        assert_eq!(statuses(&Method::Post, 40), first_run);
+    }
+
+    #[test]
+    fn test_try_match_conditions_faults_and_delay_distributions() {
+        let endpoint: schema::Endpoint = serde_json::from_str(r#"{"when": [
+            {
+                "method": "GET",
+                "request": {},
+                "response": {"status": 200, "headers": {}},
+                "delay": 50,
+                "delayDistribution": {"type": "uniform", "min": 10, "max": 20},
+                "fault": {"type": "truncate", "bytes": 4}
+            },
+            {
+                "method": "POST",
+                "request": {},
+                "response": {"status": 200, "headers": {}},
+                "fault": {"type": "reset", "probability": 0}
+            }
+        ]}"#).unwrap();
+        let scope = MatchScope { project: "handlers-test-faults", endpoint: "/unstable", seed: None };
+
+        let request = create_test_request(&Method::Get, "/unstable", None);
+        let response = try_match_conditions(&request, &endpoint, &Method::Get, &scope).unwrap();
+        assert_eq!(response.fault, Some(Fault::Truncate(4)));
+        assert!((60..=70).contains(&response.delay.as_millis()));
+
+        let request = create_test_request(&Method::Post, "/unstable", None);
+        assert_eq!(try_match_conditions(&request, &endpoint, &Method::Post, &scope).unwrap().fault, None);
+    }
+
+    #[test]
+    fn test_try_match_conditions_seeded_faults_and_delays() {
+        let endpoint: schema::Endpoint = serde_json::from_str(r#"{"when": [{
+            "method": "GET",
+            "request": {},
+            "response": {"status": 200, "headers": {}},
+            "delayDistribution": {"type": "uniform", "min": 0, "max": 1000},
+            "fault": {"type": "close", "probability": 0.5}
+        }]}"#).unwrap();
+        let scope = MatchScope { project: "handlers-test-seeded-faults", endpoint: "/flaky", seed: Some(11) };
+        let calls = || {
+            (0..20).map(|_| {
+                let request = create_test_request(&Method::Get, "/flaky", None);
+                let response = try_match_conditions(&request, &endpoint, &Method::Get, &scope).unwrap();
+                (response.delay, response.fault)
+            }).collect::<Vec<_>>()
+        };
+
+        let first = calls();
+        state::reset_project(scope.project);
+        assert_eq!(calls(), first);
+        // Calls still differ from one another.
+        assert!(first.iter().any(|(_, fault)| fault.is_some()) && first.iter().any(|(_, fault)| fault.is_none()));
+        assert!(first.iter().any(|(delay, _)| *delay != first[0].0));
+    }
+
+    #[test]
+    fn test_random_delay() {
+        let mut rng = Rng::new(3);
+        let normal = schema::DelayDistribution::Normal { mean: 100.0, std_dev: 10.0 };
+        let long_tail = schema::DelayDistribution::LongTail { median: 100.0, sigma: 1.0 };
+        let normal_delays: Vec<u64> = (0..1000).map(|_| random_delay(&normal, &mut rng)).collect();
+        let mut long_tail_delays: Vec<u64> = (0..1000).map(|_| random_delay(&long_tail, &mut rng)).collect();
+
+        let mean = normal_delays.iter().sum::<u64>() as f64 / 1000.0;
+        assert!((95.0..105.0).contains(&mean));
+        assert!(normal_delays.iter().all(|delay| (40..160).contains(delay)));
+        long_tail_delays.sort();
+        assert!((80..120).contains(&long_tail_delays[500]));
+        assert!(long_tail_delays[990] > 500);
+    }
+
+    #[test]
+    fn test_try_match_conditions_scenarios() {
+        let endpoint: schema::Endpoint = serde_json::from_str(r#"{"when": [
+            {
+                "method": "GET",
+                "request": {},
+                "scenario": "login",
+                "requiredState": "LoggedIn",
+                "response": {"status": 200, "headers": {}, "body": "profile"}
+            },
+            {
+                "method": "GET",
+                "request": {},
+                "response": {"status": 401, "headers": {}}
+            },
+            {
+                "method": "POST",
+                "request": {},
+                "scenario": "login",
+                "newState": "LoggedIn",
+                "response": {"status": 204, "headers": {}}
+            }
+        ]}"#).unwrap();
+        let scope = MatchScope { project: "handlers-test-scenarios", endpoint: "/me", seed: None };
+        let status = |method: &Method| {
+            let request = create_test_request(method, "/me", None);
+            try_match_conditions(&request, &endpoint, method, &scope).unwrap().status
+        };
+
+        assert_eq!(status(&Method::Get), 401);
+        assert_eq!(status(&Method::Post), 204);
+        assert_eq!(state::scenario_state(scope.project, "login"), "LoggedIn");
+        assert_eq!(status(&Method::Get), 200);
+        state::reset_scenario(scope.project, "login");
+        assert_eq!(status(&Method::Get), 401);
+    }
+
+    #[test]
+    fn test_check_condition_body_schema_without_on_invalid() {
+        let condition: schema::WhenCondition = serde_json::from_str(r#"{
+            "method": "POST",
+            "request": {"schema": {"type": "array", "minItems": 1}},
+            "response": {"status": 200, "headers": {}, "body": "ok"}
+        }"#).unwrap();
+        let mut request = create_test_request(&Method::Post, "/items", Some("[1]".to_string()));
+        assert!(check_condition(&request, &condition, true));
+
+        request.body = "[]".to_string();
+        assert!(!check_condition(&request, &condition, true));
+        let scope = MatchScope { project: "handlers-test-without-on-invalid", endpoint: "/items", seed: None };
+        assert!(invalid_body_response(&request, &condition, &scope).is_none());
+    }
+
+    #[test]
+    fn test_check_condition_form_fields() {
+        let condition: schema::WhenCondition = serde_json::from_str(r#"{
+            "method": "POST",
+            "request": {"form": {
+                "name": {"operator": "is", "value": "John Doe"},
+                "age": {"operator": "gte", "value": 18},
+                "debug": {"operator": "absent"}
+            }},
+            "response": {"status": 200, "headers": {}, "body": "ok"}
+        }"#).unwrap();
+        let mut request = create_test_request(&Method::Post, "/signup", Some("name=John+Doe&age=21".to_string()));
+        assert!(!check_condition(&request, &condition, true));
+
+        request.headers.insert("content-type".to_string(), "application/x-www-form-urlencoded; charset=UTF-8".to_string());
+        assert!(check_condition(&request, &condition, true));
+
+        request.body = "name=John%20Doe&age=17".to_string();
+        assert!(!check_condition(&request, &condition, true));
+    }
+
+    #[test]
+    fn test_check_condition_multipart_files() {
+        let condition: schema::WhenCondition = serde_json::from_str(r#"{
+            "method": "POST",
+            "request": {
+                "form": {"title": {"operator": "startsWith", "value": "Hol"}},
+                "files": {"avatar": {
+                    "filename": {"operator": "endsWith", "value": ".png"},
+                    "size": {"operator": "lte", "value": 8},
+                    "contentType": {"operator": "is", "value": "image/png"}
+                }}
+            },
+            "response": {"status": 200, "headers": {}, "body": "ok"}
+        }"#).unwrap();
+        let body = b"--b1\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHoliday\r\n\
+            --b1\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"me.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\x89PNG\xff\x00\r\n--b1--\r\n";
+        let mut request = create_test_request(&Method::Post, "/photos", None);
+        request.headers.insert("content-type".to_string(), "multipart/form-data; boundary=b1".to_string());
+        request.raw_body = body.to_vec();
+        request.body = String::from_utf8_lossy(body).into_owned();
+        assert!(check_condition(&request, &condition, true));
+
+        let renamed = String::from_utf8_lossy(body).replace("me.png", "me.gif");
+        request.raw_body = renamed.into_bytes();
+        assert!(!check_condition(&request, &condition, true));
+    }
+
+    #[test]
+    fn test_check_condition_text_body() {
+        let condition: schema::WhenCondition = serde_json::from_str(r#"{
+            "method": "POST",
+            "request": {"text": {"operator": "matches", "value": "^PING \\d+$"}},
+            "response": {"status": 200, "headers": {}, "body": "pong"}
+        }"#).unwrap();
+        let mut request = create_test_request(&Method::Post, "/ping", Some("PING 42".to_string()));
+        request.headers.insert("content-type".to_string(), "text/plain".to_string());
+        assert!(check_condition(&request, &condition, true));
+
+        request.headers.insert("content-type".to_string(), "application/octet-stream".to_string());
+        assert!(!check_condition(&request, &condition, true));
+        request.headers.insert("content-type".to_string(), "application/json".to_string());
+        request.body = "PING 7".to_string();
+        assert!(check_condition(&request, &condition, true));
+    }
 }
@@ -1,6 +1,8 @@
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::env;
+use crate::project_name::ProjectName;
 use crate::web_server::types::Request;
 
 
@@ -12,19 +14,40 @@ pub fn get_env_var<T: FromStr>(key: &str, default: T) -> T {
       .unwrap_or(default)
 }
 
-/// Returns the path to a project's config file.
-pub fn get_project_config_file_path(project_name: &str) -> PathBuf {
+/// Returns the path to the folder of the project config files.
+pub fn get_projects_folder_path() -> PathBuf {
   let database_root_folder = get_env_var("MOCK_SERVER_DB_ROOT", "./database".to_string());
-  PathBuf::from(format!("{}/projects/{}.json", database_root_folder, project_name))
+  PathBuf::from(format!("{}/projects", database_root_folder))
+}
+
+/// Returns the path to a project's config file.
+pub fn get_project_config_file_path(project_name: &ProjectName) -> PathBuf {
+  get_projects_folder_path().join(format!("{}.json", project_name))
+}
+
+/// Returns the project name from a request, checked to be a valid name.
+pub fn project_name_from_request(request: &Request) -> Result<ProjectName, String> {
+  ProjectName::parse(request.params.get("name").map_or("", String::as_str))
+}
+
+/// Writes a file through a temporary file in the same folder, renamed over
+/// the file once written, so that readers never see a partial file.
+pub fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+  written_temp_file(path, content)?.persist(path).map_err(|e| e.error)?;
+  Ok(())
 }
 
-/// Returns the project name from a request.
-pub fn project_name_from_request(request: &Request) -> String {
-  request.params.get("name").unwrap().to_string()
+/// Writes a new file like `write_atomically`, but fails with
+/// `ErrorKind::AlreadyExists` instead of replacing an existing file.
+pub fn write_new_atomically(path: &Path, content: &str) -> io::Result<()> {
+  written_temp_file(path, content)?.persist_noclobber(path).map_err(|e| e.error)?;
+  Ok(())
 }
 
-/// Returns the path to a project's config file from a request.
-pub fn config_file_path_from_request(request: &Request) -> PathBuf {
-  let project_name = project_name_from_request(request);
-  get_project_config_file_path(&project_name)
+fn written_temp_file(path: &Path, content: &str) -> io::Result<tempfile::NamedTempFile> {
+  let folder = path.parent().unwrap_or(Path::new("."));
+  let mut file = tempfile::NamedTempFile::new_in(folder)?;
+  file.write_all(content.as_bytes())?;
+  file.as_file().sync_all()?;
+  Ok(file)
 }
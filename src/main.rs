@@ -1,5 +1,8 @@
 mod web_server;
 
+use std::fs;
+use std::time::Duration;
+
 use web_server::{
   types::{Method, RequestOption, Response},
   Server, ServerConf,
@@ -8,29 +11,118 @@ use web_server::{
 mod llm;
 
 mod schema;
+mod datetime;
+mod matcher;
+mod routes;
+mod openapi;
+mod project_name;
+mod proxy;
+mod random;
+mod resources;
+mod state;
+mod validation;
+mod template;
 mod handlers;
 mod helpers;
 mod cache;
+mod store;
+
+/// Route of the mocked endpoints.
+/// explain: 
+/// 1. /projects/ - matches the exact path
+/// 2. ([^/?#]+) - matches the project name, e.g. "my.project-1" (percent-encoded)
+/// 3. (/[^?#]*) - matches the API path (with starting slash, e.g. "/api/v1.2/items/")
+/// 4. (?:\?.*)? - matches the query string, which is parsed and percent-decoded separately
+const MOCK_ROUTE: &str = r"^/projects/([^/?#]+)(/[^?#]*)(?:\?.*)?$";
 
-fn init() -> (String, usize, String) {
+fn init() -> (String, usize, u64, usize, u64, String) {
     let server_addr = format!("127.0.0.1:{}", helpers::get_env_var("MOCK_SERVER_PORT", "53500".to_string()));
     let max_connections: usize = helpers::get_env_var("MOCK_SERVER_MAX_CONN", 1000);
+    let keep_alive_secs: u64 = helpers::get_env_var("MOCK_SERVER_KEEP_ALIVE_SECS", 5);
+    let max_body_bytes: usize = helpers::get_env_var("MOCK_SERVER_MAX_BODY_BYTES", 10 * 1024 * 1024);
+    let watch_interval_ms: u64 = helpers::get_env_var("MOCK_SERVER_WATCH_MS", 1000);
     let database_root_folder = helpers::get_env_var("MOCK_SERVER_DB_ROOT", "./database".to_string());
     
     println!("Server is running:");
     println!("  - Address: {}", server_addr.clone());
     println!("  - Database root folder: {}", database_root_folder);
+    match store::kind() {
+        store::StoreKind::Sqlite => println!("  - Config store: sqlite ({})", store::sqlite_path().display()),
+        kind => println!("  - Config store: {}", kind),
+    }
+    match store::store().list() {
+        Ok(projects) => println!("  - Projects: {}", projects.len()),
+        Err(e) => eprintln!("  - Failed to list the projects: {}", e),
+    }
     println!("  - Max connections: {}", max_connections);
+    println!("  - Keep-alive timeout: {}s", keep_alive_secs);
+    println!("  - Max request body: {} bytes", max_body_bytes);
+    println!("  - Config reload interval: {}ms", watch_interval_ms);
 
-    (server_addr, max_connections, database_root_folder)
+    (server_addr, max_connections, keep_alive_secs, max_body_bytes, watch_interval_ms, database_root_folder)
 }
 
-fn main() {
-    let (server_addr, max_connections, _) = init();
-    
-    let mut server = Server::new(ServerConf {
-        max_connections: max_connections,
-    });
+/// `mockapi import-openapi <file> [<project>]`: converts an OpenAPI 3
+/// document to a project config, saved as the project if one is named,
+/// else printed. Returns the exit code.
+fn import_openapi_command(args: &[String]) -> i32 {
+    let (file, project_name) = match args {
+        [file] => (file, None),
+        [file, project_name] => (file, Some(project_name)),
+        _ => {
+            eprintln!("Usage: mockapi import-openapi <file> [<project>]");
+            return 2;
+        }
+    };
+    let result = fs::read_to_string(file)
+        .map_err(|e| format!("Failed to read {}: {}", file, e))
+        .and_then(|document| openapi::import(&document))
+        .and_then(|config| serde_json::to_string_pretty(&config).map_err(|e| e.to_string()));
+    let content = match result {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let Some(project_name) = project_name else {
+        println!("{}", content);
+        return 0;
+    };
+    let project_name = match project_name::ProjectName::parse(project_name) {
+        Ok(project_name) => project_name,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let store = store::store();
+    match store.exists(&project_name) {
+        Ok(false) => {}
+        Ok(true) => {
+            eprintln!("Project already exists: {}", project_name);
+            return 1;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    }
+    match store.save(&project_name, &content) {
+        Ok(_) => {
+            println!("Created project {}", project_name);
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to save config: {}", e);
+            1
+        }
+    }
+}
+
+/// Registers the admin routes, the mock route and the pages of the server.
+fn register_routes(server: &mut Server) {
+    server.get("/projects", handlers::list_projects());
 
     server.get("/projects/:name", handlers::get_config());
 
@@ -38,30 +130,43 @@ fn main() {
 
     server.put("/projects/:name", handlers::save_config());
 
+    server.delete("/projects/:name", handlers::delete_project());
+
+    // Registered before the mock route, which would otherwise take the paths.
+    server.post("/projects/:name/clone", handlers::clone_project());
+
+    server.post("/projects/:name/rename", handlers::rename_project());
+
     server.post("/llm/:name", handlers::build_config_with_llm());
 
-    // DO NOT MODIFY THIS STRING r !!!
-    let r = r"^/projects/(\w+)((/\w+)+)(\?(\w+=\w+)(&\w+=\w+)*)?$"; 
-    // explain: 
-    // 1. /projects/ - matches the exact path
-    // 2. (\w+) - matches the project name
-    // 3. (/\w+)+ - matches the API path (with starting slash, e.g. "/api/v1")
-    // 4. (\?(\w+=\w+)(&\w+=\w+)*)? - matches the queries
-    // DO NOT MODIFY THIS REGEX STRING r !!!
-    server.request(
-      handlers::mock_request(),
-      RequestOption {
-        path: web_server::types::RequestPathPattern::Match(r.to_string()),
-        method: Method::Get,
-      },
-    );
-    server.request(
+    server.post("/import/openapi/:name", handlers::import_openapi());
+
+    // Registered before the mock route, which would otherwise take the path.
+    server.get("/projects/:name/openapi", handlers::export_openapi());
+
+    server.get("/scenarios/:name", handlers::get_scenarios());
+
+    server.put("/scenarios/:name/:scenario", handlers::set_scenario());
+
+    server.delete("/scenarios/:name", handlers::reset_scenarios());
+
+    server.delete("/scenarios/:name/:scenario", handlers::reset_scenarios());
+
+    server.delete("/resources/:name", handlers::reset_resources());
+
+    server.delete("/cache", handlers::flush_cache());
+
+    server.delete("/cache/:name", handlers::flush_cache());
+
+    for method in Method::ALL {
+      server.request(
         handlers::mock_request(),
         RequestOption {
-          path: web_server::types::RequestPathPattern::Match(r.to_string()),
-          method: Method::Post,
+          path: web_server::types::RequestPathPattern::Match(MOCK_ROUTE.to_string()),
+          method,
         },
-    );
+      );
+    }
 
     // Add API documentation endpoint
     server.get("/api-doc", |_| {
@@ -77,6 +182,28 @@ fn main() {
         let html = include_str!("templates/new_project.html");
         Response::html(html.to_string())
     });
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("import-openapi") {
+        std::process::exit(import_openapi_command(&args[2..]));
+    }
+
+    let (server_addr, max_connections, keep_alive_secs, max_body_bytes, watch_interval_ms, _) = init();
+
+    // Picks up configs edited on disk, e.g. in an IDE or by a git checkout.
+    if watch_interval_ms > 0 {
+      cache::watch(Duration::from_millis(watch_interval_ms));
+    }
+    
+    let mut server = Server::new(ServerConf {
+        max_connections: max_connections,
+        keep_alive_timeout: Duration::from_secs(keep_alive_secs),
+        max_body_size: max_body_bytes,
+    });
+
+    register_routes(&mut server);
 
     server.listen(server_addr);
 
@@ -97,22 +224,30 @@ mod tests {
     fn setup_test_server() -> Server {
         let mut server = Server::new(ServerConf {
             max_connections: 10,
+            keep_alive_timeout: Duration::from_secs(1),
+            max_body_size: 1024 * 1024,
         });
 
-        server.get("/projects/:name", handlers::get_config());
-        server.post("/projects/:name", handlers::save_config());
-        server.put("/projects/:name", handlers::save_config());
-
-        server.request(
-            handlers::mock_request(),
-            RequestOption {
-                path: web_server::types::RequestPathPattern::Match(r"^/projects/([^/]+)/([^?]+)(?:\?(.*))?$".to_string()),
-                method: Method::Get,
-            },
-        );
+        register_routes(&mut server);
         server
     }
 
+    /// Runs a test against a fresh server whose projects are saved in a
+    /// temporary folder, given to the test as the database root.
+    fn with_test_server(test: impl FnOnce(&Server, &std::path::Path)) {
+        let test_dir = TempDir::new().unwrap();
+        fs::create_dir_all(test_dir.path().join("projects")).unwrap();
+        temp_env::with_var("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap()), || {
+            test(&setup_test_server(), test_dir.path())
+        });
+    }
+
+    /// Saves a project config, which must be accepted.
+    fn create_project(server: &Server, name: &str, config: &str) {
+        let response = server.test_request(Method::Post, &format!("/projects/{}", name), None, Some(config.to_string()));
+        assert_eq!(response.status, 200, "{}", response.body);
+    }
+
     #[test]
     fn test_nonexistent_project() {
         let server = setup_test_server();
@@ -123,16 +258,10 @@ mod tests {
 
     #[test]
     fn test_create_and_get_project() {
-        let test_dir = TempDir::new().unwrap();
-        fs::create_dir_all(test_dir.path().join("projects")).unwrap();
-        
-        temp_env::with_var("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap()), || {
-            let server = setup_test_server();
-            
+        with_test_server(|server, _| {
             // Create test project
             let test_config = r#"{"description": "test", "endpoints": {}}"#;
-            let response = server.test_request(Method::Post, "/projects/test-project", None, Some(test_config.to_string()));
-            assert_eq!(response.status, 200);
+            create_project(server, "test-project", test_config);
 
             // Get created project
             let response = server.test_request(Method::Get, "/projects/test-project", None, None);
@@ -142,11 +271,98 @@ mod tests {
     }
 
     #[test]
-    fn test_mock_endpoint() {
+    fn test_memory_and_sqlite_stores() {
         let test_dir = TempDir::new().unwrap();
-        fs::create_dir_all(test_dir.path().join("projects")).unwrap();
-        temp_env::with_var("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap()), || {
-            let server = setup_test_server();
+        let config = r#"{"description": "stored", "endpoints": {"/ping": {"when": [
+            {"method": "GET", "request": {}, "response": {"status": 200, "headers": {}, "body": "pong"}}
+        ]}}}"#;
+        for kind in ["memory", "sqlite"] {
+            let vars = [
+                ("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap())),
+                ("MOCK_SERVER_STORE", Some(kind)),
+            ];
+            temp_env::with_vars(vars, || {
+                let server = setup_test_server();
+                let path = format!("/projects/test-store-{}", kind);
+                let response = server.test_request(Method::Post, &path, None, Some(config.to_string()));
+                assert_eq!(response.status, 200, "{}", kind);
+                let response = server.test_request(Method::Post, &path, None, Some(config.to_string()));
+                assert_eq!(response.status, 400, "{}", kind);
+                assert_eq!(server.test_request(Method::Get, &path, None, None).body, config);
+                assert_eq!(server.test_request(Method::Get, &format!("{}/ping", path), None, None).body, "pong");
+            });
+        }
+        // Nothing was written to the projects folder.
+        assert!(!test_dir.path().join("projects").exists());
+        assert!(test_dir.path().join("projects.db").exists());
+    }
+
+    #[test]
+    fn test_project_lifecycle() {
+        with_test_server(|server, db_root| {
+            let config = r#"{"description": "shop", "endpoints": {"/ping": {"when": [
+                {"method": "GET", "request": {}, "response": {"status": 200, "headers": {}, "body": "pong"}}
+            ]}, "/items": {"when": []}}}"#;
+            create_project(server, "test-life", config);
+            fs::write(db_root.join("projects/test-broken.json"), "{").unwrap();
+
+            let response = server.test_request(Method::Get, "/projects", None, None);
+            assert_eq!(response.status, 200);
+            let listed: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+            let projects = listed["projects"].as_array().unwrap();
+            assert_eq!(projects.len(), 2);
+            assert_eq!(projects[0]["name"], "test-broken");
+            assert!(projects[0]["error"].as_str().unwrap().starts_with("Invalid project configuration format"));
+            assert_eq!(projects[1]["name"], "test-life");
+            assert_eq!(projects[1]["description"], "shop");
+            assert_eq!(projects[1]["endpoints"], 2);
+            assert!(datetime::parse_timestamp(projects[1]["modified"].as_str().unwrap()).is_some());
+
+            // Clone
+            let clone = |name: &str, body: &str| {
+                server.test_request(Method::Post, &format!("/projects/{}/clone", name), None, Some(body.to_string()))
+            };
+            assert_eq!(clone("test-life", r#"{"name": "test-life-copy"}"#).status, 200);
+            assert_eq!(clone("test-life", r#"{"name": "test-life-copy"}"#).status, 400);
+            assert_eq!(clone("test-life", r#"{"name": "../secret"}"#).status, 400);
+            assert_eq!(clone("test-life", r#"{"new": "x"}"#).status, 400);
+            assert_eq!(clone("test-missing", r#"{"name": "test-x"}"#).status, 404);
+            assert_eq!(server.test_request(Method::Get, "/projects/test-life-copy", None, None).body, config);
+            assert_eq!(server.test_request(Method::Get, "/projects/test-life-copy/ping", None, None).body, "pong");
+
+            // Rename
+            let response = server.test_request(Method::Post, "/projects/test-life/rename", None, Some(r#"{"name": "test-life-v2"}"#.to_string()));
+            assert_eq!(response.status, 200);
+            assert_eq!(server.test_request(Method::Get, "/projects/test-life", None, None).status, 404);
+            assert_eq!(server.test_request(Method::Get, "/projects/test-life/ping", None, None).body, "Project does not exist.");
+            assert_eq!(server.test_request(Method::Get, "/projects/test-life-v2/ping", None, None).body, "pong");
+
+            // Clone and rename take precedence over mocked POST /clone and /rename; other methods reach the mock.
+            let names = r#"{"description": "names", "endpoints": {"/clone": {"when": [
+                {"method": "POST", "request": {}, "response": {"status": 200, "headers": {}, "body": "mock clone"}},
+                {"method": "GET", "request": {}, "response": {"status": 200, "headers": {}, "body": "mock clone"}}
+            ]}}}"#;
+            create_project(server, "test-life-names", names);
+            assert_eq!(server.test_request(Method::Post, "/projects/test-life-names/clone", None, None).status, 400);
+            assert_eq!(server.test_request(Method::Post, "/projects/test-life-names/rename", None, None).status, 400);
+            assert_eq!(server.test_request(Method::Get, "/projects/test-life-names/clone", None, None).body, "mock clone");
+            assert_eq!(server.test_request(Method::Delete, "/projects/test-life-names", None, None).status, 200);
+
+            // Delete
+            assert_eq!(server.test_request(Method::Delete, "/projects/test-life-copy", None, None).status, 200);
+            assert_eq!(server.test_request(Method::Delete, "/projects/test-life-copy", None, None).status, 404);
+            assert_eq!(server.test_request(Method::Get, "/projects/test-life-copy/ping", None, None).body, "Project does not exist.");
+            assert!(!db_root.join("projects/test-life-copy.json").exists());
+
+            let listed: serde_json::Value = serde_json::from_str(&server.test_request(Method::Get, "/projects", None, None).body).unwrap();
+            let names: Vec<&str> = listed["projects"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+            assert_eq!(names, ["test-broken", "test-life-v2"]);
+        });
+    }
+
+    #[test]
+    fn test_mock_endpoint() {
+        with_test_server(|server, _| {
             // Create test project with comprehensive mock configuration
             let test_config = r#"{
                 "description": "test-mock1",
@@ -167,8 +383,7 @@ mod tests {
                         }]
                     }
                 }}"#;
-            let response1 = server.test_request(Method::Post, "/projects/test-mock1", None, Some(test_config.to_string()));
-            assert_eq!(response1.status, 200);
+            create_project(server, "test-mock1", test_config);
             // Test mock endpoint with matching request
             let mut headers = HashMap::new();
             headers.insert("x-test".to_string(), "value".to_string());
@@ -183,4 +398,613 @@ mod tests {
             assert_eq!(response.headers.get("content-type").unwrap(), "text/plain");
         });
     }
+
+    #[test]
+    fn test_mock_endpoint_other_methods() {
+        with_test_server(|server, _| {
+            let test_config = r#"{
+                "description": "test-methods",
+                "endpoints": {
+                    "api/item": {
+                        "when": [
+                            {
+                                "method": "GET",
+                                "request": {},
+                                "response": {"status": 200, "body": "item", "headers": {"x-kind": "item"}}
+                            },
+                            {
+                                "method": "DELETE",
+                                "request": {},
+                                "response": {"status": 204, "headers": {}}
+                            },
+                            {
+                                "method": "PATCH",
+                                "request": {"body": {"name": "new"}},
+                                "response": {"status": 200, "body": "patched", "headers": {}}
+                            }
+                        ]
+                    }
+                }}"#;
+            create_project(server, "test-methods", test_config);
+
+            let response = server.test_request(Method::Delete, "/projects/test-methods/api/item", None, None);
+            assert_eq!(response.status, 204);
+
+            let response = server.test_request(
+                Method::Patch,
+                "/projects/test-methods/api/item",
+                None,
+                Some(r#"{"name": "new"}"#.to_string()),
+            );
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body, "patched");
+
+            // HEAD answers like GET, without the body
+            let response = server.test_request(Method::Head, "/projects/test-methods/api/item", None, None);
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body, "");
+            assert_eq!(response.headers.get("Content-Length").unwrap(), "4");
+            assert_eq!(response.headers.get("x-kind").unwrap(), "item");
+
+            // OPTIONS is answered from the declared methods
+            let response = server.test_request(Method::Options, "/projects/test-methods/api/item", None, None);
+            assert_eq!(response.status, 204);
+            assert_eq!(response.headers.get("Allow").unwrap(), "GET, HEAD, DELETE, PATCH, OPTIONS");
+        });
+    }
+
+    #[test]
+    fn test_mock_endpoint_templated_keys() {
+        with_test_server(|server, _| {
+            let test_config = r#"{
+                "description": "test-templated",
+                "endpoints": {
+                    "/users/me": {
+                        "when": [{"method": "GET", "request": {}, "response": {"status": 200, "body": "me", "headers": {}}}]
+                    },
+                    "/users/:id": {
+                        "when": [
+                            {
+                                "method": "GET",
+                                "request": {"params": {"id": {"operator": "is", "value": "0"}}},
+                                "response": {"status": 404, "body": "no such user", "headers": {}}
+                            },
+                            {"method": "GET", "request": {}, "response": {"status": 200, "body": "user", "headers": {}}}
+                        ]
+                    },
+                    "/orders/{orderId}/items": {
+                        "when": [{"method": "GET", "request": {}, "response": {"status": 200, "body": "items", "headers": {}}}]
+                    },
+                    "/files/*rest": {
+                        "when": [{"method": "GET", "request": {}, "response": {"status": 200, "body": "file", "headers": {}}}]
+                    }
+                }}"#;
+            create_project(server, "test-templated", test_config);
+
+            let get = |path: &str| server.test_request(Method::Get, &format!("/projects/test-templated{}", path), None, None);
+            assert_eq!(get("/users/me").body, "me");
+            assert_eq!(get("/users/42").body, "user");
+            let response = get("/users/0");
+            assert_eq!(response.status, 404);
+            assert_eq!(response.body, "no such user");
+            assert_eq!(get("/orders/7/items").body, "items");
+            assert_eq!(get("/files/a/b.txt").body, "file");
+            assert_eq!(get("/files").status, 406);
+        });
+    }
+
+    #[test]
+    fn test_mock_endpoint_response_templates() {
+        with_test_server(|server, _| {
+            let test_config = r#"{
+                "description": "test-templates",
+                "endpoints": {
+                    "/users/:id": {
+                        "when": [
+                            {
+                                "method": "GET",
+                                "request": {"queries": {"date": {"operator": "exists"}}},
+                                "response": {
+                                    "status": 200,
+                                    "headers": {"X-User": "user-{{request.params.id}}"},
+                                    "body": {"id": "{{request.params.id}}", "date": "{{request.query.date}}", "requestId": "{{uuid}}"},
+                                    "template": true
+                                }
+                            },
+                            {
+                                "method": "POST",
+                                "request": {"bodyRules": [{"path": "$.name", "operator": "exists"}]},
+                                "response": {
+                                    "status": 201,
+                                    "headers": {},
+                                    "body": {"id": "{{request.params.id}}", "name": "{{request.body.name}}", "age": "{{request.body.age}}"},
+                                    "template": true
+                                }
+                            },
+                            {
+                                "method": "PUT",
+                                "request": {"bodyRules": [{"path": "$", "operator": "exists"}]},
+                                "response": {"status": 200, "headers": {}, "body": "{{request.params.id}}"}
+                            }
+                        ]
+                    }
+                }}"#;
+            create_project(server, "test-templates", test_config);
+
+            let response = server.test_request(Method::Get, "/projects/test-templates/users/42?date=2024-03-01", None, None);
+            assert_eq!(response.status, 200);
+            assert_eq!(response.headers.get("X-User").unwrap(), "user-42");
+            let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+            assert_eq!(body["id"], "42");
+            assert_eq!(body["date"], "2024-03-01");
+            assert_eq!(body["requestId"].as_str().unwrap().len(), 36);
+
+            let created = r#"{"name": "Ann", "age": 30}"#.to_string();
+            let response = server.test_request(Method::Post, "/projects/test-templates/users/7", None, Some(created));
+            assert_eq!(response.status, 201);
+            let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+            assert_eq!(body, serde_json::json!({"id": "7", "name": "Ann", "age": 30}));
+
+            // Without "template": true the body is returned verbatim.
+            let response = server.test_request(Method::Put, "/projects/test-templates/users/7", None, Some("{}".to_string()));
+            assert_eq!(response.body, "{{request.params.id}}");
+
+            let broken = test_config.replace("{{uuid}}", "{{request.cookies.session}}").replace("test-templates", "test-templates-broken");
+            let response = server.test_request(Method::Post, "/projects/test-templates-broken", None, Some(broken));
+            assert_eq!(response.status, 400);
+            assert!(response.body.contains("unknown template expression"));
+
+            // Nested repeats fit a level each, but not the items in all.
+            let huge = r#"{"description": "test-templates-huge", "endpoints": {"/all": {"when": [{"method": "GET", "request": {},
+                "response": {"status": 200, "headers": {}, "template": true,
+                    "body": {"$repeat": 10000, "$item": {"$repeat": 10000, "$item": "{{index}}"}}}}]}}}"#;
+            create_project(server, "test-templates-huge", huge);
+            let response = server.test_request(Method::Get, "/projects/test-templates-huge/all", None, None);
+            assert_eq!(response.status, 500);
+            assert!(response.body.contains("Failed to render template"));
+        });
+    }
+
+    #[test]
+    fn test_mock_endpoint_scenarios_and_sequences() {
+        with_test_server(|server, _| {
+            let test_config = r#"{
+                "description": "test-scenarios",
+                "endpoints": {
+                    "/jobs/:id": {
+                        "when": [{
+                            "method": "GET",
+                            "request": {},
+                            "sequence": {"responses": [
+                                {"status": 202, "headers": {}, "body": {"status": "pending"}},
+                                {"status": 202, "headers": {}, "body": {"status": "pending"}},
+                                {"status": 200, "headers": {}, "body": {"status": "done"}}
+                            ]}
+                        }]
+                    },
+                    "/cart": {
+                        "when": [
+                            {
+                                "method": "GET",
+                                "request": {},
+                                "scenario": "cart",
+                                "requiredState": "Filled",
+                                "response": {"status": 200, "headers": {}, "body": ["apple"]}
+                            },
+                            {
+                                "method": "GET",
+                                "request": {},
+                                "response": {"status": 200, "headers": {}, "body": []}
+                            },
+                            {
+                                "method": "POST",
+                                "request": {"body": {"item": "apple"}},
+                                "scenario": "cart",
+                                "newState": "Filled",
+                                "response": {"status": 201, "headers": {}}
+                            }
+                        ]
+                    }
+                }}"#;
+            create_project(server, "test-scenarios", test_config);
+
+            let poll = || server.test_request(Method::Get, "/projects/test-scenarios/jobs/1", None, None).status;
+            assert_eq!([poll(), poll(), poll(), poll()], [202, 202, 200, 200]);
+
+            let cart = || server.test_request(Method::Get, "/projects/test-scenarios/cart", None, None).body;
+            assert_eq!(cart(), "[]");
+            let item = Some(r#"{"item": "apple"}"#.to_string());
+            assert_eq!(server.test_request(Method::Post, "/projects/test-scenarios/cart", None, item).status, 201);
+            assert_eq!(cart(), r#"["apple"]"#);
+
+            let response = server.test_request(Method::Get, "/scenarios/test-scenarios", None, None);
+            let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+            assert_eq!(body, serde_json::json!({"scenarios": {"cart": "Filled"}}));
+
+            // Resetting everything starts the scenarios and the sequences over.
+            assert_eq!(server.test_request(Method::Delete, "/scenarios/test-scenarios", None, None).status, 200);
+            assert_eq!(cart(), "[]");
+            assert_eq!(poll(), 202);
+
+            let state = Some(r#"{"state": "Filled"}"#.to_string());
+            assert_eq!(server.test_request(Method::Put, "/scenarios/test-scenarios/cart", None, state).status, 200);
+            assert_eq!(cart(), r#"["apple"]"#);
+            assert_eq!(server.test_request(Method::Delete, "/scenarios/test-scenarios/cart", None, None).status, 200);
+            assert_eq!(cart(), "[]");
+            assert_eq!(server.test_request(Method::Put, "/scenarios/test-scenarios/cart", None, Some("{}".to_string())).status, 400);
+
+            let broken = test_config.replace(r#""scenario": "cart","#, "").replace("test-scenarios", "test-scenarios-broken");
+            let response = server.test_request(Method::Post, "/projects/test-scenarios-broken", None, Some(broken));
+            assert_eq!(response.status, 400);
+            assert!(response.body.contains("requiredState and newState need a scenario"));
+        });
+    }
+
+    #[test]
+    fn test_mock_resources() {
+        with_test_server(|server, _| {
+            let test_config = r#"{
+                "description": "test-resources",
+                "endpoints": {
+                    "/orders/stats": {
+                        "when": [{"method": "GET", "request": {}, "response": {"status": 200, "headers": {}, "body": "stats"}}]
+                    }
+                },
+                "resources": {
+                    "/orders": {
+                        "data": [
+                            {"id": 1, "status": "open", "total": 30},
+                            {"id": 2, "status": "closed", "total": 5},
+                            {"id": 3, "status": "open", "total": 12}
+                        ]
+                    },
+                    "/orders/items": {"data": [{"id": 1, "sku": "x"}]}
+                }}"#;
+            create_project(server, "test-resources", test_config);
+            let request = |method: Method, path: &str, body: Option<&str>| {
+                let path = format!("/projects/test-resources{}", path);
+                let response = server.test_request(method, &path, None, body.map(str::to_string));
+                let body = serde_json::from_str(&response.body).unwrap_or(serde_json::Value::Null);
+                (response.status, body, response.headers)
+            };
+
+            // Endpoints take precedence over resources, and longer resource keys over shorter ones.
+            assert_eq!(server.test_request(Method::Get, "/projects/test-resources/orders/stats", None, None).body, "stats");
+            assert_eq!(request(Method::Get, "/orders/items", None).1, serde_json::json!([{"id": 1, "sku": "x"}]));
+            assert_eq!(request(Method::Get, "/orders/items/1", None).1, serde_json::json!({"id": 1, "sku": "x"}));
+
+            let (status, body, headers) = request(Method::Get, "/orders?status=open&_sort=total&_order=desc&_limit=1", None);
+            assert_eq!(status, 200);
+            assert_eq!(body, serde_json::json!([{"id": 1, "status": "open", "total": 30}]));
+            assert_eq!(headers.get("X-Total-Count").unwrap(), "2");
+
+            let (status, body, _) = request(Method::Post, "/orders", Some(r#"{"status": "open", "total": 8}"#));
+            assert_eq!((status, body["id"].clone()), (201, serde_json::json!(4)));
+            assert_eq!(request(Method::Post, "/orders", Some(r#"{"id": 4}"#)).0, 409);
+            assert_eq!(request(Method::Post, "/orders", Some("[]")).0, 400);
+
+            let (status, body, _) = request(Method::Patch, "/orders/4", Some(r#"{"status": "closed", "id": 9}"#));
+            assert_eq!((status, body), (200, serde_json::json!({"id": 4, "status": "closed", "total": 8})));
+            let (status, body, _) = request(Method::Put, "/orders/4", Some(r#"{"total": 1}"#));
+            assert_eq!((status, body), (200, serde_json::json!({"id": 4, "total": 1})));
+            assert_eq!(request(Method::Get, "/orders/4", None).1, serde_json::json!({"id": 4, "total": 1}));
+
+            assert_eq!(request(Method::Delete, "/orders/4", None).0, 204);
+            assert_eq!(request(Method::Get, "/orders/4", None).0, 404);
+            assert_eq!(request(Method::Delete, "/orders", None).0, 405);
+
+            assert_eq!(request(Method::Delete, "/orders/1", None).0, 204);
+            assert_eq!(server.test_request(Method::Delete, "/resources/test-resources", None, None).status, 200);
+            assert_eq!(request(Method::Get, "/orders/1", None).0, 200);
+
+            let broken = test_config.replace(r#""id": 3"#, r#""id": 2"#).replace("test-resources", "test-resources-broken");
+            let response = server.test_request(Method::Post, "/projects/test-resources-broken", None, Some(broken));
+            assert_eq!(response.status, 400);
+            assert!(response.body.contains("/resources/~1orders/data/2: duplicate id 2"));
+        });
+    }
+
+    /// Starts a stand-in for a real backend and returns its base URL.
+    fn spawn_upstream() -> String {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let calls = AtomicUsize::new(0);
+        let mut upstream = Server::new(ServerConf {
+            max_connections: 10,
+            keep_alive_timeout: Duration::from_secs(1),
+            max_body_size: 1024 * 1024,
+        });
+        // Exact paths take no queries, so the listener matches a pattern.
+        let items = move |request: web_server::types::Request| {
+            let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut headers = HashMap::new();
+            headers.insert("X-Upstream".to_string(), request.queries.get("page").cloned().unwrap_or_default());
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+            Response::new(200, format!(r#"{{"call": {}}}"#, call), headers)
+        };
+        upstream.request(items, RequestOption {
+            path: web_server::types::RequestPathPattern::Match(r"^/api/items(?:\?.*)?$".to_string()),
+            method: Method::Get,
+        });
+        upstream.post("/api/echo", |request| Response::new(201, request.body, HashMap::new()));
+        upstream.get("/api/users/:id", |request| {
+            let api_key = web_server::header_value(&request.headers, "X-Api-Key").unwrap_or_default();
+            let cookie = web_server::header_value(&request.headers, "Cookie").is_some();
+            let body = format!("upstream user {} key={} cookie={}", request.params["id"], api_key, cookie);
+            Response::new(200, body, HashMap::new())
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || upstream.listen_on(listener));
+        format!("http://{}/api", addr)
+    }
+
+    #[test]
+    fn test_mock_proxy_and_record() {
+        with_test_server(|server, db_root| {
+            let upstream = spawn_upstream();
+            let test_config = format!(r#"{{
+                "description": "test-proxy",
+                "endpoints": {{
+                    "/local": {{"when": [{{"method": "GET", "request": {{}}, "response": {{"status": 200, "headers": {{}}, "body": "local"}}}}]}}
+                }},
+                "proxy": {{"upstream": "{}", "record": true}}
+            }}"#, upstream);
+            create_project(server, "test-proxy", &test_config);
+
+            // The project answers what it can; the rest goes upstream.
+            assert_eq!(server.test_request(Method::Get, "/projects/test-proxy/local", None, None).body, "local");
+            let response = server.test_request(Method::Get, "/projects/test-proxy/items?page=1", None, None);
+            assert_eq!((response.status, response.body.as_str()), (200, r#"{"call": 1}"#));
+            assert_eq!(response.headers.get("x-upstream").unwrap(), "1");
+            let echo = Some(r#"{"name": "Ann"}"#.to_string());
+            let response = server.test_request(Method::Post, "/projects/test-proxy/echo", None, echo.clone());
+            assert_eq!((response.status, response.body.as_str()), (201, r#"{"name": "Ann"}"#));
+
+            // Recorded exchanges are replayed without calling the upstream.
+            let response = server.test_request(Method::Get, "/projects/test-proxy/items?page=1", None, None);
+            assert_eq!(response.body, r#"{"call":1}"#);
+            assert_eq!(response.headers.get("x-upstream").unwrap(), "1");
+            assert_eq!(server.test_request(Method::Post, "/projects/test-proxy/echo", None, echo).status, 201);
+            let response = server.test_request(Method::Get, "/projects/test-proxy/items?page=2", None, None);
+            assert_eq!(response.body, r#"{"call": 2}"#);
+
+            // Every value of a repeated query is recorded, and the raw body as sent.
+            let response = server.test_request(Method::Get, "/projects/test-proxy/items?page=3&tag=a&tag=b", None, None);
+            assert_eq!(response.body, r#"{"call": 3}"#);
+            let response = server.test_request(Method::Get, "/projects/test-proxy/items?page=3&tag=a&tag=b", None, None);
+            assert_eq!(response.body, r#"{"call":3}"#);
+            let response = server.test_request(Method::Get, "/projects/test-proxy/items?page=3&tag=a", None, None);
+            assert_eq!(response.body, r#"{"call": 4}"#);
+            let json = HashMap::from([("Content-Type".to_string(), "application/json".to_string())]);
+            let spaced = Some("{ \"name\" :\"Bo\" }".to_string());
+            server.test_request(Method::Post, "/projects/test-proxy/echo", Some(json.clone()), spaced.clone());
+            assert_eq!(server.test_request(Method::Post, "/projects/test-proxy/echo", Some(json), spaced).status, 201);
+
+            let saved = fs::read_to_string(db_root.join("projects/test-proxy.json")).unwrap();
+            let saved: serde_json::Value = serde_json::from_str(&saved).unwrap();
+            assert_eq!(saved["endpoints"]["/items"]["when"].as_array().unwrap().len(), 4);
+            let tags = &saved["endpoints"]["/items"]["when"][2]["request"]["queries"]["tag"];
+            assert_eq!(tags, &serde_json::json!({"operator": "is", "value": ["a", "b"]}));
+            let echoes = saved["endpoints"]["/echo"]["when"].as_array().unwrap();
+            assert_eq!(echoes.len(), 2);
+            assert_eq!(echoes[0]["request"]["body"], serde_json::json!({"name": "Ann"}));
+            assert_eq!(echoes[1]["request"]["text"], serde_json::json!({"operator": "is", "value": "{ \"name\" :\"Bo\" }"}));
+
+            // Without recording every request is forwarded; an unreachable upstream gives 502.
+            let passthrough = test_config.replace("test-proxy", "test-proxy-pass").replace(", \"record\": true", "");
+            create_project(server, "test-proxy-pass", &passthrough);
+            let response = server.test_request(Method::Get, "/projects/test-proxy-pass/items", None, None);
+            assert_eq!(response.body, r#"{"call": 5}"#);
+            assert_eq!(server.test_request(Method::Get, "/projects/test-proxy-pass/items", None, None).body, r#"{"call": 6}"#);
+
+            let unreachable = test_config.replace("test-proxy", "test-proxy-down").replace(&upstream, "http://127.0.0.1:9/api");
+            create_project(server, "test-proxy-down", &unreachable);
+            let response = server.test_request(Method::Get, "/projects/test-proxy-down/items", None, None);
+            assert_eq!(response.status, 502);
+            assert!(response.body.contains("Upstream request failed"));
+        });
+    }
+
+    #[test]
+    fn test_mock_fallbacks() {
+        with_test_server(|server, _| {
+            let upstream = spawn_upstream();
+            let test_config = format!(r#"{{
+                "description": "test-fallback",
+                "endpoints": {{
+                    "/users/:id": {{
+                        "when": [{{
+                            "method": "GET",
+                            "request": {{"params": {{"id": {{"operator": "is", "value": "1"}}}}}},
+                            "response": {{"status": 200, "headers": {{}}, "body": "mocked user 1"}}
+                        }}],
+                        "fallback": {{"proxy": {{"upstream": "{}", "headers": {{"X-Api-Key": "secret", "Cookie": null}}}}}}
+                    }},
+                    "/legacy": {{
+                        "when": [{{"method": "GET", "request": {{}}, "response": {{"status": 200, "headers": {{}}}}}}],
+                        "fallback": "notImplemented"
+                    }}
+                }},
+                "fallback": {{"response": {{
+                    "status": 404,
+                    "headers": {{}},
+                    "body": {{"error": "No mock for {{{{request.method}}}} {{{{request.path}}}}"}},
+                    "template": true
+                }}}}
+            }}"#, upstream);
+            create_project(server, "test-fallback", &test_config);
+
+            // Endpoints that the backend has not built yet are mocked, the rest go to it.
+            assert_eq!(server.test_request(Method::Get, "/projects/test-fallback/users/1", None, None).body, "mocked user 1");
+            let mut headers = HashMap::new();
+            headers.insert("Cookie".to_string(), "session=1".to_string());
+            let response = server.test_request(Method::Get, "/projects/test-fallback/users/2", Some(headers), None);
+            assert_eq!(response.body, "upstream user 2 key=secret cookie=false");
+
+            assert_eq!(server.test_request(Method::Delete, "/projects/test-fallback/legacy", None, None).status, 406);
+            let response = server.test_request(Method::Delete, "/projects/test-fallback/orders/7", None, None);
+            assert_eq!(response.status, 404);
+            assert_eq!(response.body, r#"{"error":"No mock for DELETE /projects/test-fallback/orders/7"}"#);
+
+            let broken = test_config
+                .replace("test-fallback", "test-fallback-broken")
+                .replace(r#""fallback": {"response""#, &format!(r#""proxy": {{"upstream": "{}"}}, "fallback": {{"response""#, upstream));
+            let response = server.test_request(Method::Post, "/projects/test-fallback-broken", None, Some(broken));
+            assert!(response.body.contains("/fallback: use either proxy or fallback"));
+        });
+    }
+
+    #[test]
+    fn test_project_name_traversal() {
+        with_test_server(|server, db_root| {
+            let secret = r#"{"description": "secret", "endpoints": {"/hello": {"when": [{"method": "GET", "request": {}, "response": {"status": 200, "headers": {}, "body": "secret"}}]}}}"#;
+            fs::write(db_root.join("secret.json"), secret).unwrap();
+            let config = r#"{"description": "evil", "endpoints": {}}"#.to_string();
+            let names = ["..%2Fsecret", "%2e%2e%2fsecret", "..%5Csecret", "%2Etraversal", "..", "a%00b"];
+            for name in names {
+                let requests = [
+                    (Method::Get, format!("/projects/{}", name), None),
+                    (Method::Post, format!("/projects/{}", name), Some(config.clone())),
+                    (Method::Put, format!("/projects/{}", name), Some(config.clone())),
+                    (Method::Post, format!("/llm/{}", name), Some(r#"{"prompt": "an API"}"#.to_string())),
+                    (Method::Get, format!("/projects/{}/hello", name), None),
+                    (Method::Get, format!("/projects/{}/openapi", name), None),
+                    (Method::Delete, format!("/scenarios/{}", name), None),
+                ];
+                for (method, path, body) in requests {
+                    let response = server.test_request(method, &path, None, body);
+                    assert_eq!(response.status, 400, "{}", path);
+                    assert!(response.body.contains("Invalid project name"), "{}: {}", path, response.body);
+                }
+            }
+            assert_eq!(fs::read_to_string(db_root.join("secret.json")).unwrap(), secret);
+            assert_eq!(fs::read_dir(db_root.join("projects")).unwrap().count(), 0);
+            assert_eq!(fs::read_dir(db_root).unwrap().count(), 2);
+        });
+    }
+
+    #[test]
+    fn test_config_edited_on_disk() {
+        with_test_server(|server, _| {
+            let config = |body: &str| format!(r#"{{
+                "description": "test-edited",
+                "endpoints": {{"/hello": {{"when": [{{"method": "GET", "request": {{}}, "response": {{"status": 200, "headers": {{}}, "body": "{}"}}}}]}}}}
+            }}"#, body);
+            create_project(server, "test-edited", &config("v1"));
+            let hello = || server.test_request(Method::Get, "/projects/test-edited/hello", None, None).body;
+            assert_eq!(hello(), "v1");
+
+            let path = helpers::get_project_config_file_path(&project_name::ProjectName::parse("test-edited").unwrap());
+            fs::write(&path, config("version 2")).unwrap();
+            cache::reload_changed_configs();
+            assert_eq!(hello(), "version 2");
+
+            // Without the watcher, a flush makes the next request read the file.
+            fs::write(&path, config("version three")).unwrap();
+            assert_eq!(server.test_request(Method::Delete, "/cache/test-edited", None, None).status, 200);
+            assert_eq!(hello(), "version three");
+            fs::write(&path, config("v4")).unwrap();
+            assert_eq!(server.test_request(Method::Delete, "/cache", None, None).status, 200);
+            assert_eq!(hello(), "v4");
+        });
+    }
+
+    #[test]
+    fn test_import_and_export_openapi() {
+        with_test_server(|server, _| {
+            let document = r##"
+openapi: 3.0.0
+info: {title: Orders, version: "1.0"}
+paths:
+  /orders/{orderId}:
+    get:
+      parameters:
+        - {name: orderId, in: path, required: true, schema: {type: integer}}
+      responses:
+        200:
+          description: An order
+          content:
+            application/json:
+              schema:
+                type: object
+                properties:
+                  id: {type: integer, example: 42}
+                  status: {type: string, enum: [open, closed]}
+        404:
+          description: Not found
+"##;
+            let response = server.test_request(Method::Post, "/import/openapi/test-openapi", None, Some(document.to_string()));
+            assert_eq!(response.status, 200);
+            assert!(response.body.contains("/orders/{orderId}"));
+
+            let response = server.test_request(Method::Get, "/projects/test-openapi/orders/42", None, None);
+            assert_eq!(response.status, 200);
+            assert_eq!(response.headers.get("Content-Type").unwrap(), "application/json");
+            assert_eq!(response.body, r#"{"id":42,"status":"open"}"#);
+            assert_eq!(server.test_request(Method::Get, "/projects/test-openapi", None, None).status, 200);
+
+            let response = server.test_request(Method::Get, "/projects/test-openapi/openapi", None, None);
+            assert_eq!(response.status, 200);
+            let exported: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+            let example = &exported["paths"]["/orders/{orderId}"]["get"]["responses"]["200"]["content"]["application/json"]["example"];
+            assert_eq!(example, &serde_json::json!({"id": 42, "status": "open"}));
+            assert_eq!(server.test_request(Method::Get, "/projects/test-openapi-none/openapi", None, None).status, 404);
+
+            let response = server.test_request(Method::Post, "/import/openapi/test-openapi", None, Some(document.to_string()));
+            assert_eq!(response.status, 400);
+            assert!(response.body.contains("Project already exists."));
+            let response = server.test_request(Method::Post, "/import/openapi/test-openapi-2", None, Some("swagger: '2.0'".to_string()));
+            assert_eq!(response.status, 400);
+            assert!(response.body.contains("OpenAPI 3"));
+        });
+    }
+
+    #[test]
+    fn test_mock_endpoint_real_world_urls() {
+        with_test_server(|server, _| {
+            let test_config = r#"{
+                "description": "my.project-1",
+                "endpoints": {
+                    "/api/v1.2/stock-daily": {
+                        "when": [{
+                            "method": "GET",
+                            "request": {
+                                "queries": {
+                                    "name": {"operator": "is", "value": "John Doe"},
+                                    "codes": {"operator": "is", "value": "600000,600001"},
+                                    "empty": {"operator": "is", "value": ""}
+                                }
+                            },
+                            "response": {"status": 200, "body": "found", "headers": {}}
+                        }]
+                    }
+                }}"#;
+            let response = server.test_request(Method::Post, "/projects/my.project-1", None, Some(test_config.to_string()));
+            assert_eq!(response.status, 200);
+
+            let response = server.test_request(
+                Method::Get,
+                "/projects/my.project-1/api/v1.2/stock-daily/?name=John%20Doe&codes=600000%2C600001&empty=",
+                None,
+                None,
+            );
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body, "found");
+
+            let response = server.test_request(
+                Method::Get,
+                "/projects/my.project-1/api/v1.2/stock-daily?name=John+Doe&codes=600000,600001&empty",
+                None,
+                None,
+            );
+            assert_eq!(response.body, "found");
+        });
+    }
+
+    #[test]
+    fn test_method_from_str() {
+        assert_eq!("DELETE".parse::<Method>(), Ok(Method::Delete));
+        assert_eq!("patch".parse::<Method>(), Ok(Method::Patch));
+        assert!("BREW".parse::<Method>().is_err());
+    }
 }
@@ -0,0 +1,381 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::datetime;
+use crate::schema::{BodyRule, FileRule, Operator, QueryParam};
+
+mod form;
+mod json_path;
+mod json_schema;
+
+pub use form::{is_text_media_type, media_type, multipart_fields, parse_multipart, Part};
+pub use json_path::JsonPath;
+pub use json_schema::{check_schema, SchemaError};
+
+/// Type names accepted by the "type" operator.
+const TYPE_NAMES: [&str; 7] = ["string", "number", "integer", "boolean", "array", "object", "null"];
+
+lazy_static! {
+    static ref REGEX_CACHE: RwLock<HashMap<String, Regex>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Compiles a regular expression once and reuses it for later requests.
+pub fn cached_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    if let Some(regex) = REGEX_CACHE.read().unwrap().get(pattern) {
+        return Ok(regex.clone());
+    }
+    let regex = Regex::new(pattern)?;
+    REGEX_CACHE.write().unwrap().insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+/// Renders a scalar operand as text, e.g. `5` as "5".
+fn operand_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Compares two values as numbers when both are numbers, otherwise as
+/// points in time when both are ISO 8601 dates.
+pub fn compare(actual: &str, expected: &str) -> Option<Ordering> {
+    if let (Ok(actual), Ok(expected)) = (actual.trim().parse::<f64>(), expected.trim().parse::<f64>()) {
+        return actual.partial_cmp(&expected);
+    }
+    let actual = datetime::parse_timestamp(actual)?;
+    let expected = datetime::parse_timestamp(expected)?;
+    Some(actual.cmp(&expected))
+}
+
+/// Checks a value against a rule. `None` stands for a missing value, which
+/// only "absent" accepts.
+pub fn check_rule(rule: &QueryParam, actual: Option<&str>) -> bool {
+    let actual = match (rule.operator, actual) {
+        (Operator::Exists, actual) => return actual.is_some(),
+        (Operator::Absent, actual) => return actual.is_none(),
+        (_, None) => return false,
+        (_, Some(actual)) => actual,
+    };
+    if rule.operator == Operator::Matches {
+        let pattern = operand_text(&rule.value).unwrap_or_default();
+        let pattern = if rule.ignore_case { format!("(?i){}", pattern) } else { pattern };
+        return cached_regex(&pattern).is_ok_and(|regex| regex.is_match(actual));
+    }
+    if rule.operator == Operator::Type {
+        return match rule.value.as_str() {
+            Some("string") => true,
+            Some("number") => actual.trim().parse::<f64>().is_ok_and(f64::is_finite),
+            Some("integer") => actual.trim().parse::<i64>().is_ok(),
+            Some("boolean") => actual == "true" || actual == "false",
+            _ => false,
+        };
+    }
+
+    let fold = |s: &str| if rule.ignore_case { s.to_lowercase() } else { s.to_string() };
+    let actual = fold(actual);
+    let expected = operand_text(&rule.value).map(|value| fold(&value));
+    let in_list = || match &rule.value {
+        Value::Array(items) => items.iter().filter_map(operand_text).any(|item| fold(&item) == actual),
+        _ => false,
+    };
+    let ordering = || expected.as_deref().and_then(|expected| compare(&actual, expected));
+    match (rule.operator, expected.as_deref()) {
+        (Operator::In, _) => in_list(),
+        (Operator::NotIn, _) => !in_list(),
+        (Operator::Gt, _) => ordering() == Some(Ordering::Greater),
+        (Operator::Gte, _) => matches!(ordering(), Some(Ordering::Greater | Ordering::Equal)),
+        (Operator::Lt, _) => ordering() == Some(Ordering::Less),
+        (Operator::Lte, _) => matches!(ordering(), Some(Ordering::Less | Ordering::Equal)),
+        (_, None) => false,
+        (Operator::Is, Some(expected)) => actual == expected,
+        (Operator::IsNot, Some(expected)) => actual != expected,
+        (Operator::Contains, Some(expected)) => actual.contains(expected),
+        (Operator::NotContains, Some(expected)) => !actual.contains(expected),
+        (Operator::StartsWith, Some(expected)) => actual.starts_with(expected),
+        (Operator::EndsWith, Some(expected)) => actual.ends_with(expected),
+        (Operator::Matches | Operator::Exists | Operator::Absent | Operator::Type, _) => unreachable!(),
+    }
+}
+
+/// Name of the JSON type of a value, as used by the "type" operator.
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Compares two JSON values, treating `1` and `1.0` as equal.
+fn json_equals(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_equals(a, b)),
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len() && a.iter().all(|(key, a)| b.get(key).is_some_and(|b| json_equals(a, b)))
+        }
+        _ => actual == expected,
+    }
+}
+
+/// Checks one JSON value against a rule. Strings, numbers and booleans are
+/// checked like query values; arrays support "contains" on their items, and
+/// objects and arrays compare as JSON with "is" and "is!".
+fn check_json_value(rule: &QueryParam, actual: &Value) -> bool {
+    match (rule.operator, actual) {
+        (Operator::Type, _) => match rule.value.as_str() {
+            Some("integer") => actual.as_i64().is_some() || actual.as_u64().is_some()
+                || actual.as_f64().is_some_and(|n| n.fract() == 0.0),
+            Some(name) => json_type(actual) == name,
+            None => false,
+        },
+        (Operator::Contains, Value::Array(items)) => items.iter().any(|item| json_equals(item, &rule.value)),
+        (Operator::NotContains, Value::Array(items)) => !items.iter().any(|item| json_equals(item, &rule.value)),
+        (Operator::Is, Value::Array(_) | Value::Object(_) | Value::Null) => json_equals(actual, &rule.value),
+        (Operator::IsNot, Value::Array(_) | Value::Object(_) | Value::Null) => !json_equals(actual, &rule.value),
+        (Operator::Is, Value::Number(_)) if rule.value.is_number() => json_equals(actual, &rule.value),
+        (Operator::IsNot, Value::Number(_)) if rule.value.is_number() => !json_equals(actual, &rule.value),
+        (_, actual) => match operand_text(actual) {
+            Some(text) => check_rule(rule, Some(&text)),
+            None => matches!(rule.operator, Operator::IsNot | Operator::NotContains | Operator::NotIn),
+        },
+    }
+}
+
+/// Checks a body rule against a JSON request body. When the path selects
+/// several values, one of them must satisfy the rule; "absent" requires the
+/// path to select nothing.
+pub fn check_body_rule(rule: &BodyRule, body: &Value) -> bool {
+    let Ok(path) = JsonPath::parse(&rule.path) else {
+        return false;
+    };
+    let selected = path.select(body);
+    match rule.rule.operator {
+        Operator::Exists => !selected.is_empty(),
+        Operator::Absent => selected.is_empty(),
+        _ => selected.into_iter().any(|value| check_json_value(&rule.rule, value)),
+    }
+}
+
+/// Checks an uploaded file against a file rule; a missing file never matches.
+pub fn check_file_rule(rule: &FileRule, file: Option<&Part>) -> bool {
+    let Some(file) = file else {
+        return false;
+    };
+    let check = |rule: &Option<QueryParam>, actual: Option<&str>| rule.as_ref().is_none_or(|rule| check_rule(rule, actual));
+    check(&rule.filename, file.filename.as_deref())
+        && check(&rule.size, Some(&file.data.len().to_string()))
+        && check(&rule.content_type, file.content_type.as_deref())
+}
+
+/// Validates a request body against a JSON Schema. A body that is not JSON
+/// fails with a single error at the root.
+pub fn validate_body(schema: &Value, body: &str) -> Vec<SchemaError> {
+    match serde_json::from_str::<Value>(body) {
+        Ok(body) => json_schema::validate(schema, &body),
+        Err(e) => vec![SchemaError { path: String::new(), message: format!("body is not valid JSON: {}", e) }],
+    }
+}
+
+/// Checks that `actual` contains `expected`: objects may have extra members,
+/// and each item of an expected array must be contained in some actual item.
+pub fn json_contains(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => expected
+            .iter()
+            .all(|(key, expected)| actual.get(key).is_some_and(|actual| json_contains(actual, expected))),
+        (Value::Array(actual), Value::Array(expected)) => expected
+            .iter()
+            .all(|expected| actual.iter().any(|actual| json_contains(actual, expected))),
+        _ => json_equals(actual, expected),
+    }
+}
+
+/// Tells whether a query rule compares every value of a repeated query, in
+/// order: "is" with a list, e.g. `{"operator": "is", "value": ["a", "b"]}`.
+pub fn is_value_list(rule: &QueryParam) -> bool {
+    rule.operator == Operator::Is && rule.value.is_array()
+}
+
+/// Checks all the values of a query against an "is" rule with a list.
+pub fn check_value_list(rule: &QueryParam, actual: Option<&Vec<String>>) -> bool {
+    let (Value::Array(expected), Some(actual)) = (&rule.value, actual) else {
+        return false;
+    };
+    let fold = |s: &str| if rule.ignore_case { s.to_lowercase() } else { s.to_string() };
+    expected.len() == actual.len()
+        && expected.iter().zip(actual).all(|(expected, actual)| {
+            operand_text(expected).is_some_and(|expected| fold(&expected) == fold(actual))
+        })
+}
+
+/// Checks that the operand of a rule suits its operator, so that a broken
+/// rule is reported when the configuration is saved instead of never matching.
+pub fn validate_rule(rule: &QueryParam) -> Result<(), String> {
+    match rule.operator {
+        Operator::Is if is_value_list(rule) => match &rule.value {
+            Value::Array(items) if items.iter().all(|item| operand_text(item).is_some()) => Ok(()),
+            _ => Err("expected a list of strings or numbers as value".to_string()),
+        },
+        Operator::Exists | Operator::Absent => Ok(()),
+        Operator::Type => match rule.value.as_str() {
+            Some(name) if TYPE_NAMES.contains(&name) => Ok(()),
+            _ => Err(format!("expected one of {} as value", TYPE_NAMES.join(", "))),
+        },
+        Operator::In | Operator::NotIn => match &rule.value {
+            Value::Array(items) if items.iter().all(|item| operand_text(item).is_some()) => Ok(()),
+            _ => Err("expected a list of strings or numbers as value".to_string()),
+        },
+        Operator::Matches => match &rule.value {
+            Value::String(pattern) => cached_regex(pattern)
+                .map(|_| ())
+                .map_err(|e| format!("invalid regular expression: {}", e)),
+            _ => Err("expected a regular expression string as value".to_string()),
+        },
+        Operator::Gt | Operator::Gte | Operator::Lt | Operator::Lte => match operand_text(&rule.value) {
+            Some(value) if value.trim().parse::<f64>().is_ok() || datetime::parse_timestamp(&value).is_some() => Ok(()),
+            _ => Err("expected a number or an ISO 8601 date as value".to_string()),
+        },
+        _ => match operand_text(&rule.value) {
+            Some(_) => Ok(()),
+            None => Err("expected a string or number as value".to_string()),
+        },
+    }
+}
+
+/// Validates the JSONPath and the rule of a body rule. Unlike query rules,
+/// "is", "is!", "contains" and "contains!" may compare against any JSON value.
+pub fn validate_body_rule(rule: &BodyRule) -> Result<(), String> {
+    JsonPath::parse(&rule.path)?;
+    match rule.rule.operator {
+        Operator::Is | Operator::IsNot | Operator::Contains | Operator::NotContains => Ok(()),
+        _ => validate_rule(&rule.rule),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(operator: Operator, value: Value) -> QueryParam {
+        QueryParam { operator, value, ignore_case: false }
+    }
+
+    #[test]
+    fn test_check_rule_string_operators() {
+        assert!(check_rule(&rule(Operator::StartsWith, json!("ab")), Some("abc")));
+        assert!(check_rule(&rule(Operator::EndsWith, json!("bc")), Some("abc")));
+        assert!(!check_rule(&rule(Operator::Is, json!("ABC")), Some("abc")));
+        let mut ignore_case = rule(Operator::Is, json!("ABC"));
+        ignore_case.ignore_case = true;
+        assert!(check_rule(&ignore_case, Some("abc")));
+    }
+
+    #[test]
+    fn test_check_rule_regex() {
+        assert!(check_rule(&rule(Operator::Matches, json!(r"^\d{4}$")), Some("2024")));
+        assert!(!check_rule(&rule(Operator::Matches, json!(r"^\d{4}$")), Some("24")));
+        let mut ignore_case = rule(Operator::Matches, json!(r"^\D+$"));
+        ignore_case.ignore_case = true;
+        assert!(check_rule(&ignore_case, Some("ABC")));
+    }
+
+    #[test]
+    fn test_check_rule_comparisons() {
+        assert!(check_rule(&rule(Operator::Gt, json!(10)), Some("10.5")));
+        assert!(!check_rule(&rule(Operator::Gt, json!(10)), Some("9")));
+        assert!(check_rule(&rule(Operator::Lte, json!("2024-03-01")), Some("2024-02-29")));
+        assert!(check_rule(&rule(Operator::Gte, json!("2024-03-01")), Some("2024-03-01T08:00:00Z")));
+        assert!(!check_rule(&rule(Operator::Lt, json!(10)), Some("abc")));
+    }
+
+    #[test]
+    fn test_check_rule_presence_and_lists() {
+        assert!(check_rule(&rule(Operator::Exists, Value::Null), Some("")));
+        assert!(!check_rule(&rule(Operator::Exists, Value::Null), None));
+        assert!(check_rule(&rule(Operator::Absent, Value::Null), None));
+        assert!(!check_rule(&rule(Operator::Is, json!("x")), None));
+        assert!(check_rule(&rule(Operator::In, json!(["a", 1])), Some("1")));
+        assert!(check_rule(&rule(Operator::NotIn, json!(["a", "b"])), Some("c")));
+    }
+
+    #[test]
+    fn test_validate_rule() {
+        assert!(validate_rule(&rule(Operator::Is, json!("x"))).is_ok());
+        assert!(validate_rule(&rule(Operator::Is, Value::Null)).is_err());
+        assert!(validate_rule(&rule(Operator::In, json!("x"))).is_err());
+        assert!(validate_rule(&rule(Operator::Matches, json!("("))).is_err());
+        assert!(validate_rule(&rule(Operator::Gt, json!("soon"))).is_err());
+        assert!(validate_rule(&rule(Operator::Absent, Value::Null)).is_ok());
+        assert!(validate_rule(&rule(Operator::Is, json!(["a", 2]))).is_ok());
+        assert!(validate_rule(&rule(Operator::Is, json!(["a", {}]))).is_err());
+    }
+
+    #[test]
+    fn test_check_value_list() {
+        let values = vec!["a".to_string(), "B".to_string()];
+        assert!(check_value_list(&rule(Operator::Is, json!(["a", "B"])), Some(&values)));
+        assert!(!check_value_list(&rule(Operator::Is, json!(["B", "a"])), Some(&values)));
+        assert!(!check_value_list(&rule(Operator::Is, json!(["a"])), Some(&values)));
+        assert!(!check_value_list(&rule(Operator::Is, json!(["a", "B"])), None));
+        let mut ignore_case = rule(Operator::Is, json!(["A", "b"]));
+        ignore_case.ignore_case = true;
+        assert!(check_value_list(&ignore_case, Some(&values)));
+    }
+
+    #[test]
+    fn test_check_body_rule() {
+        let body = json!({
+            "user": {"address": {"zip": "10115"}, "age": 42},
+            "tags": ["new", "vip"],
+            "items": [{"qty": 1}, {"qty": 5}]
+        });
+        let body_rule = |path: &str, operator: Operator, value: Value| BodyRule { path: path.to_string(), rule: rule(operator, value) };
+        assert!(check_body_rule(&body_rule("$.user.address.zip", Operator::Matches, json!(r"^\d{5}$")), &body));
+        assert!(check_body_rule(&body_rule("$.user.age", Operator::Is, json!(42.0)), &body));
+        assert!(check_body_rule(&body_rule("$.user.age", Operator::Gte, json!(18)), &body));
+        assert!(check_body_rule(&body_rule("$.user.age", Operator::Type, json!("integer")), &body));
+        assert!(!check_body_rule(&body_rule("$.user", Operator::Type, json!("array")), &body));
+        assert!(check_body_rule(&body_rule("$.tags", Operator::Contains, json!("vip")), &body));
+        assert!(!check_body_rule(&body_rule("$.tags", Operator::Contains, json!("vi")), &body));
+        assert!(check_body_rule(&body_rule("$.items[*].qty", Operator::Gt, json!(3)), &body));
+        assert!(check_body_rule(&body_rule("$.user.email", Operator::Absent, Value::Null), &body));
+        assert!(!check_body_rule(&body_rule("$.user.email", Operator::Is, json!("x")), &body));
+    }
+
+    #[test]
+    fn test_json_contains() {
+        let actual = json!({"a": 1, "b": {"c": [1, 2, {"d": true, "e": 0}]}, "f": "x"});
+        assert!(json_contains(&actual, &json!({"b": {"c": [{"d": true}, 2]}})));
+        assert!(json_contains(&actual, &json!({"a": 1.0})));
+        assert!(!json_contains(&actual, &json!({"b": {"c": [3]}})));
+        assert!(!json_contains(&actual, &json!({"g": null})));
+    }
+
+    #[test]
+    fn test_validate_body_rule() {
+        let body_rule = |path: &str, operator: Operator, value: Value| BodyRule { path: path.to_string(), rule: rule(operator, value) };
+        assert!(validate_body_rule(&body_rule("$.tags", Operator::Contains, json!({"id": 1}))).is_ok());
+        assert!(validate_body_rule(&body_rule("tags", Operator::Exists, Value::Null)).is_err());
+        assert!(validate_body_rule(&body_rule("$.age", Operator::Type, json!("date"))).is_err());
+    }
+
+    #[test]
+    fn test_unknown_operator_is_rejected() {
+        let parsed: Result<QueryParam, _> = serde_json::from_str(r#"{"operator": "like", "value": "x"}"#);
+        assert!(parsed.is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+/// One part of a `multipart/form-data` body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    /// The form field name from `Content-Disposition`.
+    pub name: String,
+    /// The uploaded file name; `None` for plain fields.
+    pub filename: Option<String>,
+    /// The part's own `Content-Type`, if any.
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Returns the media type of a `Content-Type` header in lowercase, without
+/// parameters, e.g. "multipart/form-data" for `multipart/form-data; boundary=x`.
+pub fn media_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// Tells whether a media type is text that "text" rules apply to: `text/*`
+/// and JSON, e.g. "application/json" or "application/problem+json".
+pub fn is_text_media_type(media_type: &str) -> bool {
+    media_type.starts_with("text/") || media_type == "application/json" || media_type.ends_with("+json")
+}
+
+/// Returns a parameter of a header value such as `boundary` in
+/// `multipart/form-data; boundary=x` or `name` in `form-data; name="a"`.
+fn header_param(value: &str, param: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case(param) {
+            return None;
+        }
+        let value = value.trim();
+        let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+        Some(value.to_string())
+    })
+}
+
+/// Finds the first occurrence of `needle` in `haystack` at or after `from`.
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| position + from)
+}
+
+/// Parses a `multipart/form-data` body (RFC 7578) with the boundary from its
+/// `Content-Type` header.
+pub fn parse_multipart(content_type: &str, body: &[u8]) -> Result<Vec<Part>, String> {
+    let boundary = header_param(content_type, "boundary").ok_or("missing multipart boundary")?;
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut position = find(body, &delimiter, 0).ok_or("missing first multipart boundary")? + delimiter.len();
+    let mut parts = Vec::new();
+    loop {
+        // "--" after a boundary ends the body; otherwise a line break starts the part headers.
+        if body[position..].starts_with(b"--") {
+            return Ok(parts);
+        }
+        position = find(body, b"\r\n", position).ok_or("truncated multipart boundary line")? + 2;
+        let headers_end = find(body, b"\r\n\r\n", position).ok_or("truncated multipart headers")?;
+        let headers = String::from_utf8_lossy(&body[position..headers_end]);
+        let data_start = headers_end + 4;
+        let mut closing = b"\r\n".to_vec();
+        closing.extend_from_slice(&delimiter);
+        let data_end = find(body, &closing, data_start).ok_or("missing closing multipart boundary")?;
+
+        let mut part = Part { name: String::new(), filename: None, content_type: None, data: body[data_start..data_end].to_vec() };
+        for line in headers.split("\r\n") {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            if key.trim().eq_ignore_ascii_case("Content-Disposition") {
+                part.name = header_param(value, "name").unwrap_or_default();
+                part.filename = header_param(value, "filename");
+            } else if key.trim().eq_ignore_ascii_case("Content-Type") {
+                part.content_type = Some(value.trim().to_string());
+            }
+        }
+        parts.push(part);
+        position = data_end + closing.len();
+    }
+}
+
+/// Returns the plain (non-file) fields of a multipart body, with every value
+/// of repeated fields in order.
+pub fn multipart_fields(parts: &[Part]) -> HashMap<String, Vec<String>> {
+    let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+    for part in parts.iter().filter(|part| part.filename.is_none()) {
+        fields.entry(part.name.clone()).or_default().push(String::from_utf8_lossy(&part.data).into_owned());
+    }
+    fields
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multipart() {
+        let body = b"preamble\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Hello\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"avatar\"; filename=\"me.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\
+            \x89PNG\r\n\x00\x01\r\n--XyZ--\r\n";
+        let parts = parse_multipart("multipart/form-data; boundary=\"XyZ\"", body).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "title");
+        assert_eq!(parts[0].data, b"Hello");
+        assert_eq!(parts[1].filename.as_deref(), Some("me.png"));
+        assert_eq!(parts[1].content_type.as_deref(), Some("image/png"));
+        assert_eq!(parts[1].data, b"\x89PNG\r\n\x00\x01");
+        assert_eq!(multipart_fields(&parts).get("title").unwrap(), &vec!["Hello".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_multipart_errors() {
+        assert!(parse_multipart("multipart/form-data", b"").is_err());
+        assert!(parse_multipart("multipart/form-data; boundary=b", b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nx").is_err());
+    }
+
+    #[test]
+    fn test_media_type() {
+        assert_eq!(media_type("Multipart/Form-Data; boundary=x"), "multipart/form-data");
+        assert_eq!(media_type("text/plain"), "text/plain");
+    }
+}
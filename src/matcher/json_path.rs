@@ -0,0 +1,160 @@
+use serde_json::Value;
+
+/// One step of a JSONPath expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    /// `.name` or `['name']`
+    Child(String),
+    /// `[0]`, or `[-1]` for the last item
+    Index(i64),
+    /// `.*` or `[*]`: every member of an object or item of an array
+    Wildcard,
+    /// `..name`: every member called `name` at any depth
+    Descendant(String),
+}
+
+/// A parsed JSONPath expression. Supports the subset `$`, `.name`,
+/// `['name']`, `[n]`, `[*]`, `.*` and `..name`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<JsonPath, String> {
+        let rest = path
+            .trim()
+            .strip_prefix('$')
+            .ok_or_else(|| format!("JSONPath \"{}\" must start with \"$\"", path))?;
+        let chars: Vec<char> = rest.chars().collect();
+        let mut steps = Vec::new();
+        let mut i = 0;
+        let name_end = |from: usize| {
+            (from..chars.len())
+                .find(|&j| chars[j] == '.' || chars[j] == '[')
+                .unwrap_or(chars.len())
+        };
+        while i < chars.len() {
+            match chars[i] {
+                '.' if chars.get(i + 1) == Some(&'.') => {
+                    let end = name_end(i + 2);
+                    let name: String = chars[i + 2..end].iter().collect();
+                    if name.is_empty() {
+                        return Err(format!("JSONPath \"{}\" has an empty member name after \"..\"", path));
+                    }
+                    steps.push(Step::Descendant(name));
+                    i = end;
+                }
+                '.' => {
+                    let end = name_end(i + 1);
+                    let name: String = chars[i + 1..end].iter().collect();
+                    match name.as_str() {
+                        "" => return Err(format!("JSONPath \"{}\" has an empty member name", path)),
+                        "*" => steps.push(Step::Wildcard),
+                        _ => steps.push(Step::Child(name)),
+                    }
+                    i = end;
+                }
+                '[' => {
+                    let end = (i..chars.len())
+                        .find(|&j| chars[j] == ']')
+                        .ok_or_else(|| format!("JSONPath \"{}\" has an unclosed \"[\"", path))?;
+                    let inner: String = chars[i + 1..end].iter().collect();
+                    let inner = inner.trim();
+                    let quoted = inner
+                        .strip_prefix('\'').and_then(|s| s.strip_suffix('\''))
+                        .or_else(|| inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')));
+                    if let Some(name) = quoted {
+                        steps.push(Step::Child(name.to_string()));
+                    } else if inner == "*" {
+                        steps.push(Step::Wildcard);
+                    } else {
+                        let index = inner
+                            .parse()
+                            .map_err(|_| format!("JSONPath \"{}\" has an invalid index \"{}\"", path, inner))?;
+                        steps.push(Step::Index(index));
+                    }
+                    i = end + 1;
+                }
+                c => return Err(format!("JSONPath \"{}\" has an unexpected character '{}'", path, c)),
+            }
+        }
+        Ok(JsonPath { steps })
+    }
+
+    /// Returns every value the path selects in a document.
+    pub fn select<'a>(&self, document: &'a Value) -> Vec<&'a Value> {
+        let mut current = vec![document];
+        for step in &self.steps {
+            let mut next = Vec::new();
+            for value in current {
+                match step {
+                    Step::Child(name) => next.extend(value.get(name)),
+                    Step::Index(index) => {
+                        if let Value::Array(items) = value {
+                            let index = if *index < 0 { items.len() as i64 + index } else { *index };
+                            next.extend(usize::try_from(index).ok().and_then(|index| items.get(index)));
+                        }
+                    }
+                    Step::Wildcard => match value {
+                        Value::Array(items) => next.extend(items.iter()),
+                        Value::Object(members) => next.extend(members.values()),
+                        _ => {}
+                    },
+                    Step::Descendant(name) => collect_descendants(value, name, &mut next),
+                }
+            }
+            current = next;
+        }
+        current
+    }
+}
+
+/// Collects the members called `name` of a value and of all its descendants.
+fn collect_descendants<'a>(value: &'a Value, name: &str, found: &mut Vec<&'a Value>) {
+    match value {
+        Value::Object(members) => {
+            found.extend(members.get(name));
+            for member in members.values() {
+                collect_descendants(member, name, found);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_descendants(item, name, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_select() {
+        let document = json!({
+            "user": {"address": {"zip": "10115"}, "tags": ["a", "b"]},
+            "items": [{"sku": "x", "qty": 1}, {"sku": "y", "qty": 2}]
+        });
+        let select = |path: &str| JsonPath::parse(path).unwrap().select(&document).into_iter().cloned().collect::<Vec<_>>();
+        assert_eq!(select("$.user.address.zip"), vec![json!("10115")]);
+        assert_eq!(select("$['user']['tags'][1]"), vec![json!("b")]);
+        assert_eq!(select("$.items[-1].sku"), vec![json!("y")]);
+        assert_eq!(select("$.items[*].qty"), vec![json!(1), json!(2)]);
+        assert_eq!(select("$..sku"), vec![json!("x"), json!("y")]);
+        assert_eq!(select("$"), vec![document.clone()]);
+        assert!(select("$.user.missing").is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(JsonPath::parse("user.name").is_err());
+        assert!(JsonPath::parse("$.items[abc]").is_err());
+        assert!(JsonPath::parse("$.items[0").is_err());
+        assert!(JsonPath::parse("$.").is_err());
+    }
+}
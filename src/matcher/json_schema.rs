@@ -0,0 +1,423 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{cached_regex, json_equals, json_type, TYPE_NAMES};
+use crate::datetime;
+use crate::schema::escape_pointer;
+
+/// Maximum nesting of `$ref` resolutions, so that a recursive schema cannot loop forever.
+const MAX_DEPTH: usize = 64;
+
+/// A validation error: where in the instance it occurred, as a JSON pointer, and why.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+}
+
+/// Validates a JSON value against a JSON Schema and returns every error found.
+///
+/// Supports a subset of draft 2020-12: boolean schemas, `type`, `enum`, `const`,
+/// `properties`, `required`, `additionalProperties`, `patternProperties`,
+/// `minProperties`, `maxProperties`, `items`, `prefixItems`, `contains`,
+/// `minItems`, `maxItems`, `uniqueItems`, `minLength`, `maxLength`, `pattern`,
+/// `format` (`date`, `date-time`, `email`, `uuid`, `ipv4`), `minimum`,
+/// `maximum`, `exclusiveMinimum`, `exclusiveMaximum`, `multipleOf`, `allOf`,
+/// `anyOf`, `oneOf`, `not` and local `$ref`s such as `#/$defs/address`.
+/// Other keywords are ignored.
+pub fn validate(schema: &Value, instance: &Value) -> Vec<SchemaError> {
+    let mut errors = Vec::new();
+    Validator { root: schema }.check(schema, instance, "", 0, &mut errors);
+    errors
+}
+
+/// Checks that a schema only uses supported keywords correctly, so that a
+/// broken schema is reported when the configuration is saved.
+pub fn check_schema(schema: &Value) -> Result<(), String> {
+    check_schema_at(schema, schema, "")
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        name => json_type(value) == name,
+    }
+}
+
+fn check_format(format: &str, value: &str) -> bool {
+    match format {
+        "date" => value.len() == 10 && datetime::parse_timestamp(value).is_some(),
+        "date-time" => value.len() > 10 && datetime::parse_timestamp(value).is_some(),
+        "email" => value
+            .split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.') && !domain.contains('@')),
+        "uuid" => {
+            let groups: Vec<&str> = value.split('-').collect();
+            groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+                && groups.iter().all(|group| group.bytes().all(|b| b.is_ascii_hexdigit()))
+        }
+        "ipv4" => {
+            let parts: Vec<&str> = value.split('.').collect();
+            parts.len() == 4
+                && parts.iter().all(|part| {
+                    !part.is_empty() && part.len() <= 3 && part.bytes().all(|b| b.is_ascii_digit())
+                        && part.parse::<u8>().is_ok() && (part.len() == 1 || !part.starts_with('0'))
+                })
+        }
+        // Unknown formats are annotations only.
+        _ => true,
+    }
+}
+
+/// Resolves a local reference such as `#/$defs/address` against the root schema.
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    root.pointer(pointer)
+}
+
+impl<'a> Validator<'a> {
+    fn check(&self, schema: &'a Value, instance: &Value, path: &str, depth: usize, errors: &mut Vec<SchemaError>) {
+        let mut error = |message: String| errors.push(SchemaError { path: path.to_string(), message });
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return error("no value is allowed here".to_string()),
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match resolve(self.root, reference) {
+                Some(_) if depth >= MAX_DEPTH => error(format!("\"$ref\" nesting exceeds {} levels", MAX_DEPTH)),
+                Some(target) => self.check(target, instance, path, depth + 1, errors),
+                None => error(format!("unresolvable \"$ref\" {}", reference)),
+            }
+        }
+        self.check_type(schema, instance, path, errors);
+        self.check_number(schema, instance, path, errors);
+        self.check_string(schema, instance, path, errors);
+        self.check_array(schema, instance, path, depth, errors);
+        self.check_object(schema, instance, path, depth, errors);
+        self.check_combinators(schema, instance, path, depth, errors);
+    }
+
+    fn check_type(&self, schema: &Map<String, Value>, instance: &Value, path: &str, errors: &mut Vec<SchemaError>) {
+        let mut error = |message: String| errors.push(SchemaError { path: path.to_string(), message });
+        match schema.get("type") {
+            Some(Value::String(name)) if !has_type(instance, name) => {
+                error(format!("expected {}, got {}", name, json_type(instance)))
+            }
+            Some(Value::Array(names)) if !names.iter().filter_map(Value::as_str).any(|name| has_type(instance, name)) => {
+                let names: Vec<&str> = names.iter().filter_map(Value::as_str).collect();
+                error(format!("expected one of {}, got {}", names.join(", "), json_type(instance)))
+            }
+            _ => {}
+        }
+        if let Some(Value::Array(values)) = schema.get("enum") {
+            if !values.iter().any(|value| json_equals(value, instance)) {
+                error(format!("value must be one of {}", Value::Array(values.clone())))
+            }
+        }
+        if let Some(value) = schema.get("const") {
+            if !json_equals(value, instance) {
+                error(format!("value must be {}", value))
+            }
+        }
+    }
+
+    fn check_number(&self, schema: &Map<String, Value>, instance: &Value, path: &str, errors: &mut Vec<SchemaError>) {
+        let Some(number) = instance.as_f64() else {
+            return;
+        };
+        let mut error = |message: String| errors.push(SchemaError { path: path.to_string(), message });
+        let limit = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+        if let Some(minimum) = limit("minimum").filter(|&minimum| number < minimum) {
+            error(format!("must be at least {}", minimum));
+        }
+        if let Some(maximum) = limit("maximum").filter(|&maximum| number > maximum) {
+            error(format!("must be at most {}", maximum));
+        }
+        if let Some(minimum) = limit("exclusiveMinimum").filter(|&minimum| number <= minimum) {
+            error(format!("must be greater than {}", minimum));
+        }
+        if let Some(maximum) = limit("exclusiveMaximum").filter(|&maximum| number >= maximum) {
+            error(format!("must be less than {}", maximum));
+        }
+        if let Some(divisor) = limit("multipleOf").filter(|&divisor| divisor > 0.0) {
+            let quotient = number / divisor;
+            if (quotient - quotient.round()).abs() > 1e-9 {
+                error(format!("must be a multiple of {}", divisor));
+            }
+        }
+    }
+
+    fn check_string(&self, schema: &Map<String, Value>, instance: &Value, path: &str, errors: &mut Vec<SchemaError>) {
+        let Some(string) = instance.as_str() else {
+            return;
+        };
+        let mut error = |message: String| errors.push(SchemaError { path: path.to_string(), message });
+        let length = string.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64).filter(|&min| length < min) {
+            error(format!("must be at least {} characters long", min));
+        }
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64).filter(|&max| length > max) {
+            error(format!("must be at most {} characters long", max));
+        }
+        if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+            if !cached_regex(pattern).is_ok_and(|regex| regex.is_match(string)) {
+                error(format!("must match the pattern {}", pattern));
+            }
+        }
+        if let Some(format) = schema.get("format").and_then(Value::as_str) {
+            if !check_format(format, string) {
+                error(format!("must be a valid {}", format));
+            }
+        }
+    }
+
+    fn check_array(&self, schema: &'a Map<String, Value>, instance: &Value, path: &str, depth: usize, errors: &mut Vec<SchemaError>) {
+        let Some(items) = instance.as_array() else {
+            return;
+        };
+        let count = items.len() as u64;
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64).filter(|&min| count < min) {
+            errors.push(SchemaError { path: path.to_string(), message: format!("must have at least {} items", min) });
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64).filter(|&max| count > max) {
+            errors.push(SchemaError { path: path.to_string(), message: format!("must have at most {} items", max) });
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            let duplicate = (0..items.len()).any(|i| items[i + 1..].iter().any(|other| json_equals(&items[i], other)));
+            if duplicate {
+                errors.push(SchemaError { path: path.to_string(), message: "items must be unique".to_string() });
+            }
+        }
+        let prefix = schema.get("prefixItems").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+        for (index, item) in items.iter().enumerate() {
+            let item_schema = match prefix.get(index) {
+                Some(item_schema) => Some(item_schema),
+                None => schema.get("items"),
+            };
+            if let Some(item_schema) = item_schema {
+                self.check(item_schema, item, &format!("{}/{}", path, index), depth, errors);
+            }
+        }
+        if let Some(contains) = schema.get("contains") {
+            if !items.iter().any(|item| self.is_valid(contains, item, depth)) {
+                errors.push(SchemaError { path: path.to_string(), message: "must contain a matching item".to_string() });
+            }
+        }
+    }
+
+    fn check_object(&self, schema: &'a Map<String, Value>, instance: &Value, path: &str, depth: usize, errors: &mut Vec<SchemaError>) {
+        let Some(members) = instance.as_object() else {
+            return;
+        };
+        let count = members.len() as u64;
+        if let Some(min) = schema.get("minProperties").and_then(Value::as_u64).filter(|&min| count < min) {
+            errors.push(SchemaError { path: path.to_string(), message: format!("must have at least {} properties", min) });
+        }
+        if let Some(max) = schema.get("maxProperties").and_then(Value::as_u64).filter(|&max| count > max) {
+            errors.push(SchemaError { path: path.to_string(), message: format!("must have at most {} properties", max) });
+        }
+        for name in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+            if !members.contains_key(name) {
+                errors.push(SchemaError {
+                    path: format!("{}/{}", path, escape_pointer(name)),
+                    message: "is required".to_string(),
+                });
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let pattern_properties = schema.get("patternProperties").and_then(Value::as_object);
+        for (name, value) in members {
+            let member_path = format!("{}/{}", path, escape_pointer(name));
+            let mut described = false;
+            if let Some(property) = properties.and_then(|properties| properties.get(name)) {
+                described = true;
+                self.check(property, value, &member_path, depth, errors);
+            }
+            for (pattern, property) in pattern_properties.into_iter().flatten() {
+                if cached_regex(pattern).is_ok_and(|regex| regex.is_match(name)) {
+                    described = true;
+                    self.check(property, value, &member_path, depth, errors);
+                }
+            }
+            if !described {
+                match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => errors.push(SchemaError {
+                        path: member_path,
+                        message: "is not an allowed property".to_string(),
+                    }),
+                    Some(additional) => self.check(additional, value, &member_path, depth, errors),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    fn check_combinators(&self, schema: &'a Map<String, Value>, instance: &Value, path: &str, depth: usize, errors: &mut Vec<SchemaError>) {
+        for subschema in schema.get("allOf").and_then(Value::as_array).into_iter().flatten() {
+            self.check(subschema, instance, path, depth, errors);
+        }
+        if let Some(subschemas) = schema.get("anyOf").and_then(Value::as_array) {
+            if !subschemas.iter().any(|subschema| self.is_valid(subschema, instance, depth)) {
+                errors.push(SchemaError { path: path.to_string(), message: "must match at least one schema of anyOf".to_string() });
+            }
+        }
+        if let Some(subschemas) = schema.get("oneOf").and_then(Value::as_array) {
+            let matching = subschemas.iter().filter(|subschema| self.is_valid(subschema, instance, depth)).count();
+            if matching != 1 {
+                errors.push(SchemaError {
+                    path: path.to_string(),
+                    message: format!("must match exactly one schema of oneOf, matched {}", matching),
+                });
+            }
+        }
+        if let Some(subschema) = schema.get("not") {
+            if self.is_valid(subschema, instance, depth) {
+                errors.push(SchemaError { path: path.to_string(), message: "must not match the schema of not".to_string() });
+            }
+        }
+    }
+
+    fn is_valid(&self, schema: &'a Value, instance: &Value, depth: usize) -> bool {
+        let mut errors = Vec::new();
+        self.check(schema, instance, "", depth, &mut errors);
+        errors.is_empty()
+    }
+}
+
+fn check_schema_at(root: &Value, schema: &Value, location: &str) -> Result<(), String> {
+    let schema = match schema {
+        Value::Bool(_) => return Ok(()),
+        Value::Object(schema) => schema,
+        _ => return Err(format!("{}: a schema must be an object or a boolean", location)),
+    };
+    let invalid = |keyword: &str, expected: &str| Err(format!("{}: \"{}\" must be {}", location, keyword, expected));
+    for (keyword, value) in schema {
+        let at = format!("{}/{}", location, escape_pointer(keyword));
+        match keyword.as_str() {
+            "type" => {
+                let names: Vec<&Value> = match value {
+                    Value::Array(names) => names.iter().collect(),
+                    name => vec![name],
+                };
+                if !names.iter().all(|name| name.as_str().is_some_and(|name| TYPE_NAMES.contains(&name))) {
+                    return invalid(keyword, &format!("one or a list of {}", TYPE_NAMES.join(", ")));
+                }
+            }
+            "enum" if !value.is_array() => return invalid(keyword, "an array"),
+            "required" if !value.as_array().is_some_and(|names| names.iter().all(Value::is_string)) => {
+                return invalid(keyword, "an array of strings")
+            }
+            "minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum" if !value.is_number() => {
+                return invalid(keyword, "a number")
+            }
+            "multipleOf" if !value.as_f64().is_some_and(|n| n > 0.0) => return invalid(keyword, "a positive number"),
+            "minLength" | "maxLength" | "minItems" | "maxItems" | "minProperties" | "maxProperties" if !value.is_u64() => {
+                return invalid(keyword, "a non-negative integer")
+            }
+            "uniqueItems" if !value.is_boolean() => return invalid(keyword, "a boolean"),
+            "format" if !value.is_string() => return invalid(keyword, "a string"),
+            "pattern" => match value.as_str() {
+                Some(pattern) => {
+                    cached_regex(pattern).map_err(|e| format!("{}: invalid regular expression: {}", location, e))?;
+                }
+                None => return invalid(keyword, "a regular expression string"),
+            },
+            "$ref" => match value.as_str() {
+                Some(reference) if resolve(root, reference).is_some() => {}
+                Some(reference) => return Err(format!("{}: unresolvable \"$ref\" {}", location, reference)),
+                None => return invalid(keyword, "a string"),
+            },
+            "items" | "additionalProperties" | "contains" | "not" => check_schema_at(root, value, &at)?,
+            "properties" | "patternProperties" | "$defs" => match value.as_object() {
+                Some(members) => {
+                    for (name, subschema) in members {
+                        if keyword == "patternProperties" {
+                            cached_regex(name).map_err(|e| format!("{}: invalid regular expression: {}", at, e))?;
+                        }
+                        check_schema_at(root, subschema, &format!("{}/{}", at, escape_pointer(name)))?;
+                    }
+                }
+                None => return invalid(keyword, "an object of schemas"),
+            },
+            "prefixItems" | "allOf" | "anyOf" | "oneOf" => match value.as_array() {
+                Some(subschemas) if !subschemas.is_empty() => {
+                    for (index, subschema) in subschemas.iter().enumerate() {
+                        check_schema_at(root, subschema, &format!("{}/{}", at, index))?;
+                    }
+                }
+                _ => return invalid(keyword, "a non-empty array of schemas"),
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn paths(errors: &[SchemaError]) -> Vec<&str> {
+        errors.iter().map(|error| error.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_validate_object() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "age"],
+            "properties": {
+                "name": {"type": "string", "minLength": 1},
+                "age": {"type": "integer", "minimum": 0},
+                "email": {"type": "string", "format": "email"},
+                "tags": {"type": "array", "items": {"type": "string"}, "uniqueItems": true}
+            },
+            "additionalProperties": false
+        });
+        assert!(validate(&schema, &json!({"name": "Ann", "age": 30, "tags": ["a", "b"]})).is_empty());
+
+        let errors = validate(&schema, &json!({"name": "", "age": -1.5, "email": "nope", "tags": ["a", 1, "a"], "x": 1}));
+        assert_eq!(
+            paths(&errors),
+            vec!["/age", "/age", "/email", "/name", "/tags", "/tags/1", "/x"]
+        );
+        assert_eq!(paths(&validate(&schema, &json!({"name": "Ann"}))), vec!["/age"]);
+        assert_eq!(validate(&schema, &json!([]))[0].message, "expected object, got array");
+    }
+
+    #[test]
+    fn test_validate_combinators_and_refs() {
+        let schema = json!({
+            "$defs": {"id": {"oneOf": [{"type": "integer"}, {"type": "string", "format": "uuid"}]}},
+            "type": "object",
+            "properties": {
+                "id": {"$ref": "#/$defs/id"},
+                "kind": {"enum": ["a", "b"]},
+                "point": {"type": "array", "prefixItems": [{"type": "number"}, {"type": "number"}], "maxItems": 2},
+                "note": {"anyOf": [{"type": "null"}, {"type": "string", "maxLength": 3}]}
+            }
+        });
+        let valid = json!({"id": "123e4567-e89b-12d3-a456-426614174000", "kind": "a", "point": [1, 2.5], "note": null});
+        assert!(validate(&schema, &valid).is_empty());
+        let invalid = json!({"id": "x", "kind": "c", "point": [1, "2", 3], "note": "long"});
+        assert_eq!(paths(&validate(&schema, &invalid)), vec!["/id", "/kind", "/note", "/point", "/point/1"]);
+    }
+
+    #[test]
+    fn test_check_schema() {
+        assert!(check_schema(&json!({"type": "object", "properties": {"a": {"$ref": "#/$defs/a"}}, "$defs": {"a": true}})).is_ok());
+        assert!(check_schema(&json!({"type": "text"})).is_err());
+        assert!(check_schema(&json!({"properties": {"a": {"pattern": "("}}})).is_err());
+        assert!(check_schema(&json!({"$ref": "#/$defs/missing"})).is_err());
+        assert!(check_schema(&json!({"minLength": -1})).is_err());
+        assert!(check_schema(&json!("string")).is_err());
+    }
+}
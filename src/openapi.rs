@@ -0,0 +1,711 @@
+//! Import of OpenAPI 3.0/3.1 documents: each path becomes an endpoint and
+//! each operation a condition answering with the operation's success
+//! response. The response body is the example the document gives, or else
+//! a value generated from the response schema.
+//!
+//! Export works the other way round: the conditions of each endpoint become
+//! the operations of an OpenAPI 3.1 document, with their parameters from the
+//! matching rules and their responses, examples and inferred schemas from
+//! the configured responses.
+
+use crate::schema::{Endpoint, HeaderRule, Operator, ProjectConfig, QueryParam, ResponseConfig, WhenCondition};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Operations of a path item, in the order their conditions are listed.
+const METHODS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+/// How deep schemas are followed when generating a value, so that
+/// recursive schemas such as trees still give a finite value.
+const MAX_DEPTH: usize = 8;
+
+/// Converts an OpenAPI document, in JSON or YAML, to a project configuration.
+pub fn import(document: &str) -> Result<ProjectConfig, String> {
+    let document: Value = match serde_json::from_str(document) {
+        Ok(document) => document,
+        Err(_) => {
+            let document = serde_yaml::from_str(document).map_err(|e| format!("Invalid OpenAPI document: {}", e))?;
+            yaml_to_json(document)
+        }
+    };
+    if !document.get("openapi").and_then(Value::as_str).is_some_and(|version| version.starts_with("3.")) {
+        return Err("Invalid OpenAPI document: expected an OpenAPI 3 document with \"openapi\": \"3.x\"".to_string());
+    }
+
+    let base_path = base_path(&document);
+    let mut endpoints = HashMap::new();
+    for (path, item) in document.get("paths").and_then(Value::as_object).into_iter().flatten() {
+        let item = resolve(&document, item);
+        let conditions = METHODS
+            .iter()
+            .filter_map(|method| Some((method, item.get(*method)?)))
+            .map(|(method, operation)| condition(&document, method, operation))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("/paths/{}: {}", path, e))?;
+        if !conditions.is_empty() {
+            let endpoint = Endpoint { conditions, fallback: None };
+            endpoints.insert(format!("{}{}", base_path, path), endpoint);
+        }
+    }
+
+    let description = document.pointer("/info/title").and_then(Value::as_str).unwrap_or("Imported from OpenAPI");
+    let mut config = ProjectConfig {
+        description: description.to_string(),
+        endpoints,
+        seed: None,
+        resources: HashMap::new(),
+        proxy: None,
+        fallback: None,
+        routes: Vec::new(),
+    };
+    config.validate()?;
+    config.build_routes();
+    Ok(config)
+}
+
+/// Converts a YAML value to JSON. Unlike JSON, YAML allows keys that are
+/// not strings, such as the unquoted status codes of `responses`.
+fn yaml_to_json(value: serde_yaml::Value) -> Value {
+    match value {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(value) => Value::Bool(value),
+        serde_yaml::Value::Number(number) => match (number.as_i64(), number.as_u64(), number.as_f64()) {
+            (Some(value), _, _) => json!(value),
+            (_, Some(value), _) => json!(value),
+            (_, _, value) => json!(value),
+        },
+        serde_yaml::Value::String(value) => Value::String(value),
+        serde_yaml::Value::Sequence(values) => Value::Array(values.into_iter().map(yaml_to_json).collect()),
+        serde_yaml::Value::Mapping(mapping) => Value::Object(
+            mapping
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match yaml_to_json(key) {
+                        Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    (key, yaml_to_json(value))
+                })
+                .collect(),
+        ),
+        serde_yaml::Value::Tagged(tagged) => yaml_to_json(tagged.value),
+    }
+}
+
+/// Returns the path of the first server URL, e.g. "/v1" for
+/// "https://api.example.com/v1", with its variables set to their defaults.
+fn base_path(document: &Value) -> String {
+    let Some(server) = document.pointer("/servers/0") else {
+        return String::new();
+    };
+    let mut url = server.get("url").and_then(Value::as_str).unwrap_or_default().to_string();
+    for (name, variable) in server.get("variables").and_then(Value::as_object).into_iter().flatten() {
+        if let Some(default) = variable.get("default").and_then(Value::as_str) {
+            url = url.replace(&format!("{{{}}}", name), default);
+        }
+    }
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+        None => url.as_str(),
+    };
+    path.trim_end_matches('/').to_string()
+}
+
+/// Follows local references such as "#/components/schemas/User".
+fn resolve<'a>(document: &'a Value, mut value: &'a Value) -> &'a Value {
+    for _ in 0..MAX_DEPTH {
+        let Some(target) = value.get("$ref").and_then(Value::as_str) else {
+            break;
+        };
+        match target.strip_prefix('#').and_then(|pointer| document.pointer(pointer)) {
+            Some(target) => value = target,
+            None => break,
+        }
+    }
+    value
+}
+
+/// Builds the condition of an operation, which answers with its success
+/// response: the lowest 2xx status, else "default", else the first one.
+fn condition(document: &Value, method: &str, operation: &Value) -> Result<WhenCondition, String> {
+    let responses = operation.get("responses").and_then(Value::as_object);
+    let mut codes: Vec<&String> = responses.into_iter().flat_map(Map::keys).collect();
+    codes.sort();
+    let code = codes.iter().find(|code| code.starts_with('2'))
+        .or_else(|| codes.iter().find(|code| code.as_str() == "default"))
+        .or_else(|| codes.first());
+    let status = match code {
+        Some(code) => code.replace(['X', 'x'], "0").parse::<u16>().unwrap_or(200),
+        None => 200,
+    };
+
+    let mut headers = Map::new();
+    let mut body = None;
+    if let Some(response) = code.and_then(|code| responses?.get(code.as_str())) {
+        let response = resolve(document, response);
+        for (name, header) in response.get("headers").and_then(Value::as_object).into_iter().flatten() {
+            let header = resolve(document, header);
+            let value = header.get("example").cloned()
+                .unwrap_or_else(|| header.get("schema").map_or(Value::Null, |schema| example(document, schema, 0)));
+            let value = match value {
+                Value::String(value) => value,
+                Value::Null => continue,
+                value => value.to_string(),
+            };
+            headers.insert(name.clone(), Value::String(value));
+        }
+        if let Some((media_type, media)) = media_type(response) {
+            headers.insert("Content-Type".to_string(), Value::String(media_type.clone()));
+            body = Some(media_example(document, media));
+        }
+    }
+
+    let condition = json!({
+        "method": method.to_uppercase(),
+        "request": {},
+        "response": {"status": status, "headers": headers, "body": body},
+    });
+    serde_json::from_value(condition).map_err(|e| e.to_string())
+}
+
+/// Picks the media type of a response: JSON if offered, else the first one.
+fn media_type(response: &Value) -> Option<(&String, &Value)> {
+    let content = response.get("content")?.as_object()?;
+    content.iter().find(|(media_type, _)| media_type.as_str() == "application/json")
+        .or_else(|| content.iter().find(|(media_type, _)| media_type.contains("json")))
+        .or_else(|| content.iter().next())
+}
+
+/// Returns the example of a media type, from `example`, the first of
+/// `examples` or its schema.
+fn media_example(document: &Value, media: &Value) -> Value {
+    if let Some(example) = media.get("example") {
+        return example.clone();
+    }
+    let first = media.get("examples").and_then(Value::as_object).and_then(|examples| examples.values().next());
+    if let Some(value) = first.and_then(|example| resolve(document, example).get("value")) {
+        return value.clone();
+    }
+    media.get("schema").map_or(Value::Null, |schema| example(document, schema, 0))
+}
+
+/// Generates a value that satisfies a schema, from its examples and
+/// defaults where given.
+fn example(document: &Value, schema: &Value, depth: usize) -> Value {
+    if depth > MAX_DEPTH {
+        return Value::Null;
+    }
+    let schema = resolve(document, schema);
+    for keyword in ["example", "default", "const"] {
+        if let Some(value) = schema.get(keyword) {
+            return value.clone();
+        }
+    }
+    for keyword in ["examples", "enum", "oneOf", "anyOf"] {
+        if let Some(first) = schema.get(keyword).and_then(Value::as_array).and_then(|values| values.first()) {
+            return match keyword {
+                "examples" | "enum" => first.clone(),
+                _ => example(document, first, depth + 1),
+            };
+        }
+    }
+    if let Some(schemas) = schema.get("allOf").and_then(Value::as_array) {
+        let mut merged = Map::new();
+        for schema in schemas {
+            match example(document, schema, depth + 1) {
+                Value::Object(fields) => merged.extend(fields),
+                value => return value,
+            }
+        }
+        return Value::Object(merged);
+    }
+
+    // OpenAPI 3.1 types can be a list, e.g. ["string", "null"].
+    let schema_type = match schema.get("type") {
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).find(|t| *t != "null"),
+        Some(schema_type) => schema_type.as_str(),
+        None if schema.get("properties").is_some() => Some("object"),
+        None if schema.get("items").is_some() => Some("array"),
+        None => None,
+    };
+    match schema_type {
+        Some("object") => {
+            let properties = schema.get("properties").and_then(Value::as_object).into_iter().flatten();
+            Value::Object(properties.map(|(name, property)| (name.clone(), example(document, property, depth + 1))).collect())
+        }
+        Some("array") => match schema.get("items") {
+            Some(items) => json!([example(document, items, depth + 1)]),
+            None => json!([]),
+        },
+        Some("string") => Value::String(string_example(schema.get("format").and_then(Value::as_str)).to_string()),
+        Some("integer") => json!(schema.get("minimum").and_then(Value::as_i64).unwrap_or(0)),
+        Some("number") => json!(schema.get("minimum").and_then(Value::as_f64).unwrap_or(0.0)),
+        Some("boolean") => Value::Bool(true),
+        _ => Value::Null,
+    }
+}
+
+/// Returns a string in the given format.
+fn string_example(format: Option<&str>) -> &'static str {
+    match format {
+        Some("date-time") => "2024-01-01T00:00:00Z",
+        Some("date") => "2024-01-01",
+        Some("time") => "00:00:00",
+        Some("email") => "user@example.com",
+        Some("uuid") => "3fa85f64-5717-4562-b3fc-2c963f66afa6",
+        Some("uri") | Some("url") => "https://example.com",
+        Some("hostname") => "example.com",
+        Some("ipv4") => "192.0.2.1",
+        Some("ipv6") => "2001:db8::1",
+        Some("byte") => "c3RyaW5n",
+        _ => "string",
+    }
+}
+
+/// Request headers that OpenAPI describes elsewhere than in the parameters.
+const UNDOCUMENTED_HEADERS: [&str; 3] = ["accept", "content-type", "authorization"];
+
+/// Converts a project configuration to an OpenAPI 3.1 document.
+pub fn export(config: &ProjectConfig) -> Value {
+    let mut paths = Map::new();
+    for (key, endpoint) in &config.endpoints {
+        let (path, path_params) = openapi_path(key);
+        let mut methods: Vec<String> = Vec::new();
+        for condition in &endpoint.conditions {
+            let method = condition.method.to_lowercase();
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        }
+        let item = paths.entry(path).or_insert_with(|| json!({}));
+        for method in methods {
+            let conditions: Vec<&WhenCondition> = endpoint.conditions
+                .iter()
+                .filter(|condition| condition.method.eq_ignore_ascii_case(&method))
+                .collect();
+            item[method.as_str()] = operation(&path_params, &conditions);
+        }
+    }
+    json!({
+        "openapi": "3.1.0",
+        "info": {"title": config.description, "version": "1.0.0"},
+        "paths": paths,
+    })
+}
+
+/// Converts an endpoint key to an OpenAPI path, e.g. "/users/:id" and
+/// "/files/*rest" to "/users/{id}" and "/files/{rest}", and returns it with
+/// the names of its path params.
+fn openapi_path(key: &str) -> (String, Vec<String>) {
+    let mut params = Vec::new();
+    let segments: Vec<String> = key
+        .split('/')
+        .map(|segment| {
+            let name = segment.strip_prefix(':')
+                .or_else(|| segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')))
+                .or_else(|| segment.strip_prefix('*').map(|name| if name.is_empty() { "path" } else { name }));
+            match name {
+                Some(name) => {
+                    params.push(name.to_string());
+                    format!("{{{}}}", name)
+                }
+                None => segment.to_string(),
+            }
+        })
+        .collect();
+    (segments.join("/"), params)
+}
+
+/// Describes the conditions of one method of an endpoint as an operation.
+fn operation(path_params: &[String], conditions: &[&WhenCondition]) -> Value {
+    let mut parameters = Vec::new();
+    for name in path_params {
+        let rules: Vec<&QueryParam> = conditions.iter()
+            .filter_map(|condition| condition.request.params.as_ref()?.get(name))
+            .collect();
+        let mut parameter = parameter(name, "path", &rules);
+        parameter["required"] = Value::Bool(true);
+        parameters.push(parameter);
+    }
+
+    // A query or header is required if every condition asks for it.
+    let mut queries: BTreeMap<&String, Vec<&QueryParam>> = BTreeMap::new();
+    let mut headers: BTreeMap<String, Vec<QueryParam>> = BTreeMap::new();
+    for condition in conditions {
+        for (name, rule) in condition.request.queries.iter().flatten() {
+            queries.entry(name).or_default().push(rule);
+        }
+        for (name, rule) in condition.request.headers.iter().flatten() {
+            if UNDOCUMENTED_HEADERS.iter().any(|header| header.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            let rule = match rule {
+                HeaderRule::Exact(value) => QueryParam { operator: Operator::Is, value: Value::String(value.clone()), ignore_case: false },
+                HeaderRule::Rule(rule) => rule.clone(),
+            };
+            headers.entry(name.clone()).or_default().push(rule);
+        }
+    }
+    let headers = headers.iter().map(|(name, rules)| (name, "header", rules.iter().collect::<Vec<_>>()));
+    for (name, location, rules) in queries.iter().map(|(name, rules)| (*name, "query", rules.clone())).chain(headers) {
+        if rules.iter().all(|rule| rule.operator == Operator::Absent) {
+            continue;
+        }
+        let mut parameter = parameter(name, location, &rules);
+        let required = rules.len() == conditions.len() && rules.iter().all(|rule| rule.operator != Operator::Absent);
+        parameter["required"] = Value::Bool(required);
+        parameters.push(parameter);
+    }
+
+    let mut operation = Map::new();
+    if !parameters.is_empty() {
+        operation.insert("parameters".to_string(), Value::Array(parameters));
+    }
+    if let Some(request_body) = request_body(conditions) {
+        operation.insert("requestBody".to_string(), request_body);
+    }
+
+    let mut responses: BTreeMap<u16, Vec<&ResponseConfig>> = BTreeMap::new();
+    for condition in conditions {
+        for (_, response) in condition.responses() {
+            responses.entry(response.status).or_default().push(response);
+        }
+    }
+    let responses: Map<String, Value> = responses
+        .into_iter()
+        .map(|(status, responses)| (status.to_string(), response(status, &responses)))
+        .collect();
+    operation.insert("responses".to_string(), Value::Object(responses));
+    Value::Object(operation)
+}
+
+/// Describes a query, header or path param from its matching rules.
+fn parameter(name: &str, location: &str, rules: &[&QueryParam]) -> Value {
+    let mut schema = json!({"type": "string"});
+    let mut example = None;
+    if let Some(rule) = rules.first() {
+        match (rule.operator, &rule.value) {
+            (Operator::Is, value) => example = Some(value.clone()),
+            (Operator::In, Value::Array(values)) => {
+                schema["enum"] = Value::Array(values.clone());
+                example = values.first().cloned();
+            }
+            (Operator::Matches, Value::String(pattern)) => schema["pattern"] = Value::String(pattern.clone()),
+            (Operator::StartsWith, Value::String(prefix)) => schema["pattern"] = Value::String(format!("^{}", regex::escape(prefix))),
+            (Operator::EndsWith, Value::String(suffix)) => schema["pattern"] = Value::String(format!("{}$", regex::escape(suffix))),
+            (Operator::Type, Value::String(value_type)) => schema["type"] = Value::String(value_type.clone()),
+            (Operator::Gt | Operator::Gte | Operator::Lt | Operator::Lte, Value::Number(bound)) => {
+                let keyword = match rule.operator {
+                    Operator::Gt => "exclusiveMinimum",
+                    Operator::Gte => "minimum",
+                    Operator::Lt => "exclusiveMaximum",
+                    _ => "maximum",
+                };
+                schema = json!({"type": "number", keyword: bound});
+            }
+            _ => {}
+        }
+    }
+    let mut parameter = json!({"name": name, "in": location, "schema": schema});
+    if let Some(example) = example {
+        parameter["example"] = example;
+    }
+    parameter
+}
+
+/// Describes the request bodies of the conditions: their JSON Schema if
+/// they have one, else examples of the expected bodies or form fields.
+fn request_body(conditions: &[&WhenCondition]) -> Option<Value> {
+    if let Some(schema) = conditions.iter().find_map(|condition| condition.request.schema.as_ref()) {
+        return Some(json!({"content": {"application/json": {"schema": schema}}}));
+    }
+    let bodies: Vec<Value> = conditions.iter().filter_map(|condition| condition.request.body.clone()).collect();
+    if !bodies.is_empty() {
+        return Some(json!({"content": {"application/json": media(&bodies)}}));
+    }
+    let form = conditions.iter().find_map(|condition| condition.request.form.as_ref())?;
+    let properties: Map<String, Value> = form.keys().map(|name| (name.clone(), json!({"type": "string"}))).collect();
+    let schema = json!({"type": "object", "properties": properties});
+    Some(json!({"content": {"application/x-www-form-urlencoded": {"schema": schema}}}))
+}
+
+/// Describes the responses of one status code, with their bodies as
+/// examples and the headers of the first one.
+fn response(status: u16, responses: &[&ResponseConfig]) -> Value {
+    let description = match status {
+        100..=199 => "Informational response",
+        200..=299 => "Successful response",
+        300..=399 => "Redirection",
+        400..=499 => "Client error",
+        _ => "Server error",
+    };
+    let mut response = json!({"description": description});
+
+    let content_type = |response: &ResponseConfig| response.headers.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .map(|(_, value)| value.clone());
+    let headers: Map<String, Value> = responses[0].headers
+        .iter()
+        .filter(|(name, _)| !name.eq_ignore_ascii_case("content-type") && !name.eq_ignore_ascii_case("content-length"))
+        .map(|(name, value)| (name.clone(), json!({"schema": {"type": "string"}, "example": value})))
+        .collect();
+    if !headers.is_empty() {
+        response["headers"] = Value::Object(headers);
+    }
+
+    let bodies: Vec<Value> = responses.iter().filter_map(|response| response.body.clone()).collect();
+    if let Some(first) = bodies.first() {
+        let media_type = responses.iter().find_map(|response| content_type(response)).unwrap_or_else(|| {
+            match first {
+                Value::String(_) => "text/plain".to_string(),
+                _ => "application/json".to_string(),
+            }
+        });
+        response["content"] = json!({media_type: media(&bodies)});
+    }
+    response
+}
+
+/// Describes a media type by examples of its values and the schema inferred
+/// from the first one.
+fn media(examples: &[Value]) -> Value {
+    let mut distinct: Vec<&Value> = Vec::new();
+    for example in examples {
+        if !distinct.contains(&example) {
+            distinct.push(example);
+        }
+    }
+    let mut media = json!({"schema": infer_schema(distinct[0])});
+    if let [example] = distinct.as_slice() {
+        media["example"] = (*example).clone();
+    } else {
+        let examples: Map<String, Value> = distinct
+            .iter()
+            .enumerate()
+            .map(|(i, example)| (format!("example{}", i + 1), json!({"value": example})))
+            .collect();
+        media["examples"] = Value::Object(examples);
+    }
+    media
+}
+
+/// Infers a JSON Schema from a value: its type, and for objects the
+/// properties, all of which are required.
+fn infer_schema(value: &Value) -> Value {
+    match value {
+        Value::Null => json!({"type": "null"}),
+        Value::Bool(_) => json!({"type": "boolean"}),
+        Value::Number(number) if number.is_f64() => json!({"type": "number"}),
+        Value::Number(_) => json!({"type": "integer"}),
+        Value::String(_) => json!({"type": "string"}),
+        Value::Array(items) => match items.first() {
+            Some(item) => json!({"type": "array", "items": infer_schema(item)}),
+            None => json!({"type": "array"}),
+        },
+        Value::Object(fields) => {
+            let properties: Map<String, Value> = fields.iter().map(|(name, value)| (name.clone(), infer_schema(value))).collect();
+            let required: Vec<&String> = fields.keys().collect();
+            json!({"type": "object", "properties": properties, "required": required})
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PETSTORE: &str = r##"
+openapi: 3.0.3
+info:
+  title: Petstore
+  version: 1.0.0
+servers:
+  - url: https://petstore.example.com/{version}
+    variables:
+      version:
+        default: v1
+paths:
+  /pets:
+    get:
+      responses:
+        "200":
+          description: A list of pets
+          headers:
+            X-Total-Count:
+              schema:
+                type: integer
+          content:
+            application/json:
+              schema:
+                type: array
+                items:
+                  $ref: "#/components/schemas/Pet"
+    post:
+      responses:
+        201:
+          description: Created
+          content:
+            application/json:
+              examples:
+                rex:
+                  value: {id: 7, name: Rex}
+        "400":
+          description: Bad request
+  /pets/{petId}:
+    get:
+      responses:
+        default:
+          $ref: "#/components/responses/Pet"
+    delete:
+      responses:
+        "204":
+          description: Deleted
+components:
+  responses:
+    Pet:
+      description: A pet
+      content:
+        application/json:
+          example: {id: 1, name: Tom}
+  schemas:
+    Pet:
+      type: object
+      properties:
+        id: {type: integer, format: int64, minimum: 1}
+        name: {type: string}
+        tag: {type: [string, "null"], enum: [cat, dog]}
+        born: {type: string, format: date}
+        owner: {$ref: "#/components/schemas/Owner"}
+    Owner:
+      allOf:
+        - properties: {email: {type: string, format: email}}
+        - properties: {pets: {type: array, items: {$ref: "#/components/schemas/Pet"}}}
+"##;
+
+    fn response<'a>(config: &'a ProjectConfig, path: &str, method: &str) -> &'a crate::schema::ResponseConfig {
+        let endpoint = &config.endpoints[path];
+        let condition = endpoint.conditions.iter().find(|c| c.method == method).unwrap();
+        condition.response.as_ref().unwrap()
+    }
+
+    #[test]
+    fn test_import_yaml() {
+        let config = import(PETSTORE).unwrap();
+        assert_eq!(config.description, "Petstore");
+        let mut paths: Vec<&String> = config.endpoints.keys().collect();
+        paths.sort();
+        assert_eq!(paths, ["/v1/pets", "/v1/pets/{petId}"]);
+        assert_eq!(config.find_endpoint("/v1/pets/7").unwrap().2["petId"], "7");
+
+        let list = response(&config, "/v1/pets", "GET");
+        assert_eq!(list.status, 200);
+        assert_eq!(list.headers["Content-Type"], "application/json");
+        assert_eq!(list.headers["X-Total-Count"], "0");
+        let pet = &list.body.as_ref().unwrap()[0];
+        assert_eq!(pet["id"], json!(1));
+        assert_eq!(pet["name"], json!("string"));
+        assert_eq!(pet["tag"], json!("cat"));
+        assert_eq!(pet["born"], json!("2024-01-01"));
+        assert_eq!(pet["owner"]["email"], json!("user@example.com"));
+        assert!(pet["owner"]["pets"].is_array());
+
+        let created = response(&config, "/v1/pets", "POST");
+        assert_eq!(created.status, 201);
+        assert_eq!(created.body, Some(json!({"id": 7, "name": "Rex"})));
+
+        let found = response(&config, "/v1/pets/{petId}", "GET");
+        assert_eq!(found.status, 200);
+        assert_eq!(found.body, Some(json!({"id": 1, "name": "Tom"})));
+
+        let deleted = response(&config, "/v1/pets/{petId}", "DELETE");
+        assert_eq!(deleted.status, 204);
+        assert_eq!(deleted.body, None);
+        assert!(deleted.headers.is_empty());
+    }
+
+    #[test]
+    fn test_import_json() {
+        let document = json!({
+            "openapi": "3.1.0",
+            "info": {"title": "Status", "version": "1"},
+            "paths": {"/status": {"get": {"responses": {"2XX": {
+                "description": "ok",
+                "content": {"text/plain": {"schema": {"type": "string", "examples": ["up"]}}}
+            }}}}}
+        });
+        let config = import(&document.to_string()).unwrap();
+        let status = response(&config, "/status", "GET");
+        assert_eq!(status.status, 200);
+        assert_eq!(status.headers["Content-Type"], "text/plain");
+        assert_eq!(status.body, Some(json!("up")));
+    }
+
+    #[test]
+    fn test_import_rejects_other_documents() {
+        assert!(import("swagger: \"2.0\"\npaths: {}").unwrap_err().contains("OpenAPI 3"));
+        assert!(import("{\"openapi\": ").is_err());
+        assert!(import("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_export() {
+        let config: ProjectConfig = serde_json::from_value(json!({
+            "description": "Shop",
+            "endpoints": {
+                "/orders/:id": {"when": [
+                    {
+                        "method": "GET",
+                        "request": {
+                            "queries": {"expand": {"operator": "in", "value": ["items", "customer"]}},
+                            "headers": {"X-Tenant": "acme", "Accept": "application/json"}
+                        },
+                        "response": {"status": 200, "headers": {"X-Request-Id": "1"}, "body": {"id": 1, "total": 9.5, "items": [{"sku": "a"}]}}
+                    },
+                    {
+                        "method": "GET",
+                        "request": {"headers": {"X-Tenant": "acme"}},
+                        "sequence": {"responses": [
+                            {"status": 200, "headers": {}, "body": {"id": 2, "total": 3.0, "items": []}},
+                            {"status": 404, "headers": {}, "body": "Not found"}
+                        ]}
+                    },
+                    {
+                        "method": "PUT",
+                        "request": {"body": {"total": 10}},
+                        "response": {"status": 204, "headers": {}}
+                    }
+                ]}
+            }
+        })).unwrap();
+        let document = export(&config);
+        assert_eq!(document["openapi"], "3.1.0");
+        assert_eq!(document["info"]["title"], "Shop");
+
+        let get = &document["paths"]["/orders/{id}"]["get"];
+        let parameters = get["parameters"].as_array().unwrap();
+        assert_eq!(parameters.len(), 3);
+        assert_eq!(parameters[0], json!({"name": "id", "in": "path", "required": true, "schema": {"type": "string"}}));
+        assert_eq!(parameters[1]["name"], "expand");
+        assert_eq!(parameters[1]["required"], false);
+        assert_eq!(parameters[1]["schema"]["enum"], json!(["items", "customer"]));
+        assert_eq!(parameters[2], json!({"name": "X-Tenant", "in": "header", "required": true, "schema": {"type": "string"}, "example": "acme"}));
+
+        let ok = &get["responses"]["200"];
+        assert_eq!(ok["headers"]["X-Request-Id"]["example"], "1");
+        let media = &ok["content"]["application/json"];
+        assert_eq!(media["examples"]["example2"]["value"]["id"], 2);
+        assert_eq!(media["schema"]["properties"]["total"], json!({"type": "number"}));
+        assert_eq!(media["schema"]["properties"]["items"]["items"]["properties"]["sku"], json!({"type": "string"}));
+        assert_eq!(get["responses"]["404"]["content"]["text/plain"]["example"], "Not found");
+
+        let put = &document["paths"]["/orders/{id}"]["put"];
+        assert_eq!(put["requestBody"]["content"]["application/json"]["example"], json!({"total": 10}));
+        assert_eq!(put["responses"]["204"], json!({"description": "Successful response"}));
+
+        // The exported document imports back to the same paths and responses.
+        let imported = import(&document.to_string()).unwrap();
+        assert_eq!(response(&imported, "/orders/{id}", "GET").body, Some(json!({"id": 1, "total": 9.5, "items": [{"sku": "a"}]})));
+        assert_eq!(response(&imported, "/orders/{id}", "PUT").status, 204);
+    }
+}
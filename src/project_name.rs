@@ -0,0 +1,57 @@
+//! Names of projects, which are also the names of their config files.
+
+use std::fmt;
+use std::ops::Deref;
+
+/// Longest project name accepted.
+pub const MAX_LENGTH: usize = 100;
+
+/// A project name that is safe to use as a file name: 1 to 100 ASCII
+/// letters, digits, '-', '_' and '.', not starting with '.'. It can thus
+/// never name a hidden file, a parent folder or a file in another folder.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProjectName(String);
+
+impl ProjectName {
+    /// Checks a project name, e.g. the `:name` of a route, once decoded.
+    pub fn parse(name: &str) -> Result<ProjectName, String> {
+        let allowed = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+        if name.is_empty() || name.len() > MAX_LENGTH || name.starts_with('.') || !name.chars().all(allowed) {
+            return Err(format!(
+                "Invalid project name: use 1 to {} letters, digits, '-', '_' and '.', not starting with '.'.",
+                MAX_LENGTH
+            ));
+        }
+        Ok(ProjectName(name.to_string()))
+    }
+}
+
+impl Deref for ProjectName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ProjectName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for name in ["sales", "my.project-1", "a_b", "v1.2.3", &"x".repeat(MAX_LENGTH)] {
+            assert_eq!(&*ProjectName::parse(name).unwrap(), name);
+        }
+        for name in ["", ".", "..", ".hidden", "../secret", "a/b", "a\\b", "a%2Fb", "a b", "café", "a\0", &"x".repeat(MAX_LENGTH + 1)] {
+            assert!(ProjectName::parse(name).unwrap_err().starts_with("Invalid project name"), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Proxy mode: requests that nothing in a project answers are forwarded to
+//! the project's upstream, and the exchanges can be recorded into the
+//! project as new conditions.
+
+use crate::{cache, matcher, project_name::ProjectName, schema::{Endpoint, ProjectConfig, ProxyConfig, WhenCondition}, store};
+use crate::web_server::{self, types::{Method, Request, Response}};
+use lazy_static::lazy_static;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long to wait for the upstream before answering 502.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Headers that belong to one connection and are not forwarded. Content
+/// encodings are not forwarded either, so that bodies stay readable.
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection", "keep-alive", "transfer-encoding", "te", "trailer", "upgrade",
+    "proxy-connection", "content-length", "accept-encoding",
+];
+
+/// Response headers not worth recording: they describe the upstream's
+/// answer at the time, not the mocked endpoint.
+const UNRECORDED_HEADERS: [&str; 3] = ["date", "server", "content-encoding"];
+
+lazy_static! {
+    /// Serializes the recordings, which rewrite the whole project config.
+    static ref RECORDING: Mutex<()> = Mutex::new(());
+    /// The client of every forwarded request, so that connections to the
+    /// upstreams are reused, and the runtime its requests run on. Handlers
+    /// are synchronous, so they block on this runtime.
+    static ref UPSTREAM: Result<(tokio::runtime::Runtime, reqwest::Client), String> = upstream_client();
+}
+
+fn upstream_client() -> Result<(tokio::runtime::Runtime, reqwest::Client), String> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .thread_name("proxy")
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(UPSTREAM_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok((runtime, client))
+}
+
+/// Forwards a request to the upstream, with the headers rewritten as the
+/// proxy configuration says. `path` is the still percent-encoded endpoint
+/// path, which is appended to the upstream URL with the query.
+pub fn forward(proxy_config: &ProxyConfig, path: &str, request: &Request) -> Result<Response, String> {
+    let query = request.raw_url.split_once('?').map(|(_, query)| format!("?{}", query)).unwrap_or_default();
+    let url = format!("{}{}{}", proxy_config.upstream.trim_end_matches('/'), path, query);
+    let method = reqwest::Method::from_bytes(request.method.to_string().as_bytes()).map_err(|e| e.to_string())?;
+
+    let (runtime, client) = UPSTREAM.as_ref().map_err(|e| e.clone())?;
+    let mut upstream_request = client.request(method, &url).body(request.raw_body.clone());
+    let rewritten = |name: &str| proxy_config.headers.keys().any(|header| header.eq_ignore_ascii_case(name));
+    for (name, value) in &request.headers {
+        if !is_hop_by_hop(name) && !name.eq_ignore_ascii_case("host") && !rewritten(name) {
+            upstream_request = upstream_request.header(name, value);
+        }
+    }
+    for (name, value) in &proxy_config.headers {
+        if let Some(value) = value {
+            upstream_request = upstream_request.header(name, value);
+        }
+    }
+
+    runtime.block_on(async {
+        let upstream_response = upstream_request.send().await.map_err(|e| e.to_string())?;
+        let status = upstream_response.status().as_u16();
+        let headers: HashMap<String, String> = upstream_response
+            .headers()
+            .iter()
+            .filter(|(name, _)| !is_hop_by_hop(name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = upstream_response.bytes().await.map_err(|e| e.to_string())?;
+        Ok(Response::new(status, String::from_utf8_lossy(&body).into_owned(), headers))
+    })
+}
+
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP_HEADERS.iter().any(|header| header.eq_ignore_ascii_case(name))
+}
+
+/// Builds a condition that matches the request and returns the response.
+/// The method, every value of the queries and the body are matched exactly,
+/// with `text/*` and JSON bodies compared as sent; returns None for bodies
+/// that no rule can match exactly, e.g. binary uploads, and for HEAD
+/// requests, whose responses have no body to record.
+pub fn recorded_condition(request: &Request, response: &Response) -> Option<WhenCondition> {
+    if request.method == Method::Head {
+        return None;
+    }
+    let mut matching = Map::new();
+    if !request.query_values.is_empty() {
+        let queries: Map<String, Value> = request.query_values
+            .iter()
+            .map(|(name, values)| {
+                let value = match values.as_slice() {
+                    [value] => json!(value),
+                    values => json!(values),
+                };
+                (name.clone(), json!({"operator": "is", "value": value}))
+            })
+            .collect();
+        matching.insert("queries".to_string(), Value::Object(queries));
+    }
+    if !request.body.is_empty() {
+        let content_type = web_server::header_value(&request.headers, "Content-Type").unwrap_or_default();
+        let media_type = matcher::media_type(content_type);
+        match (serde_json::from_str::<Value>(&request.body), media_type.as_str()) {
+            (_, media_type) if matcher::is_text_media_type(media_type) => {
+                matching.insert("text".to_string(), json!({"operator": "is", "value": request.body}));
+            }
+            (Ok(body), _) => {
+                matching.insert("body".to_string(), body);
+            }
+            (Err(_), "application/x-www-form-urlencoded") => {
+                let form: Map<String, Value> = web_server::extract_queries(&request.body)
+                    .into_iter()
+                    .filter_map(|(name, values)| Some((name, json!({"operator": "is", "value": values.first()?}))))
+                    .collect();
+                matching.insert("form".to_string(), Value::Object(form));
+            }
+            _ => return None,
+        }
+    }
+
+    let headers: HashMap<&String, &String> = response.headers
+        .iter()
+        .filter(|(name, _)| !UNRECORDED_HEADERS.iter().any(|header| header.eq_ignore_ascii_case(name)))
+        .collect();
+    // JSON bodies are stored as JSON, anything else verbatim as a string.
+    let body = serde_json::from_str::<Value>(&response.body).unwrap_or_else(|_| Value::String(response.body.clone()));
+    let condition = json!({
+        "method": request.method.to_string(),
+        "request": matching,
+        "response": {"status": response.status, "headers": headers, "body": body},
+    });
+    serde_json::from_value(condition).ok()
+}
+
+/// Appends a recorded condition to the endpoint with the exact path as its
+/// key, creating the endpoint if needed, and saves the project.
+pub fn record(project_name: &ProjectName, path: &str, condition: WhenCondition) -> Result<(), String> {
+    let _recording = RECORDING.lock().unwrap();
+    let mut config: ProjectConfig = (*cache::get_or_else_load_cached_config(project_name)?).clone();
+    let endpoint = config.endpoints.entry(path.to_string()).or_insert_with(|| Endpoint {
+        conditions: Vec::new(),
+        fallback: None,
+    });
+    endpoint.conditions.push(condition);
+    config.build_routes();
+
+    let content = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    store::store().save(project_name, &content)?;
+    cache::cache_config(project_name, config);
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! Small pseudo-random number generator for mock data, so that random
+//! values need no external crate. Not suitable for cryptography.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// SplitMix64 generator: fast, with a 64-bit state that any seed initializes well.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// Creates a generator seeded from the clock and a process-wide counter,
+    /// so that generators created at the same instant still differ.
+    pub fn from_entropy() -> Rng {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or_default();
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut rng = Rng::new(nanos ^ count.wrapping_mul(0xA076_1D64_78BD_642F));
+        rng.next_u64();
+        rng
+    }
+
+    /// Creates a generator from a seed and some data, so that the same seed
+    /// and data always give the same sequence (FNV-1a over the data).
+    pub fn derived(seed: u64, data: &[&str]) -> Rng {
+        let mut hash = 0xCBF2_9CE4_8422_2325u64 ^ seed;
+        for part in data {
+            for byte in part.bytes().chain([0xFF]) {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3);
+            }
+        }
+        Rng::new(hash)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal sample (mean 0, deviation 1), by the Box-Muller
+    /// transform; `1 - u` keeps the logarithm finite.
+    pub fn normal(&mut self) -> f64 {
+        let (u, v) = (1.0 - self.next_f64(), self.next_f64());
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    /// Uniform integer in `min..=max`; the bounds may come in either order.
+    pub fn range(&mut self, min: i64, max: i64) -> i64 {
+        let (min, max) = if min <= max { (min, max) } else { (max, min) };
+        let span = max.wrapping_sub(min) as u64;
+        if span == u64::MAX {
+            return self.next_u64() as i64;
+        }
+        // Multiply-shift maps the 64 random bits onto the span without a modulo bias worth noting.
+        let offset = ((self.next_u64() as u128 * (span as u128 + 1)) >> 64) as u64;
+        min.wrapping_add(offset as i64)
+    }
+
+    /// Random version 4 UUID, e.g. `9b2c1f2e-8d4a-4e1b-a3f0-5c6d7e8f9a0b`.
+    pub fn uuid(&mut self) -> String {
+        let (high, low) = (self.next_u64(), self.next_u64());
+        let high = (high & 0xFFFF_FFFF_FFFF_0FFF) | 0x0000_0000_0000_4000;
+        let low = (low & 0x3FFF_FFFF_FFFF_FFFF) | 0x8000_0000_0000_0000;
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            high >> 32, (high >> 16) & 0xFFFF, high & 0xFFFF, low >> 48, low & 0xFFFF_FFFF_FFFF
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_rng_is_deterministic() {
+        let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+        for _ in 0..10 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+        assert_eq!(Rng::derived(1, &["GET", "/a"]).next_u64(), Rng::derived(1, &["GET", "/a"]).next_u64());
+        assert_ne!(Rng::derived(1, &["GET", "/a"]).next_u64(), Rng::derived(1, &["GET", "/b"]).next_u64());
+    }
+
+    #[test]
+    fn test_range_and_float_bounds() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            assert!((1..=6).contains(&rng.range(1, 6)));
+            assert!((0.0..1.0).contains(&rng.next_f64()));
+        }
+        assert_eq!(rng.range(5, 5), 5);
+        assert!((-3..=3).contains(&rng.range(3, -3)));
+    }
+
+    #[test]
+    fn test_uuid_format() {
+        let uuid = Rng::new(1).uuid();
+        let groups: Vec<&str> = uuid.split('-').collect();
+        assert_eq!(groups.iter().map(|g| g.len()).collect::<Vec<_>>(), vec![8, 4, 4, 4, 12]);
+        assert!(groups[2].starts_with('4'));
+        assert!(matches!(&groups[3][..1], "8" | "9" | "a" | "b"));
+    }
+}
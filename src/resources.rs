@@ -0,0 +1,332 @@
+//! REST collections declared in the "resources" section of a project. Each
+//! collection is served from an in-memory store that starts with the seed
+//! data of the configuration and keeps every change until it is reset, the
+//! project is saved or the server restarts.
+
+use crate::random::Rng;
+use crate::schema::Resource;
+use crate::web_server::types::{Method, Request, Response};
+use lazy_static::lazy_static;
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Page size of `_page` without `_limit`.
+const DEFAULT_PAGE_SIZE: usize = 10;
+
+lazy_static! {
+    /// Project name -> collection key -> items.
+    static ref STORE: Mutex<HashMap<String, HashMap<String, Vec<Value>>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns every collection of a project to its seed data.
+pub fn reset_project(project: &str) {
+    STORE.lock().unwrap().remove(project);
+}
+
+/// Serves a request to a collection (`id` is None) or to one of its items.
+pub fn handle(project: &str, key: &str, resource: &Resource, id: Option<&str>, request: &Request) -> Response {
+    let mut store = STORE.lock().unwrap();
+    let items = store
+        .entry(project.to_string())
+        .or_default()
+        .entry(key.to_string())
+        .or_insert_with(|| resource.data.clone());
+    let id_field = resource.id_field.as_str();
+    match (id, &request.method) {
+        (None, Method::Get | Method::Head) => match list(items, &request.queries) {
+            Ok((total, page)) => {
+                let mut response = json_response(200, &Value::Array(page));
+                response.headers.insert("X-Total-Count".to_string(), total.to_string());
+                response
+            }
+            Err(e) => error_response(400, &e),
+        },
+        (None, Method::Post) => {
+            let mut item = match body_object(request) {
+                Ok(item) => item,
+                Err(e) => return error_response(400, &e),
+            };
+            match item.get(id_field) {
+                Some(id @ (Value::String(_) | Value::Number(_))) => {
+                    if position(items, id_field, &text(id)).is_some() {
+                        return error_response(409, &format!("An item with {} {} already exists.", id_field, text(id)));
+                    }
+                }
+                Some(_) => return error_response(400, &format!("\"{}\" must be a string or a number.", id_field)),
+                None => {
+                    item.insert(id_field.to_string(), next_id(items, id_field));
+                }
+            }
+            let item = Value::Object(item);
+            items.push(item.clone());
+            json_response(201, &item)
+        }
+        (Some(id), Method::Get | Method::Head) => match position(items, id_field, id) {
+            Some(index) => json_response(200, &items[index]),
+            None => not_found(),
+        },
+        (Some(id), Method::Put | Method::Patch) => {
+            let Some(index) = position(items, id_field, id) else {
+                return not_found();
+            };
+            let body = match body_object(request) {
+                Ok(body) => body,
+                Err(e) => return error_response(400, &e),
+            };
+            let current_id = items[index][id_field].clone();
+            let mut item = if request.method == Method::Put {
+                Value::Object(body)
+            } else {
+                let mut item = items[index].clone();
+                merge_patch(&mut item, &Value::Object(body));
+                item
+            };
+            // The id comes from the path; the body cannot change it.
+            item[id_field] = current_id;
+            items[index] = item.clone();
+            json_response(200, &item)
+        }
+        (Some(id), Method::Delete) => match position(items, id_field, id) {
+            Some(index) => {
+                items.remove(index);
+                Response::new(204, String::new(), HashMap::new())
+            }
+            None => not_found(),
+        },
+        (None, _) => method_not_allowed("GET, HEAD, POST, OPTIONS", &request.method),
+        (Some(_), _) => method_not_allowed("GET, HEAD, PUT, PATCH, DELETE, OPTIONS", &request.method),
+    }
+}
+
+/// Filters, sorts and paginates the items of a collection by the request
+/// queries, and returns the number of items that passed the filters with
+/// the requested page of them.
+///
+/// - `field=value` keeps items whose field equals the value; the suffixes
+///   `_ne`, `_gte`, `_lte` and `_like` (case-insensitive substring) compare
+///   differently, and nested fields are named with dots, e.g. `customer.name`.
+/// - `_sort=a,b` with `_order=asc,desc` sorts by one or more fields.
+/// - `_page` (from 1) and `_limit` select a page.
+pub fn list(items: &[Value], queries: &HashMap<String, String>) -> Result<(usize, Vec<Value>), String> {
+    let mut filters = Vec::new();
+    for (name, expected) in queries {
+        if name.starts_with('_') {
+            continue;
+        }
+        let (field, operator) = ["_ne", "_gte", "_lte", "_like"]
+            .iter()
+            .find_map(|suffix| Some((name.strip_suffix(suffix)?, *suffix)))
+            .unwrap_or((name.as_str(), ""));
+        filters.push((field, operator, expected.as_str()));
+    }
+    let mut selected: Vec<&Value> = items
+        .iter()
+        .filter(|item| filters.iter().all(|(field, operator, expected)| {
+            let actual = field_value(item, field);
+            match *operator {
+                "_ne" => actual.map(text) != Some(expected.to_string()),
+                "_gte" => actual.is_some_and(|actual| compare_text(actual, expected) != Ordering::Less),
+                "_lte" => actual.is_some_and(|actual| compare_text(actual, expected) != Ordering::Greater),
+                "_like" => actual.is_some_and(|actual| text(actual).to_lowercase().contains(&expected.to_lowercase())),
+                _ => actual.map(text) == Some(expected.to_string()),
+            }
+        }))
+        .collect();
+
+    if let Some(sort) = queries.get("_sort") {
+        let fields: Vec<&str> = sort.split(',').map(str::trim).collect();
+        let orders: Vec<&str> = queries.get("_order").map(|order| order.split(',').map(str::trim).collect()).unwrap_or_default();
+        if let Some(order) = orders.iter().find(|order| !matches!(**order, "asc" | "desc")) {
+            return Err(format!("_order: expected asc or desc, got \"{}\".", order));
+        }
+        selected.sort_by(|a, b| {
+            fields.iter().enumerate().fold(Ordering::Equal, |ordering, (i, field)| {
+                ordering.then_with(|| {
+                    compare_values(field_value(a, field), field_value(b, field), orders.get(i) == Some(&"desc"))
+                })
+            })
+        });
+    }
+
+    let total = selected.len();
+    let number = |name: &str| -> Result<Option<usize>, String> {
+        queries.get(name).map(|value| match value.parse::<usize>() {
+            Ok(number) if name != "_page" || number > 0 => Ok(number),
+            _ => Err(format!("{}: expected a positive integer, got \"{}\".", name, value)),
+        }).transpose()
+    };
+    let (page, limit) = (number("_page")?, number("_limit")?);
+    let start = match page {
+        Some(page) => (page - 1).saturating_mul(limit.unwrap_or(DEFAULT_PAGE_SIZE)),
+        None => 0,
+    };
+    let limit = limit.unwrap_or(if page.is_some() { DEFAULT_PAGE_SIZE } else { usize::MAX });
+    Ok((total, selected.into_iter().skip(start).take(limit).cloned().collect()))
+}
+
+/// Applies a JSON merge patch (RFC 7396): objects are merged recursively,
+/// null removes a field and any other value replaces it.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target = target.as_object_mut().unwrap();
+    for (name, value) in patch {
+        if value.is_null() {
+            target.remove(name);
+        } else {
+            merge_patch(target.entry(name.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Returns a field of an item, following dots into nested objects.
+fn field_value<'a>(item: &'a Value, field: &str) -> Option<&'a Value> {
+    field.split('.').try_fold(item, |value, name| value.get(name))
+}
+
+/// Returns a value as it is written in a query or a path, e.g. `7` or `Ann`,
+/// which is also how item ids are compared.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Compares a value with a query value, numerically if both are numbers.
+fn compare_text(actual: &Value, expected: &str) -> Ordering {
+    match (actual.as_f64(), expected.parse::<f64>()) {
+        (Some(actual), Ok(expected)) => actual.partial_cmp(&expected).unwrap_or(Ordering::Equal),
+        _ => text(actual).cmp(&expected.to_string()),
+    }
+}
+
+/// Orders two field values for sorting: numbers numerically, other values
+/// by their text, and missing fields last in either order.
+fn compare_values(a: Option<&Value>, b: Option<&Value>, descending: bool) -> Ordering {
+    let (a, b) = match (a, b) {
+        (None, None) => return Ordering::Equal,
+        (None, Some(_)) => return Ordering::Greater,
+        (Some(_), None) => return Ordering::Less,
+        (Some(a), Some(b)) => (a, b),
+    };
+    let ordering = match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => text(a).cmp(&text(b)),
+    };
+    if descending { ordering.reverse() } else { ordering }
+}
+
+/// Finds the index of the item with an id.
+fn position(items: &[Value], id_field: &str, id: &str) -> Option<usize> {
+    items.iter().position(|item| item.get(id_field).map(text).as_deref() == Some(id))
+}
+
+/// Returns the id of a new item: one more than the largest integer id, or
+/// a UUID if the collection uses other ids.
+fn next_id(items: &[Value], id_field: &str) -> Value {
+    let ids: Option<Vec<i64>> = items.iter().map(|item| item.get(id_field).and_then(Value::as_i64)).collect();
+    match ids {
+        Some(ids) => Value::from(ids.into_iter().max().unwrap_or(0) + 1),
+        None => Value::String(Rng::from_entropy().uuid()),
+    }
+}
+
+/// Parses the request body as a JSON object.
+fn body_object(request: &Request) -> Result<Map<String, Value>, String> {
+    match serde_json::from_str(&request.body) {
+        Ok(Value::Object(object)) => Ok(object),
+        _ => Err("Expected a JSON object in the request body.".to_string()),
+    }
+}
+
+fn json_response(status: u16, body: &Value) -> Response {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    Response::new(status, body.to_string(), headers)
+}
+
+fn error_response(status: u16, message: &str) -> Response {
+    json_response(status, &serde_json::json!({ "error": message }))
+}
+
+fn not_found() -> Response {
+    error_response(404, "Item does not exist.")
+}
+
+/// Answers OPTIONS with the allowed methods and any other unsupported method with 405.
+fn method_not_allowed(allowed: &str, method: &Method) -> Response {
+    let mut response = match method {
+        Method::Options => Response::new(204, String::new(), HashMap::new()),
+        _ => error_response(405, "Method not allowed."),
+    };
+    response.headers.insert("Allow".to_string(), allowed.to_string());
+    response
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn queries(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn ids(page: &[Value]) -> Vec<i64> {
+        page.iter().map(|item| item["id"].as_i64().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_list_filters_sorts_and_paginates() {
+        let items: Vec<Value> = serde_json::from_value(json!([
+            {"id": 1, "status": "open", "total": 30, "customer": {"name": "Ann"}},
+            {"id": 2, "status": "closed", "total": 5, "customer": {"name": "Bob"}},
+            {"id": 3, "status": "open", "total": 12, "customer": {"name": "Annie"}},
+            {"id": 4, "status": "open", "total": 12}
+        ])).unwrap();
+
+        let (total, page) = list(&items, &queries(&[("status", "open")])).unwrap();
+        assert_eq!((total, ids(&page)), (3, vec![1, 3, 4]));
+        let (_, page) = list(&items, &queries(&[("total_gte", "12"), ("status_ne", "closed")])).unwrap();
+        assert_eq!(ids(&page), vec![1, 3, 4]);
+        let (_, page) = list(&items, &queries(&[("customer.name_like", "ann")])).unwrap();
+        assert_eq!(ids(&page), vec![1, 3]);
+
+        let (_, page) = list(&items, &queries(&[("_sort", "total,id"), ("_order", "asc,desc")])).unwrap();
+        assert_eq!(ids(&page), vec![2, 4, 3, 1]);
+        let (_, page) = list(&items, &queries(&[("_sort", "customer.name"), ("_order", "desc")])).unwrap();
+        assert_eq!(ids(&page), vec![2, 3, 1, 4]);
+
+        let (total, page) = list(&items, &queries(&[("_page", "2"), ("_limit", "3")])).unwrap();
+        assert_eq!((total, ids(&page)), (4, vec![4]));
+        let (_, page) = list(&items, &queries(&[("_limit", "2")])).unwrap();
+        assert_eq!(ids(&page), vec![1, 2]);
+
+        assert!(list(&items, &queries(&[("_page", "0")])).is_err());
+        assert!(list(&items, &queries(&[("_sort", "id"), ("_order", "up")])).is_err());
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut item = json!({"id": 1, "name": "Ann", "address": {"city": "Oslo", "zip": "0150"}});
+        merge_patch(&mut item, &json!({"name": "Anna", "address": {"zip": null}, "tags": ["vip"]}));
+        assert_eq!(item, json!({"id": 1, "name": "Anna", "address": {"city": "Oslo"}, "tags": ["vip"]}));
+    }
+
+    #[test]
+    fn test_next_id() {
+        assert_eq!(next_id(&[], "id"), json!(1));
+        assert_eq!(next_id(&[json!({"id": 4}), json!({"id": 9})], "id"), json!(10));
+        assert_eq!(next_id(&[json!({"id": "a"})], "id").as_str().unwrap().len(), 36);
+    }
+}
@@ -0,0 +1,158 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// One segment of an endpoint key.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Matches the segment text exactly, e.g. `users`.
+    Literal(String),
+    /// Matches any single segment and captures it, e.g. `:id` or `{id}`.
+    Param(String),
+    /// Matches the rest of the path (one or more segments) and captures it
+    /// joined by slashes, e.g. `*rest`. A bare `*` is captured as `*`.
+    Wildcard(String),
+}
+
+impl Segment {
+    /// Ranks segments for route selection: literal beats param beats wildcard.
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Literal(_) => 2,
+            Segment::Param(_) => 1,
+            Segment::Wildcard(_) => 0,
+        }
+    }
+}
+
+/// A compiled endpoint key such as `/users/:id`, `/files/*rest` or
+/// `/orders/{orderId}/items`.
+#[derive(Debug, Clone)]
+pub struct RoutePattern {
+    /// The endpoint key as written in the project configuration.
+    pub key: String,
+    segments: Vec<Segment>,
+}
+
+/// Splits a path into its segments, ignoring leading, trailing and
+/// repeated slashes, so that `api/test`, `/api/test` and `/api/test/` agree.
+pub fn path_segments(path: &str) -> Vec<&str> {
+    path.split('/').filter(|segment| !segment.is_empty()).collect()
+}
+
+impl RoutePattern {
+    pub fn parse(key: &str) -> RoutePattern {
+        let segments = path_segments(key)
+            .into_iter()
+            .map(|segment| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = segment.strip_prefix('*') {
+                    let name = if name.is_empty() { "*" } else { name };
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Literal(segment.to_string())
+                }
+            })
+            .collect();
+        RoutePattern { key: key.to_string(), segments }
+    }
+
+    /// Matches a request path and returns the captured values.
+    pub fn match_path(&self, path: &str) -> Option<HashMap<String, String>> {
+        let path = path_segments(path);
+        let mut params = HashMap::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(literal) => {
+                    if path.get(i) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    params.insert(name.clone(), path.get(i)?.to_string());
+                }
+                Segment::Wildcard(name) => {
+                    if path.len() <= i {
+                        return None;
+                    }
+                    params.insert(name.clone(), path[i..].join("/"));
+                    return Some(params);
+                }
+            }
+        }
+        (path.len() == self.segments.len()).then_some(params)
+    }
+
+    /// Compares two keys that matched the same path; the greater one is more
+    /// specific. Segments are compared from left to right, so `/users/me`
+    /// beats `/users/:id`, which beats `/users/*rest`.
+    pub fn compare_specificity(&self, other: &RoutePattern) -> Ordering {
+        let ranks = |pattern: &RoutePattern| pattern.segments.iter().map(Segment::rank).collect::<Vec<_>>();
+        ranks(self)
+            .cmp(&ranks(other))
+            .then(self.segments.len().cmp(&other.segments.len()))
+    }
+}
+
+/// Picks the most specific key matching the path.
+/// Returns the matched key and the values it captured.
+pub fn find_route<'a>(
+    routes: &'a [RoutePattern],
+    path: &str,
+) -> Option<(&'a RoutePattern, HashMap<String, String>)> {
+    routes
+        .iter()
+        .filter_map(|route| route.match_path(path).map(|params| (route, params)))
+        .max_by(|(a, _), (b, _)| a.compare_specificity(b))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes(keys: &[&str]) -> Vec<RoutePattern> {
+        keys.iter().map(|key| RoutePattern::parse(key)).collect()
+    }
+
+    #[test]
+    fn test_match_path_params() {
+        let route = RoutePattern::parse("/orders/{orderId}/items/:itemId");
+        let params = route.match_path("/orders/7/items/42").unwrap();
+        assert_eq!(params.get("orderId").unwrap(), "7");
+        assert_eq!(params.get("itemId").unwrap(), "42");
+        assert!(route.match_path("/orders/7/items").is_none());
+        assert!(route.match_path("/orders/7/items/42/extra").is_none());
+    }
+
+    #[test]
+    fn test_match_path_wildcard() {
+        let route = RoutePattern::parse("/files/*rest");
+        let params = route.match_path("/files/a/b/c.txt").unwrap();
+        assert_eq!(params.get("rest").unwrap(), "a/b/c.txt");
+        assert!(route.match_path("/files").is_none());
+    }
+
+    #[test]
+    fn test_match_path_ignores_surrounding_slashes() {
+        let route = RoutePattern::parse("api/test");
+        assert!(route.match_path("/api/test/").is_some());
+    }
+
+    #[test]
+    fn test_find_route_prefers_specific_keys() {
+        let routes = routes(&["/users/*rest", "/users/:id", "/users/me"]);
+        assert_eq!(find_route(&routes, "/users/me").unwrap().0.key, "/users/me");
+        assert_eq!(find_route(&routes, "/users/1").unwrap().0.key, "/users/:id");
+        assert_eq!(find_route(&routes, "/users/1/posts").unwrap().0.key, "/users/*rest");
+        assert!(find_route(&routes, "/orders").is_none());
+    }
+
+    #[test]
+    fn test_find_route_compares_segments_left_to_right() {
+        let routes = routes(&["/:kind/latest", "/users/:id"]);
+        assert_eq!(find_route(&routes, "/users/latest").unwrap().0.key, "/users/:id");
+    }
+}
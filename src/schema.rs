@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use crate::matcher;
+use crate::template;
+use crate::routes::{self, RoutePattern};
+use crate::web_server::types::Method;
 
 /// Root configuration for a mock project
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -9,9 +11,84 @@ pub struct ProjectConfig {
     /// Project description/name
     pub description: String,
     /// Map of endpoint paths to their configurations
-    /// Key: endpoint path (e.g., "/api/test")
+    /// Key: endpoint path (e.g., "/api/test"), optionally templated with
+    /// path params (":id" or "{id}") and a trailing wildcard ("*rest")
     /// Value: endpoint configuration
     pub endpoints: HashMap<String, Endpoint>,
+    /// Optional seed of the random values in response templates, so that the
+    /// same request always gets the same fake data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Map of collection paths to REST resources served from an in-memory
+    /// store, for requests that no endpoint answers
+    /// Key: collection path (e.g., "/orders"); items are at "/orders/{id}"
+    /// Value: resource configuration
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub resources: HashMap<String, Resource>,
+    /// Backend that receives the requests nothing in the project answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ProxyConfig>,
+    /// What to answer when nothing in the project matches; an alternative
+    /// to `proxy` that can also return a default response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<Fallback>,
+    #[serde(skip)]
+    pub routes: Vec<RoutePattern>,
+}
+
+/// A REST collection: list (with filtering, sorting and pagination), get,
+/// create, replace, patch and delete its items (see resources.rs)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Resource {
+    /// Name of the field that holds the item id (default: "id")
+    #[serde(default = "default_id_field", rename = "idField")]
+    pub id_field: String,
+    /// Items the collection starts with, and returns to when it is reset
+    #[serde(default)]
+    pub data: Vec<serde_json::Value>,
+}
+
+/// Forwarding of unanswered requests to a real backend (see proxy.rs)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxyConfig {
+    /// Base URL of the backend, e.g. "http://localhost:8080/api"; the
+    /// endpoint path and the query are appended to it
+    pub upstream: String,
+    /// Save each forwarded request and its response in the project as a
+    /// new condition, so that the next such request is answered by the mock
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub record: bool,
+    /// Request headers to set on the forwarded request, e.g. "Host" or
+    /// "Authorization" for the backend; null removes the header
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, Option<String>>,
+}
+
+impl ProxyConfig {
+    /// Checks the upstream URL. The error starts with the location in the
+    /// proxy, e.g. "/upstream: ...".
+    fn validate(&self) -> Result<(), String> {
+        if !(self.upstream.starts_with("http://") || self.upstream.starts_with("https://")) {
+            return Err("/upstream: expected an http:// or https:// URL".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Fallback {
+    /// Checks the proxy or the response of a fallback. The error starts with
+    /// the location in the fallback, e.g. "/proxy/upstream: ...".
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Fallback::Proxy(proxy) => proxy.validate().map_err(|e| format!("/proxy{}", e)),
+            Fallback::Response(response) => response.validate().map_err(|e| format!("/response{}", e)),
+            Fallback::NotImplemented => Ok(()),
+        }
+    }
+}
+
+fn default_id_field() -> String {
+    "id".to_string()
 }
 
 /// Configuration for a specific endpoint
@@ -21,8 +98,23 @@ pub struct Endpoint {
     /// Multiple conditions allow different responses based on request details
     #[serde(rename = "when")]
     pub conditions: Vec<WhenCondition>,
-    #[serde(skip)]
-    pub condition_map: HashMap<EndpointKey, (ResponseConfig, u64)>,
+    /// What to answer when no condition matches, instead of the project's fallback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<Fallback>,
+}
+
+/// Answer to the requests that no condition matches
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Fallback {
+    /// Forward the request to a backend, e.g. `{"proxy": {"upstream": "http://localhost:8080"}}`
+    #[serde(rename = "proxy")]
+    Proxy(ProxyConfig),
+    /// Return a fixed response, e.g. `{"response": {"status": 404, "headers": {}}}`
+    #[serde(rename = "response")]
+    Response(ResponseConfig),
+    /// Return "406 Not implemented.", as without a fallback: `"notImplemented"`
+    #[serde(rename = "notImplemented")]
+    NotImplemented,
 }
 
 /// Defines a specific request condition and its response
@@ -34,11 +126,245 @@ pub struct WhenCondition {
     // #[serde(default, skip_serializing_if = "Option::is_none")]
     // pub request: Option<RequestConfig>,
     pub request: RequestConfig,
-    /// Response to return when request matches
-    pub response: ResponseConfig,
+    /// Response to return when request matches; required unless `sequence`
+    /// or `responses` is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ResponseConfig>,
+    /// Responses returned one after the other by successive matching requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<Sequence>,
+    /// Responses picked at random by weight, or on every n-th call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub responses: Option<Vec<WeightedResponse>>,
+    /// Name of the scenario (a per-project state machine starting in the
+    /// state "Started") that `requiredState` and `newState` refer to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scenario: Option<String>,
+    /// The condition only matches while the scenario is in this state
+    #[serde(default, rename = "requiredState", skip_serializing_if = "Option::is_none")]
+    pub required_state: Option<String>,
+    /// State the scenario moves to when the condition matches
+    #[serde(default, rename = "newState", skip_serializing_if = "Option::is_none")]
+    pub new_state: Option<String>,
     /// Optional delay in milliseconds before sending response
     #[serde(default)]
     pub delay: u64,
+    /// Random delay in milliseconds added to `delay` on each response
+    #[serde(default, rename = "delayDistribution", skip_serializing_if = "Option::is_none")]
+    pub delay_distribution: Option<DelayDistribution>,
+    /// Connection failure to simulate instead of sending the response normally
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault: Option<FaultConfig>,
+}
+
+/// Distribution of a random delay in milliseconds, e.g.
+/// `{"type": "uniform", "min": 100, "max": 500}`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum DelayDistribution {
+    /// Any delay between `min` and `max`, equally likely
+    #[serde(rename = "uniform")]
+    Uniform { min: u64, max: u64 },
+    /// Delays around `mean`, most within `stdDev` of it
+    #[serde(rename = "normal")]
+    Normal {
+        mean: f64,
+        #[serde(rename = "stdDev")]
+        std_dev: f64,
+    },
+    /// Long tail: half of the delays below `median`, with rare delays many
+    /// times longer, the more so the larger `sigma` (log-normal)
+    #[serde(rename = "longTail")]
+    LongTail { median: f64, sigma: f64 },
+}
+
+/// A connection failure, e.g. `{"type": "truncate", "bytes": 10}`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FaultConfig {
+    #[serde(flatten)]
+    pub kind: FaultKind,
+    /// Chance of the fault on each response, from 0 to 1 (default: 1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probability: Option<f64>,
+}
+
+/// The kinds of connection failures
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum FaultKind {
+    /// Reset the connection (TCP RST) without responding
+    #[serde(rename = "reset")]
+    Reset,
+    /// Close the connection without responding
+    #[serde(rename = "close")]
+    Close,
+    /// Send only the first `bytes` bytes of the body, then close
+    #[serde(rename = "truncate")]
+    Truncate { bytes: usize },
+    /// Send a Content-Length header off by `offset` bytes, e.g. 10 or -10, then close
+    #[serde(rename = "contentLength")]
+    ContentLength { offset: i64 },
+    /// Send the response slowly, at `bytesPerSecond`
+    #[serde(rename = "trickle")]
+    Trickle {
+        #[serde(rename = "bytesPerSecond")]
+        bytes_per_second: u64,
+    },
+    /// Send bytes that are not valid HTTP, then close
+    #[serde(rename = "malformed")]
+    Malformed,
+}
+
+/// Responses returned in order, e.g. 202 Accepted twice and then 200 Done
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Sequence {
+    /// The responses, in order
+    pub responses: Vec<ResponseConfig>,
+    /// What follows the last response (default: "stick")
+    #[serde(default)]
+    pub mode: SequenceMode,
+}
+
+/// Behavior of a sequence after its last response
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum SequenceMode {
+    /// Keep returning the last response
+    #[default]
+    #[serde(rename = "stick")]
+    Stick,
+    /// Start over with the first response
+    #[serde(rename = "cycle")]
+    Cycle,
+}
+
+impl Sequence {
+    /// Returns the response for a call, counting from 0.
+    pub fn response_at(&self, call: usize) -> Option<&ResponseConfig> {
+        let last = self.responses.len().checked_sub(1)?;
+        match self.mode {
+            SequenceMode::Stick => self.responses.get(call.min(last)),
+            SequenceMode::Cycle => self.responses.get(call % self.responses.len()),
+        }
+    }
+}
+
+/// One of the responses of a condition, e.g. `{"weight": 8, "response": {"status": 503, ...}}`
+/// for 8 calls in every 100 when the weights add up to 100, or
+/// `{"every": 5, "response": ...}` for the 5th, 10th, ... call
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WeightedResponse {
+    /// Relative chance of the response among the responses without `every`
+    /// (default: 1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+    /// Return the response on every n-th call instead of at random
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub every: Option<usize>,
+    pub response: ResponseConfig,
+}
+
+impl WeightedResponse {
+    /// Picks the response for a call, counting from 0: the first response
+    /// whose `every` divides the call number (counting from 1), or else a
+    /// weighted response chosen by `roll`, a number in `[0, 1)`.
+    pub fn pick(choices: &[WeightedResponse], call: usize, roll: f64) -> Option<&ResponseConfig> {
+        if let Some(choice) = choices.iter().find(|choice| choice.every.is_some_and(|n| (call + 1).is_multiple_of(n))) {
+            return Some(&choice.response);
+        }
+        let weighted: Vec<(f64, &ResponseConfig)> = choices.iter()
+            .filter(|choice| choice.every.is_none())
+            .map(|choice| (choice.weight.unwrap_or(1.0), &choice.response))
+            .collect();
+        let mut target = roll * weighted.iter().map(|(weight, _)| weight).sum::<f64>();
+        for (weight, response) in &weighted {
+            if target < *weight {
+                return Some(response);
+            }
+            target -= weight;
+        }
+        // Rounding can leave the target just past the last positive weight.
+        weighted.iter().rev().find(|(weight, _)| *weight > 0.0).map(|(_, response)| *response)
+    }
+}
+
+impl WhenCondition {
+    /// Returns every response the condition may answer with, and its
+    /// location in the condition, e.g. "response" or "sequence/responses/1".
+    pub fn responses(&self) -> Vec<(String, &ResponseConfig)> {
+        let single = self.response.iter().map(|response| ("response".to_string(), response));
+        let sequence = self.sequence.iter().flat_map(|sequence| {
+            sequence.responses.iter().enumerate().map(|(i, response)| (format!("sequence/responses/{}", i), response))
+        });
+        let weighted = self.responses.iter().flat_map(|choices| {
+            choices.iter().enumerate().map(|(i, choice)| (format!("responses/{}/response", i), &choice.response))
+        });
+        single.chain(sequence).chain(weighted).collect()
+    }
+
+    /// Checks the responses, the scenario, the delay and the fault of a
+    /// condition. The error starts with the location in the condition,
+    /// e.g. "sequence: ...".
+    fn validate(&self) -> Result<(), String> {
+        let given = [self.response.is_some(), self.sequence.is_some(), self.responses.is_some()];
+        match given.iter().filter(|given| **given).count() {
+            0 => return Err("response: a response, a sequence or responses are required".to_string()),
+            1 => {}
+            _ => return Err("response: use only one of response, sequence and responses".to_string()),
+        }
+        if self.sequence.as_ref().is_some_and(|sequence| sequence.responses.is_empty()) {
+            return Err("sequence/responses: at least one response is required".to_string());
+        }
+        if let Some(choices) = &self.responses {
+            for (index, choice) in choices.iter().enumerate() {
+                match (choice.weight, choice.every) {
+                    (Some(_), Some(_)) => return Err(format!("responses/{}: use either weight or every", index)),
+                    (Some(weight), None) if !(weight.is_finite() && weight >= 0.0) => {
+                        return Err(format!("responses/{}/weight: expected a non-negative number", index))
+                    }
+                    (None, Some(0)) => return Err(format!("responses/{}/every: expected a positive integer", index)),
+                    _ => {}
+                }
+            }
+            let total_weight: f64 = choices.iter()
+                .filter(|choice| choice.every.is_none())
+                .map(|choice| choice.weight.unwrap_or(1.0))
+                .sum();
+            if total_weight <= 0.0 {
+                return Err("responses: at least one response without every needs a positive weight".to_string());
+            }
+        }
+        for (location, response) in self.responses() {
+            response.validate().map_err(|e| format!("{}{}", location, e))?;
+        }
+        if self.scenario.is_none() && (self.required_state.is_some() || self.new_state.is_some()) {
+            return Err("scenario: requiredState and newState need a scenario".to_string());
+        }
+        match &self.delay_distribution {
+            Some(DelayDistribution::Uniform { min, max }) if min > max => {
+                return Err("delayDistribution: min must not exceed max".to_string())
+            }
+            Some(DelayDistribution::Normal { mean, std_dev }) if !(mean.is_finite() && std_dev.is_finite() && *std_dev >= 0.0) => {
+                return Err("delayDistribution: expected a finite mean and a non-negative stdDev".to_string())
+            }
+            Some(DelayDistribution::LongTail { median, sigma }) if !(median.is_finite() && *median > 0.0 && sigma.is_finite() && *sigma >= 0.0) => {
+                return Err("delayDistribution: expected a positive median and a non-negative sigma".to_string())
+            }
+            _ => {}
+        }
+        if let Some(fault) = &self.fault {
+            if fault.probability.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+                return Err("fault/probability: expected a number from 0 to 1".to_string());
+            }
+            match fault.kind {
+                FaultKind::Trickle { bytes_per_second: 0 } => {
+                    return Err("fault/bytesPerSecond: expected a positive integer".to_string())
+                }
+                FaultKind::ContentLength { offset: 0 } => return Err("fault/offset: must not be 0".to_string()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Request matching configuration
@@ -50,30 +376,190 @@ pub struct RequestConfig {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub queries: Option<HashMap<String, QueryParam>>,
     /// Map of expected request headers
-    /// Key: header name
-    /// Value: expected header value
+    /// Key: header name, compared case-insensitively
+    /// Value: expected header value, or a matching rule object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, HeaderRule>>,
+    /// Map of path params captured by a templated endpoint key to their
+    /// matching rules, e.g. "id" for the endpoint "/users/:id"
+    /// Key: path param name
+    /// Value: matching operator and expected value
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub headers: Option<HashMap<String, String>>,
+    pub params: Option<HashMap<String, QueryParam>>,
     /// Optional JSON body to match against
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub body: Option<serde_json::Value>,
+    /// How `body` is compared with the request body (default: "exact")
+    #[serde(default, rename = "bodyMatch", skip_serializing_if = "BodyMatch::is_exact")]
+    pub body_match: BodyMatch,
+    /// Rules on fields of a JSON request body, all of which must hold
+    #[serde(default, rename = "bodyRules", skip_serializing_if = "Option::is_none")]
+    pub body_rules: Option<Vec<BodyRule>>,
+    /// JSON Schema (draft 2020-12 subset) the JSON request body must satisfy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<serde_json::Value>,
+    /// Response for a request that matches everything but `schema`; without
+    /// it such a request does not match the condition
+    #[serde(default, rename = "onInvalid", skip_serializing_if = "Option::is_none")]
+    pub on_invalid: Option<InvalidBodyResponse>,
+    /// Map of form fields of an "application/x-www-form-urlencoded" or
+    /// "multipart/form-data" body to their matching rules
+    /// Key: field name
+    /// Value: matching operator and expected value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub form: Option<HashMap<String, QueryParam>>,
+    /// Map of files uploaded in a "multipart/form-data" body to their rules
+    /// Key: field name of the file part
+    /// Value: rules on the file name, size and content type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<HashMap<String, FileRule>>,
+    /// Matching rule for a "text/*" body as a whole, e.g. {"operator": "contains", "value": "ping"}
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<QueryParam>,
+}
+
+/// Rules on a file uploaded in a multipart body. The file must be present;
+/// each rule is optional.
+#[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq, Clone)]
+pub struct FileRule {
+    /// Rule on the uploaded file name, e.g. {"operator": "endsWith", "value": ".png"}
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<QueryParam>,
+    /// Rule on the file size in bytes, e.g. {"operator": "lte", "value": 1048576}
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<QueryParam>,
+    /// Rule on the Content-Type of the file part, e.g. {"operator": "is", "value": "image/png"}
+    #[serde(default, rename = "contentType", skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<QueryParam>,
+}
+
+/// Response to a request body failing `RequestConfig.schema`. The body is
+/// generated: {"errors": [{"path": "/user/age", "message": "expected integer, got string"}]}
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct InvalidBodyResponse {
+    /// HTTP status code to return, 400 or 422 (default: 400)
+    #[serde(default = "InvalidBodyResponse::default_status")]
+    pub status: u16,
+    /// Response headers
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+impl InvalidBodyResponse {
+    fn default_status() -> u16 {
+        400
+    }
+}
+
+/// Comparison mode of `RequestConfig.body`
+#[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq, Clone, Copy, Default)]
+pub enum BodyMatch {
+    /// The request body must equal `body`
+    #[default]
+    #[serde(rename = "exact")]
+    Exact,
+    /// The request body must contain `body`: objects may have extra members,
+    /// and every item of an expected array must match some actual item
+    #[serde(rename = "partial")]
+    Partial,
+}
+
+impl BodyMatch {
+    pub fn is_exact(&self) -> bool {
+        *self == BodyMatch::Exact
+    }
+}
+
+/// Rule on the values selected by a JSONPath in the request body, e.g.
+/// {"path": "$.user.address.zip", "operator": "matches", "value": "^\\d{5}$"}.
+/// With several selected values (e.g. "$.items[*].qty") one of them must match.
+#[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq, Clone)]
+pub struct BodyRule {
+    /// JSONPath of the checked field: "$", ".name", "['name']", "[0]", "[*]", ".*" and "..name" are supported
+    pub path: String,
+    /// Matching operator and expected value; "contains" on an array checks
+    /// for an item, and "type" checks the JSON type
+    #[serde(flatten)]
+    pub rule: QueryParam,
 }
 
 /// Query parameter matching configuration
 #[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq, Clone)]
 pub struct QueryParam {
-    /// Matching operator: "is", "is!", "contains", "contains!"
-    pub operator: String,
-    /// Expected value to match against
-    pub value: String,
+    /// Matching operator, see [Operator]
+    pub operator: Operator,
+    /// Expected value to match against: a string or number for most
+    /// operators, a list for "in"/"not_in", a regex for "matches", a number
+    /// or ISO 8601 date for "gt"/"gte"/"lt"/"lte", omitted for "exists"/"absent"
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub value: serde_json::Value,
+    /// Compare strings case-insensitively (default: false)
+    #[serde(default, rename = "ignoreCase", skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_case: bool,
 }
 
-/// Endpoint hashmap key
-#[derive(Eq, PartialEq, Clone, Debug)]
-pub struct EndpointKey {
-    pub method: String, // UPPERCASE
-    pub queries: Option<HashMap<String, QueryParam>>,
-    pub body: Option<serde_json::Value>,
+/// Header matching configuration
+#[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum HeaderRule {
+    /// Plain string: the header must have exactly this value
+    Exact(String),
+    /// Operator object, as for queries, e.g. {"operator": "matches", "value": "^Bearer .+$"}
+    Rule(QueryParam),
+}
+
+/// Matching operators of a [QueryParam]
+#[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq, Clone, Copy)]
+pub enum Operator {
+    /// Value equals `value`
+    #[serde(rename = "is")]
+    Is,
+    /// Value differs from `value`
+    #[serde(rename = "is!")]
+    IsNot,
+    /// Value contains `value`
+    #[serde(rename = "contains")]
+    Contains,
+    /// Value does not contain `value`
+    #[serde(rename = "contains!")]
+    NotContains,
+    /// Value starts with `value`
+    #[serde(rename = "startsWith")]
+    StartsWith,
+    /// Value ends with `value`
+    #[serde(rename = "endsWith")]
+    EndsWith,
+    /// Value matches the regular expression `value`
+    #[serde(rename = "matches")]
+    Matches,
+    /// Value is greater than `value`, compared as numbers or ISO 8601 dates
+    #[serde(rename = "gt")]
+    Gt,
+    /// Value is greater than or equal to `value`
+    #[serde(rename = "gte")]
+    Gte,
+    /// Value is less than `value`
+    #[serde(rename = "lt")]
+    Lt,
+    /// Value is less than or equal to `value`
+    #[serde(rename = "lte")]
+    Lte,
+    /// Value is present, whatever it is
+    #[serde(rename = "exists")]
+    Exists,
+    /// Value is missing
+    #[serde(rename = "absent")]
+    Absent,
+    /// Value equals one of the items of the list `value`
+    #[serde(rename = "in")]
+    In,
+    /// Value equals none of the items of the list `value`
+    #[serde(rename = "not_in")]
+    NotIn,
+    /// Value has the type `value`: "string", "number", "integer", "boolean",
+    /// "array", "object" or "null" (the last three only in body rules)
+    #[serde(rename = "type")]
+    Type,
 }
 
 /// Response configuration
@@ -88,71 +574,200 @@ pub struct ResponseConfig {
     /// Optional JSON response body
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub body: Option<serde_json::Value>,
+    /// Replace `{{ ... }}` expressions in the body and header values with
+    /// request data, e.g. "{{request.params.id}}" (see template.rs)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub template: bool,
 }
 
-// Custom serialization/deserialization for Endpoint to build the HashMap
-impl Endpoint {
-    pub fn build_condition_map(&mut self) {
-        self.condition_map = self.conditions.iter().map(|condition: &WhenCondition| {            
-            let key = EndpointKey {
-                method: condition.method.clone(),
-                queries: condition.request.queries.clone(),
-                body: condition.request.body.clone(),
-            };
-            let value = (condition.response.clone(), condition.delay);
-            (key, value)
-        }).collect();
+impl ResponseConfig {
+    /// Checks the status code, and the templates of a response rendered as
+    /// a template. The error starts with the location in the response, e.g.
+    /// "/status: ...".
+    fn validate(&self) -> Result<(), String> {
+        if !(100..=599).contains(&self.status) {
+            return Err("/status: expected a status code from 100 to 599".to_string());
+        }
+        if !self.template {
+            return Ok(());
+        }
+        let headers = self.headers.values().map(|value| template::validate_str(value));
+        let body = self.body.iter().map(template::validate_value);
+        headers.chain(body).collect::<Result<(), String>>().map_err(|e| format!(": {}", e))
+    }
+}
+
+impl ProjectConfig {
+    /// Compiles the endpoint keys for path matching.
+    pub fn build_routes(&mut self) {
+        self.routes = self.endpoints.keys().map(|key| RoutePattern::parse(key)).collect();
+    }
+
+    /// Finds the endpoint for a request path, preferring the most specific
+    /// key, and returns its key and the endpoint with the path params
+    /// captured by the key.
+    pub fn find_endpoint(&self, path: &str) -> Option<(&str, &Endpoint, HashMap<String, String>)> {
+        // A literal key spelled exactly like the path is always the most specific.
+        if let Some((key, endpoint)) = self.endpoints.get_key_value(path) {
+            return Some((key, endpoint, HashMap::new()));
+        }
+        let (route, params) = routes::find_route(&self.routes, path)?;
+        Some((&route.key, &self.endpoints[&route.key], params))
+    }
+
+    /// Finds the resource for a request path, and returns its key and the
+    /// resource with the item id if the path names an item. Of overlapping
+    /// resources, e.g. `/orders` and `/orders/items`, the longest key wins.
+    pub fn find_resource(&self, path: &str) -> Option<(&str, &Resource, Option<String>)> {
+        let path = routes::path_segments(path);
+        self.resources
+            .iter()
+            .filter_map(|(key, resource)| {
+                let key_segments = routes::path_segments(key);
+                let id = match path.strip_prefix(key_segments.as_slice())? {
+                    [] => None,
+                    [id] => Some(id.to_string()),
+                    _ => return None,
+                };
+                Some((key_segments.len(), key.as_str(), resource, id))
+            })
+            .max_by_key(|(length, key, _, _)| (*length, *key))
+            .map(|(_, key, resource, id)| (key, resource, id))
     }
 }
 
+/// Escapes a key for use in a JSON pointer (RFC 6901).
+pub fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
 
-// Custom Hash implementation for HashMap in RequestConfig
-impl Hash for RequestConfig {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        // Hash length first for better distribution
-        if let Some(queries) = &self.queries {
-            queries.len().hash(state);
-            // Combine all key-value hashes in sorted order without allocation
-            let mut hasher = DefaultHasher::new();
-            for key in queries.keys().collect::<Vec<_>>() {
-                key.hash(&mut hasher);
-                queries[key].hash(&mut hasher);
+impl ProjectConfig {
+    /// Checks the parts of the configuration that deserialization cannot,
+    /// such as the operands of the matching rules. The error names the
+    /// offending rule with a JSON pointer.
+    pub fn validate(&self) -> Result<(), String> {
+        for (path, endpoint) in &self.endpoints {
+            for (index, condition) in endpoint.conditions.iter().enumerate() {
+                if condition.method.parse::<Method>().is_err() {
+                    return Err(format!(
+                        "/endpoints/{}/when/{}/method: unknown HTTP method \"{}\"",
+                        escape_pointer(path), index, condition.method
+                    ));
+                }
+                let rule_sets = [
+                    ("queries", &condition.request.queries),
+                    ("params", &condition.request.params),
+                    ("form", &condition.request.form),
+                ];
+                let header_rules = condition.request.headers.iter().flatten()
+                    .filter_map(|(name, rule)| match rule {
+                        HeaderRule::Rule(rule) => Some(("headers", name, rule)),
+                        HeaderRule::Exact(_) => None,
+                    });
+                let file_rules = condition.request.files.iter().flatten()
+                    .flat_map(|(name, file)| [
+                        ("filename", &file.filename),
+                        ("size", &file.size),
+                        ("contentType", &file.content_type),
+                    ].into_iter().filter_map(move |(field, rule)| Some((name, field, rule.as_ref()?))));
+                let rules = rule_sets.into_iter()
+                    .flat_map(|(section, rules)| rules.iter().flatten().map(move |(name, rule)| (section, escape_pointer(name), rule)))
+                    .chain(header_rules.map(|(section, name, rule)| (section, escape_pointer(name), rule)))
+                    .chain(file_rules.map(|(name, field, rule)| ("files", format!("{}/{}", escape_pointer(name), field), rule)))
+                    .chain(condition.request.text.iter().map(|rule| ("text", String::new(), rule)));
+                for (section, name, rule) in rules {
+                    let location = if name.is_empty() { section.to_string() } else { format!("{}/{}", section, name) };
+                    // Only queries have several values to compare with a list.
+                    let checked = match section != "queries" && matcher::is_value_list(rule) {
+                        true => Err("expected a string or number as value".to_string()),
+                        false => matcher::validate_rule(rule),
+                    };
+                    checked.map_err(|e| format!(
+                        "/endpoints/{}/when/{}/request/{}: {}",
+                        escape_pointer(path), index, location, e
+                    ))?;
+                }
+                for (rule_index, body_rule) in condition.request.body_rules.iter().flatten().enumerate() {
+                    matcher::validate_body_rule(body_rule).map_err(|e| format!(
+                        "/endpoints/{}/when/{}/request/bodyRules/{}: {}",
+                        escape_pointer(path), index, rule_index, e
+                    ))?;
+                }
+                if let Some(schema) = &condition.request.schema {
+                    matcher::check_schema(schema).map_err(|e| format!(
+                        "/endpoints/{}/when/{}/request/schema{}",
+                        escape_pointer(path), index, e
+                    ))?;
+                }
+                condition.validate().map_err(|e| format!(
+                    "/endpoints/{}/when/{}/{}",
+                    escape_pointer(path), index, e
+                ))?;
+                if let Some(on_invalid) = &condition.request.on_invalid {
+                    if condition.request.schema.is_none() {
+                        return Err(format!(
+                            "/endpoints/{}/when/{}/request/onInvalid: requires a schema",
+                            escape_pointer(path), index
+                        ));
+                    }
+                    if on_invalid.status != 400 && on_invalid.status != 422 {
+                        return Err(format!(
+                            "/endpoints/{}/when/{}/request/onInvalid/status: expected 400 or 422",
+                            escape_pointer(path), index
+                        ));
+                    }
+                }
             }
-            hasher.finish().hash(state);
-        }
-        
-        if let Some(headers) = &self.headers {
-            headers.len().hash(state);
-            let mut hasher = DefaultHasher::new();
-            for key in headers.keys().collect::<Vec<_>>() {
-                key.hash(&mut hasher);
-                headers[key].hash(&mut hasher);
+        }
+        if let Some(proxy) = &self.proxy {
+            if self.fallback.is_some() {
+                return Err("/fallback: use either proxy or fallback".to_string());
             }
-            hasher.finish().hash(state);
+            proxy.validate().map_err(|e| format!("/proxy{}", e))?;
+        }
+        if let Some(fallback) = &self.fallback {
+            fallback.validate().map_err(|e| format!("/fallback{}", e))?;
         }
-        
-        self.body.hash(state);
+        for (path, endpoint) in &self.endpoints {
+            if let Some(fallback) = &endpoint.fallback {
+                fallback.validate().map_err(|e| format!("/endpoints/{}/fallback{}", escape_pointer(path), e))?;
+            }
+        }
+        for (key, resource) in &self.resources {
+            resource.validate(key).map_err(|e| format!("/resources/{}{}", escape_pointer(key), e))?;
+        }
+        Ok(())
     }
 }
 
-impl Hash for EndpointKey {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.method.hash(state);
-        
-        if let Some(queries) = &self.queries {
-            // Sort keys before hashing to ensure consistent ordering
-            let mut sorted_keys: Vec<_> = queries.keys().collect();
-            sorted_keys.sort();
-            
-            queries.len().hash(state);
-            let mut hasher = DefaultHasher::new();
-            for key in sorted_keys {
-                key.hash(&mut hasher);
-                queries[key].hash(&mut hasher);
+impl Resource {
+    /// Checks the key and the seed data of a resource. The error starts
+    /// with the location in the resource, e.g. "/data/2: ...".
+    fn validate(&self, key: &str) -> Result<(), String> {
+        use serde_json::Value;
+        let segments = routes::path_segments(key);
+        if segments.is_empty() {
+            return Err(": expected a collection path such as \"/orders\"".to_string());
+        }
+        if segments.iter().any(|segment| segment.starts_with([':', '{', '*'])) {
+            return Err(": path params and wildcards are not supported in resource paths".to_string());
+        }
+        if self.id_field.is_empty() {
+            return Err("/idField: must not be empty".to_string());
+        }
+        let mut ids = HashSet::new();
+        for (index, item) in self.data.iter().enumerate() {
+            let id = match item.get(&self.id_field) {
+                Some(Value::String(id)) => id.clone(),
+                Some(Value::Number(id)) => id.to_string(),
+                Some(_) => return Err(format!("/data/{}: \"{}\" must be a string or a number", index, self.id_field)),
+                None if item.is_object() => return Err(format!("/data/{}: \"{}\" is required", index, self.id_field)),
+                None => return Err(format!("/data/{}: expected an object", index)),
+            };
+            if !ids.insert(id.clone()) {
+                return Err(format!("/data/{}: duplicate {} {}", index, self.id_field, id));
             }
-            hasher.finish().hash(state);
         }
-        
-        self.body.hash(state);
+        Ok(())
     }
-}
\ No newline at end of file
+}
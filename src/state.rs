@@ -0,0 +1,118 @@
+//! Runtime state of the mocked projects: the current state of each scenario
+//! and the number of calls of each response sequence. The state lives in
+//! memory until it is reset, the project is saved or the server restarts.
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The state every scenario starts in.
+pub const INITIAL_STATE: &str = "Started";
+
+lazy_static! {
+    /// Project name -> scenario name -> current state.
+    static ref SCENARIOS: Mutex<HashMap<String, HashMap<String, String>>> =
+        Mutex::new(HashMap::new());
+    /// Project name -> counter name -> number of calls so far.
+    static ref COUNTERS: Mutex<HashMap<String, HashMap<String, usize>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns the current state of a scenario.
+pub fn scenario_state(project: &str, scenario: &str) -> String {
+    SCENARIOS
+        .lock()
+        .unwrap()
+        .get(project)
+        .and_then(|scenarios| scenarios.get(scenario))
+        .cloned()
+        .unwrap_or_else(|| INITIAL_STATE.to_string())
+}
+
+/// Returns the state of every scenario of a project that has left its initial state.
+pub fn scenario_states(project: &str) -> HashMap<String, String> {
+    SCENARIOS.lock().unwrap().get(project).cloned().unwrap_or_default()
+}
+
+/// Moves a scenario to `new_state` if it is in `required_state`, as one
+/// step, so that concurrent requests cannot both take the same transition.
+/// Without a required state any state qualifies; without a new state the
+/// state is kept. Returns whether the scenario was in the required state.
+pub fn transition(project: &str, scenario: &str, required_state: Option<&str>, new_state: Option<&str>) -> bool {
+    let mut scenarios = SCENARIOS.lock().unwrap();
+    let states = scenarios.entry(project.to_string()).or_default();
+    let current = states.get(scenario).map_or(INITIAL_STATE, String::as_str);
+    if required_state.is_some_and(|required| required != current) {
+        return false;
+    }
+    if let Some(new_state) = new_state {
+        states.insert(scenario.to_string(), new_state.to_string());
+    }
+    true
+}
+
+/// Sets the state of a scenario.
+pub fn set_scenario_state(project: &str, scenario: &str, state: &str) {
+    SCENARIOS
+        .lock()
+        .unwrap()
+        .entry(project.to_string())
+        .or_default()
+        .insert(scenario.to_string(), state.to_string());
+}
+
+/// Returns a scenario to its initial state.
+pub fn reset_scenario(project: &str, scenario: &str) {
+    if let Some(states) = SCENARIOS.lock().unwrap().get_mut(project) {
+        states.remove(scenario);
+    }
+}
+
+/// Returns every scenario of a project to its initial state and restarts
+/// its sequences.
+pub fn reset_project(project: &str) {
+    SCENARIOS.lock().unwrap().remove(project);
+    COUNTERS.lock().unwrap().remove(project);
+}
+
+/// Counts a call and returns the number of earlier calls, starting at 0.
+pub fn next_call(project: &str, counter: &str) -> usize {
+    let mut counters = COUNTERS.lock().unwrap();
+    let calls = counters.entry(project.to_string()).or_default().entry(counter.to_string()).or_default();
+    *calls += 1;
+    *calls - 1
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transition() {
+        let project = "state-test-transition";
+        assert_eq!(scenario_state(project, "login"), INITIAL_STATE);
+        assert!(!transition(project, "login", Some("LoggedIn"), None));
+        assert!(transition(project, "login", Some(INITIAL_STATE), Some("LoggedIn")));
+        assert_eq!(scenario_state(project, "login"), "LoggedIn");
+        assert!(transition(project, "login", None, None));
+        assert_eq!(scenario_state(project, "login"), "LoggedIn");
+
+        set_scenario_state(project, "other", "Done");
+        reset_scenario(project, "login");
+        assert_eq!(scenario_state(project, "login"), INITIAL_STATE);
+        assert_eq!(scenario_states(project).get("other").unwrap(), "Done");
+    }
+
+    #[test]
+    fn test_next_call_and_reset() {
+        let project = "state-test-calls";
+        assert_eq!(next_call(project, "a"), 0);
+        assert_eq!(next_call(project, "a"), 1);
+        assert_eq!(next_call(project, "b"), 0);
+        set_scenario_state(project, "s", "x");
+        reset_project(project);
+        assert_eq!(next_call(project, "a"), 0);
+        assert_eq!(scenario_state(project, "s"), INITIAL_STATE);
+    }
+}
@@ -0,0 +1,231 @@
+//! Storage of the project configs. The backend is chosen with the
+//! `MOCK_SERVER_STORE` environment variable:
+//! - `fs` (default): one JSON file per project in `{MOCK_SERVER_DB_ROOT}/projects`
+//! - `memory`: kept in memory until the server stops, e.g. for tests and
+//!   ephemeral CI servers
+//! - `sqlite`: an embedded SQLite database, `{MOCK_SERVER_DB_ROOT}/projects.db`
+//!   unless `MOCK_SERVER_SQLITE_PATH` names another file
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::helpers;
+use crate::project_name::ProjectName;
+
+mod fs;
+mod memory;
+mod sqlite;
+
+pub use fs::FsStore;
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+/// When a stored config last changed, to notice changes made behind the
+/// server's back, e.g. a file edited in an IDE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    pub modified: SystemTime,
+    /// Changes with the content even within the precision of `modified`,
+    /// e.g. a hash of a file's content or the revision of a row
+    pub version: u64,
+}
+
+/// A place to keep the project configs, as the text they were saved with.
+pub trait ConfigStore: Send + Sync {
+    /// Returns a project's config, or None if there is no such project.
+    fn load(&self, project_name: &ProjectName) -> Result<Option<String>, String>;
+
+    /// Creates or replaces a project's config, which readers never see
+    /// partly written.
+    fn save(&self, project_name: &ProjectName, content: &str) -> Result<(), String>;
+
+    /// Saves a new project, and tells whether it was created: false if the
+    /// name is already taken, in which case nothing changes.
+    fn create(&self, project_name: &ProjectName, content: &str) -> Result<bool, String>;
+
+    /// Moves a project to a new name in one step, and tells whether it moved:
+    /// false if the new name is already taken, in which case nothing changes.
+    fn rename(&self, project_name: &ProjectName, new_name: &ProjectName) -> Result<bool, String>;
+
+    /// Removes a project, and tells whether it existed.
+    fn delete(&self, project_name: &ProjectName) -> Result<bool, String>;
+
+    /// Returns the names of the stored projects, sorted.
+    fn list(&self) -> Result<Vec<ProjectName>, String>;
+
+    /// Returns when a project's config last changed, or None if there is no
+    /// such project.
+    fn stamp(&self, project_name: &ProjectName) -> Option<Stamp>;
+
+    fn exists(&self, project_name: &ProjectName) -> Result<bool, String> {
+        Ok(self.load(project_name)?.is_some())
+    }
+}
+
+/// The backends that `MOCK_SERVER_STORE` selects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StoreKind {
+    Fs,
+    Memory,
+    Sqlite,
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fs" | "file" | "files" => Ok(StoreKind::Fs),
+            "memory" => Ok(StoreKind::Memory),
+            "sqlite" => Ok(StoreKind::Sqlite),
+            _ => Err(format!("Unknown config store: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for StoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StoreKind::Fs => "fs",
+            StoreKind::Memory => "memory",
+            StoreKind::Sqlite => "sqlite",
+        })
+    }
+}
+
+lazy_static! {
+    static ref MEMORY_STORE: Arc<MemoryStore> = Arc::new(MemoryStore::new());
+    /// Database path -> store, so that each database is opened once.
+    static ref SQLITE_STORES: Mutex<HashMap<PathBuf, Arc<SqliteStore>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns the backend selected by `MOCK_SERVER_STORE`.
+pub fn kind() -> StoreKind {
+    helpers::get_env_var("MOCK_SERVER_STORE", StoreKind::Fs)
+}
+
+/// Returns the path of the SQLite database.
+pub fn sqlite_path() -> PathBuf {
+    let database_root_folder = helpers::get_env_var("MOCK_SERVER_DB_ROOT", "./database".to_string());
+    let default = format!("{}/projects.db", database_root_folder);
+    PathBuf::from(helpers::get_env_var("MOCK_SERVER_SQLITE_PATH", default))
+}
+
+/// Returns the store of the project configs. The environment is read on
+/// every call, as for `MOCK_SERVER_DB_ROOT`.
+pub fn store() -> Arc<dyn ConfigStore> {
+    match kind() {
+        StoreKind::Fs => Arc::new(FsStore),
+        StoreKind::Memory => MEMORY_STORE.clone(),
+        StoreKind::Sqlite => {
+            let path = sqlite_path();
+            let mut stores = SQLITE_STORES.lock().unwrap();
+            stores.entry(path.clone()).or_insert_with(|| Arc::new(SqliteStore::new(path))).clone()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Checks the behavior every backend shares.
+    fn check_store(store: &dyn ConfigStore) {
+        let alpha = ProjectName::parse("store-alpha").unwrap();
+        let beta = ProjectName::parse("store-beta").unwrap();
+        assert_eq!(store.load(&alpha).unwrap(), None);
+        assert!(!store.exists(&alpha).unwrap());
+        assert_eq!(store.stamp(&alpha), None);
+        assert!(!store.delete(&alpha).unwrap());
+
+        store.save(&beta, "{\"v\": 1}").unwrap();
+        store.save(&alpha, "{}").unwrap();
+        assert_eq!(store.load(&alpha).unwrap().as_deref(), Some("{}"));
+        assert!(store.exists(&alpha).unwrap());
+        assert_eq!(store.list().unwrap(), vec![alpha.clone(), beta.clone()]);
+
+        let stamp = store.stamp(&beta).unwrap();
+        store.save(&beta, "{\"v\": 22}").unwrap();
+        assert_eq!(store.load(&beta).unwrap().as_deref(), Some("{\"v\": 22}"));
+        assert_ne!(store.stamp(&beta).unwrap(), stamp);
+
+        assert!(store.delete(&alpha).unwrap());
+        assert_eq!(store.load(&alpha).unwrap(), None);
+        assert_eq!(store.list().unwrap(), vec![beta.clone()]);
+
+        // Creating and renaming never replace another project.
+        assert!(!store.create(&beta, "{}").unwrap());
+        assert!(store.create(&alpha, "{\"v\": 3}").unwrap());
+        assert!(!store.rename(&alpha, &beta).unwrap());
+        assert_eq!(store.load(&beta).unwrap().as_deref(), Some("{\"v\": 22}"));
+        let gamma = ProjectName::parse("store-gamma").unwrap();
+        assert!(store.rename(&alpha, &gamma).unwrap());
+        assert_eq!(store.load(&gamma).unwrap().as_deref(), Some("{\"v\": 3}"));
+        assert_eq!(store.load(&alpha).unwrap(), None);
+        assert!(store.rename(&alpha, &ProjectName::parse("store-delta").unwrap()).is_err());
+        assert_eq!(store.list().unwrap(), vec![beta, gamma]);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        check_store(&MemoryStore::new());
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        let test_dir = TempDir::new().unwrap();
+        check_store(&SqliteStore::new(test_dir.path().join("projects.db")));
+        assert!(SqliteStore::new(test_dir.path().join("missing/projects.db")).list().is_err());
+    }
+
+    #[test]
+    fn test_fs_store() {
+        let test_dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(test_dir.path().join("projects")).unwrap();
+        temp_env::with_var("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap()), || {
+            check_store(&FsStore);
+            // Other files in the folder are not projects.
+            std::fs::write(test_dir.path().join("projects/notes.txt"), "").unwrap();
+            std::fs::write(test_dir.path().join("projects/.hidden.json"), "{}").unwrap();
+            assert_eq!(FsStore.list().unwrap().len(), 2);
+
+            // An edit of the same length within the precision of the
+            // modification time still changes the stamp.
+            let beta = ProjectName::parse("store-beta").unwrap();
+            let path = test_dir.path().join("projects/store-beta.json");
+            let stamp = FsStore.stamp(&beta).unwrap();
+            std::fs::write(&path, "{\"v\": 23}").unwrap();
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(stamp.modified).unwrap();
+            let edited = FsStore.stamp(&beta).unwrap();
+            assert_eq!(edited.modified, stamp.modified);
+            assert_ne!(edited, stamp);
+        });
+    }
+
+    #[test]
+    fn test_store_selection() {
+        let test_dir = TempDir::new().unwrap();
+        temp_env::with_vars(
+            [
+                ("MOCK_SERVER_DB_ROOT", Some(test_dir.path().to_str().unwrap())),
+                ("MOCK_SERVER_STORE", Some("sqlite")),
+            ],
+            || {
+                assert_eq!(kind(), StoreKind::Sqlite);
+                let project_name = ProjectName::parse("store-selected").unwrap();
+                store().save(&project_name, "{}").unwrap();
+                assert!(store().exists(&project_name).unwrap());
+                assert!(test_dir.path().join("projects.db").exists());
+            },
+        );
+        temp_env::with_var("MOCK_SERVER_STORE", Some("nope"), || assert_eq!(kind(), StoreKind::Fs));
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::ErrorKind;
+
+use super::{ConfigStore, Stamp};
+use crate::helpers;
+use crate::project_name::ProjectName;
+
+/// Keeps each project in `{MOCK_SERVER_DB_ROOT}/projects/{name}.json`. The
+/// folder is read from the environment on every call.
+pub struct FsStore;
+
+impl ConfigStore for FsStore {
+    fn load(&self, project_name: &ProjectName) -> Result<Option<String>, String> {
+        match fs::read_to_string(helpers::get_project_config_file_path(project_name)) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read project {}: {}", project_name, e)),
+        }
+    }
+
+    fn save(&self, project_name: &ProjectName, content: &str) -> Result<(), String> {
+        helpers::write_atomically(&helpers::get_project_config_file_path(project_name), content)
+            .map_err(|e| e.to_string())
+    }
+
+    fn create(&self, project_name: &ProjectName, content: &str) -> Result<bool, String> {
+        match helpers::write_new_atomically(&helpers::get_project_config_file_path(project_name), content) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn rename(&self, project_name: &ProjectName, new_name: &ProjectName) -> Result<bool, String> {
+        // A hard link fails if the new file exists, where a rename would
+        // replace it; the old name is removed once the link is made.
+        let path = helpers::get_project_config_file_path(project_name);
+        let new_path = helpers::get_project_config_file_path(new_name);
+        match fs::hard_link(&path, &new_path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => return Ok(false),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Err(format!("Project does not exist: {}", project_name)),
+            Err(e) => return Err(format!("Failed to rename project {}: {}", project_name, e)),
+        }
+        if let Err(e) = fs::remove_file(&path) {
+            let _ = fs::remove_file(&new_path);
+            return Err(format!("Failed to rename project {}: {}", project_name, e));
+        }
+        Ok(true)
+    }
+
+    fn delete(&self, project_name: &ProjectName) -> Result<bool, String> {
+        match fs::remove_file(helpers::get_project_config_file_path(project_name)) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("Failed to delete project {}: {}", project_name, e)),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<ProjectName>, String> {
+        let entries = match fs::read_dir(helpers::get_projects_folder_path()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to list projects: {}", e)),
+        };
+        let mut names: Vec<ProjectName> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .filter_map(|entry| {
+                let file_name = entry.file_name().into_string().ok()?;
+                ProjectName::parse(file_name.strip_suffix(".json")?).ok()
+            })
+            .collect();
+        names.sort_by(|a, b| a[..].cmp(&b[..]));
+        Ok(names)
+    }
+
+    fn stamp(&self, project_name: &ProjectName) -> Option<Stamp> {
+        let path = helpers::get_project_config_file_path(project_name);
+        let metadata = fs::metadata(&path).ok()?;
+        // The content is hashed as the modification time may be too coarse
+        // to tell apart two quick edits.
+        let mut hasher = DefaultHasher::new();
+        fs::read(&path).ok()?.hash(&mut hasher);
+        Some(Stamp { modified: metadata.modified().ok()?, version: hasher.finish() })
+    }
+
+    fn exists(&self, project_name: &ProjectName) -> Result<bool, String> {
+        Ok(helpers::get_project_config_file_path(project_name).is_file())
+    }
+}
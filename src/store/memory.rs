@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use super::{ConfigStore, Stamp};
+use crate::project_name::ProjectName;
+
+/// Keeps the projects in memory, lost when the server stops.
+pub struct MemoryStore {
+    /// Project name -> config and its stamp
+    projects: Mutex<HashMap<ProjectName, (String, Stamp)>>,
+    /// Number of saves so far, the version of the next stamp
+    saves: Mutex<u64>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore { projects: Mutex::new(HashMap::new()), saves: Mutex::new(0) }
+    }
+}
+
+impl ConfigStore for MemoryStore {
+    fn load(&self, project_name: &ProjectName) -> Result<Option<String>, String> {
+        Ok(self.projects.lock().unwrap().get(project_name).map(|(content, _)| content.clone()))
+    }
+
+    fn save(&self, project_name: &ProjectName, content: &str) -> Result<(), String> {
+        let mut saves = self.saves.lock().unwrap();
+        *saves += 1;
+        let stamp = Stamp { modified: SystemTime::now(), version: *saves };
+        self.projects.lock().unwrap().insert(project_name.clone(), (content.to_string(), stamp));
+        Ok(())
+    }
+
+    fn create(&self, project_name: &ProjectName, content: &str) -> Result<bool, String> {
+        let mut saves = self.saves.lock().unwrap();
+        let mut projects = self.projects.lock().unwrap();
+        if projects.contains_key(project_name) {
+            return Ok(false);
+        }
+        *saves += 1;
+        let stamp = Stamp { modified: SystemTime::now(), version: *saves };
+        projects.insert(project_name.clone(), (content.to_string(), stamp));
+        Ok(true)
+    }
+
+    fn rename(&self, project_name: &ProjectName, new_name: &ProjectName) -> Result<bool, String> {
+        let mut projects = self.projects.lock().unwrap();
+        if projects.contains_key(new_name) {
+            return Ok(false);
+        }
+        let project = projects.remove(project_name).ok_or_else(|| format!("Project does not exist: {}", project_name))?;
+        projects.insert(new_name.clone(), project);
+        Ok(true)
+    }
+
+    fn delete(&self, project_name: &ProjectName) -> Result<bool, String> {
+        Ok(self.projects.lock().unwrap().remove(project_name).is_some())
+    }
+
+    fn list(&self) -> Result<Vec<ProjectName>, String> {
+        let mut names: Vec<ProjectName> = self.projects.lock().unwrap().keys().cloned().collect();
+        names.sort_by(|a, b| a[..].cmp(&b[..]));
+        Ok(names)
+    }
+
+    fn stamp(&self, project_name: &ProjectName) -> Option<Stamp> {
+        self.projects.lock().unwrap().get(project_name).map(|(_, stamp)| *stamp)
+    }
+}
@@ -0,0 +1,142 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{ConfigStore, Stamp};
+use crate::project_name::ProjectName;
+
+/// Keeps the projects in a table of an SQLite database:
+/// `projects(name, content, modified, revision)`, with `modified` in
+/// milliseconds since the epoch and `revision` counting the saves.
+pub struct SqliteStore {
+    path: PathBuf,
+    /// Opened on first use, and again after a failure to open
+    connection: Mutex<Option<Connection>>,
+}
+
+impl SqliteStore {
+    pub fn new(path: PathBuf) -> SqliteStore {
+        SqliteStore { path, connection: Mutex::new(None) }
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Option<Connection>>, String> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            let opened = Connection::open(&self.path).and_then(|opened| {
+                opened.execute_batch(
+                    "CREATE TABLE IF NOT EXISTS projects (
+                        name TEXT PRIMARY KEY,
+                        content TEXT NOT NULL,
+                        modified INTEGER NOT NULL,
+                        revision INTEGER NOT NULL DEFAULT 1
+                    )",
+                )?;
+                Ok(opened)
+            });
+            *connection = Some(opened.map_err(|e| format!("Failed to open {}: {}", self.path.display(), e))?);
+        }
+        Ok(connection)
+    }
+
+    /// Runs a query on the database, whose errors are reported as strings.
+    fn with_connection<T>(&self, query: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Result<T, String> {
+        let connection = self.connection()?;
+        query(connection.as_ref().unwrap()).map_err(|e| e.to_string())
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+impl ConfigStore for SqliteStore {
+    fn load(&self, project_name: &ProjectName) -> Result<Option<String>, String> {
+        self.with_connection(|connection| {
+            connection
+                .query_row("SELECT content FROM projects WHERE name = ?1", params![&project_name[..]], |row| row.get(0))
+                .optional()
+        })
+    }
+
+    fn save(&self, project_name: &ProjectName, content: &str) -> Result<(), String> {
+        self.with_connection(|connection| {
+            connection.execute(
+                "INSERT INTO projects (name, content, modified) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (name) DO UPDATE SET
+                    content = excluded.content,
+                    modified = excluded.modified,
+                    revision = revision + 1",
+                params![&project_name[..], content, now_millis()],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn create(&self, project_name: &ProjectName, content: &str) -> Result<bool, String> {
+        let created = self.with_connection(|connection| {
+            connection.execute(
+                "INSERT INTO projects (name, content, modified) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (name) DO NOTHING",
+                params![&project_name[..], content, now_millis()],
+            )
+        })?;
+        Ok(created > 0)
+    }
+
+    fn rename(&self, project_name: &ProjectName, new_name: &ProjectName) -> Result<bool, String> {
+        // The connection is held throughout, so no other query of the server
+        // comes in between.
+        self.with_connection(|connection| {
+            let exists = |name: &ProjectName| {
+                connection
+                    .query_row("SELECT 1 FROM projects WHERE name = ?1", params![&name[..]], |_| Ok(()))
+                    .optional()
+                    .map(|row| row.is_some())
+            };
+            if !exists(project_name)? {
+                return Ok(Err(format!("Project does not exist: {}", project_name)));
+            }
+            if exists(new_name)? {
+                return Ok(Ok(false));
+            }
+            connection.execute("UPDATE projects SET name = ?2 WHERE name = ?1", params![&project_name[..], &new_name[..]])?;
+            Ok(Ok(true))
+        })?
+    }
+
+    fn delete(&self, project_name: &ProjectName) -> Result<bool, String> {
+        let deleted = self.with_connection(|connection| {
+            connection.execute("DELETE FROM projects WHERE name = ?1", params![&project_name[..]])
+        })?;
+        Ok(deleted > 0)
+    }
+
+    fn list(&self) -> Result<Vec<ProjectName>, String> {
+        let names: Vec<String> = self.with_connection(|connection| {
+            let mut statement = connection.prepare("SELECT name FROM projects ORDER BY name")?;
+            let names = statement.query_map([], |row| row.get(0))?.collect();
+            names
+        })?;
+        // Rows added by hand may not hold valid names; they are not projects.
+        Ok(names.iter().filter_map(|name| ProjectName::parse(name).ok()).collect())
+    }
+
+    fn stamp(&self, project_name: &ProjectName) -> Option<Stamp> {
+        let (modified, revision): (i64, i64) = self
+            .with_connection(|connection| {
+                connection
+                    .query_row(
+                        "SELECT modified, revision FROM projects WHERE name = ?1",
+                        params![&project_name[..]],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .optional()
+            })
+            .ok()??;
+        Some(Stamp {
+            modified: UNIX_EPOCH + Duration::from_millis(modified.max(0) as u64),
+            version: revision as u64,
+        })
+    }
+}
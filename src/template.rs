@@ -0,0 +1,491 @@
+//! Response templating: `{{ ... }}` expressions in response bodies and
+//! headers are replaced with request data or generated values.
+//!
+//! Expressions:
+//! - `request.params.<name>`, `request.query.<name>`, `request.headers.<name>`
+//! - `request.body` or `request.body.<path>`, e.g. `request.body.items[0].name`
+//! - `request.url`, `request.path`, `request.method`
+//! - `now` (ISO 8601 UTC date-time), `timestamp` (milliseconds since the epoch),
+//!   `uuid` and `randomInt <min> <max>`
+//! - `fake.<generator> [args]` for fake data (see fake.rs), e.g. `fake.email`
+//!   or `fake.pick 'open' 'closed'`
+//! - `index`: the position in the array generated by `{"$repeat": n, "$item": ...}`
+//!
+//! Rendering is JSON-aware: a string that consists of a single expression is
+//! replaced by the expression's JSON value, so `"{{request.body.qty}}"` stays
+//! a number, while expressions inside longer strings are interpolated as text.
+
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::datetime;
+use crate::matcher::{self, JsonPath};
+use crate::random::Rng;
+use crate::web_server::{self, types::Request};
+
+mod fake;
+
+/// Maximum number of items a `$repeat` may generate.
+const MAX_REPEAT: u64 = 10_000;
+
+/// Maximum number of items all the `$repeat`s of a value may generate
+/// together, e.g. nested ones, so that one response stays cheap to render.
+const MAX_RENDERED_ITEMS: u64 = 100_000;
+
+/// Request data available to template expressions, and the random number
+/// generator of the generated values.
+#[derive(Debug, Clone)]
+pub struct Context {
+    params: HashMap<String, String>,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Value,
+    url: String,
+    path: String,
+    method: String,
+    rng: RefCell<Rng>,
+}
+
+impl Context {
+    /// Collects the data of a request. A JSON body is available field by
+    /// field, as are the fields of a form body; any other body is a string.
+    pub fn from_request(request: &Request) -> Context {
+        let content_type = web_server::header_value(&request.headers, "Content-Type").unwrap_or_default();
+        let body = if request.body.is_empty() {
+            Value::Null
+        } else if let Ok(json) = serde_json::from_str(&request.body) {
+            json
+        } else if matcher::media_type(content_type) == "application/x-www-form-urlencoded" {
+            web_server::extract_queries(&request.body)
+                .into_iter()
+                .filter_map(|(name, values)| Some((name, Value::String(values.into_iter().next()?))))
+                .collect::<serde_json::Map<_, _>>()
+                .into()
+        } else {
+            Value::String(request.body.clone())
+        };
+        Context {
+            params: request.params.clone(),
+            query: request.queries.clone(),
+            headers: request.headers.clone(),
+            body,
+            url: request.raw_url.clone(),
+            path: request.path.clone(),
+            method: request.method.to_string(),
+            rng: RefCell::new(Rng::from_entropy()),
+        }
+    }
+
+    /// Seeds the generated values from a project seed and the request, so
+    /// that the same request always gets the same values.
+    pub fn with_seed(self, seed: u64) -> Context {
+        let body = self.body.to_string();
+        let rng = Rng::derived(seed, &[&self.method, &self.url, &body]);
+        Context { rng: RefCell::new(rng), ..self }
+    }
+}
+
+/// Splits an expression into words; single or double quotes group words
+/// with spaces, e.g. `fake.pick 'in stock' sold`.
+fn words(source: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = source.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut word = String::new();
+            let mut closed = false;
+            for next in chars.by_ref() {
+                if next == c {
+                    closed = true;
+                    break;
+                }
+                word.push(next);
+            }
+            if !closed {
+                return Err(format!("unclosed quote in template expression \"{}\"", source.trim()));
+            }
+            words.push(word);
+        } else {
+            let mut word = String::new();
+            while let Some(&next) = chars.peek() {
+                if next.is_whitespace() {
+                    break;
+                }
+                word.push(next);
+                chars.next();
+            }
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+/// A parsed template expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Params(String),
+    Query(String),
+    Header(String),
+    Body(JsonPath),
+    Url,
+    Path,
+    Method,
+    Now,
+    Timestamp,
+    Uuid,
+    RandomInt(i64, i64),
+    Fake(fake::Fake),
+    Index,
+}
+
+fn parse_expr(source: &str) -> Result<Expr, String> {
+    let words = words(source)?;
+    let (head, args) = words.split_first().ok_or("empty template expression")?;
+    let head = head.as_str();
+    let no_args = |expr: Expr| if args.is_empty() { Ok(expr) } else { Err(format!("\"{}\" takes no arguments", head)) };
+    if let Some(name) = head.strip_prefix("fake.") {
+        return fake::parse(name, args).map(Expr::Fake);
+    }
+    match head {
+        "index" => no_args(Expr::Index),
+        "now" => no_args(Expr::Now),
+        "timestamp" => no_args(Expr::Timestamp),
+        "uuid" => no_args(Expr::Uuid),
+        "randomInt" => {
+            let bound = |index: usize, default: i64| match args.get(index) {
+                Some(arg) => arg.parse().map_err(|_| format!("\"randomInt\" expects integer bounds, got \"{}\"", arg)),
+                None => Ok(default),
+            };
+            if args.len() > 2 {
+                return Err("\"randomInt\" takes at most two arguments".to_string());
+            }
+            Ok(Expr::RandomInt(bound(0, 0)?, bound(1, 100)?))
+        }
+        _ => {
+            let Some(rest) = head.strip_prefix("request.").or((head == "request").then_some("")) else {
+                return Err(format!("unknown template expression \"{}\"", source.trim()));
+            };
+            if !args.is_empty() {
+                return Err(format!("\"{}\" takes no arguments", head));
+            }
+            let (section, name) = match rest.find(['.', '[']) {
+                Some(index) => (&rest[..index], rest[index..].strip_prefix('.').unwrap_or(&rest[index..])),
+                None => (rest, ""),
+            };
+            let named = |expr: fn(String) -> Expr| {
+                if name.is_empty() {
+                    Err(format!("\"request.{}\" needs a name, e.g. \"request.{}.id\"", section, section))
+                } else {
+                    Ok(expr(name.to_string()))
+                }
+            };
+            match section {
+                "params" => named(Expr::Params),
+                "query" => named(Expr::Query),
+                "headers" => named(Expr::Header),
+                "body" if name.is_empty() => Ok(Expr::Body(JsonPath::parse("$")?)),
+                "body" if rest[section.len()..].starts_with('[') => Ok(Expr::Body(JsonPath::parse(&format!("${}", name))?)),
+                "body" => Ok(Expr::Body(JsonPath::parse(&format!("$.{}", name))?)),
+                "url" | "path" | "method" if !name.is_empty() => Err(format!("\"request.{}\" has no fields", section)),
+                "url" => Ok(Expr::Url),
+                "path" => Ok(Expr::Path),
+                "method" => Ok(Expr::Method),
+                _ => Err(format!("unknown template expression \"{}\"", source.trim())),
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression; missing request data is `null`, as is
+    /// `index` outside of a `$repeat`.
+    fn eval(&self, context: &Context, index: Option<usize>) -> Value {
+        let text = |value: Option<&String>| value.map_or(Value::Null, |value| Value::String(value.clone()));
+        match self {
+            Expr::Params(name) => text(context.params.get(name)),
+            Expr::Query(name) => text(context.query.get(name)),
+            Expr::Header(name) => web_server::header_value(&context.headers, name).map_or(Value::Null, Value::from),
+            Expr::Body(path) => path.select(&context.body).into_iter().next().cloned().unwrap_or(Value::Null),
+            Expr::Url => Value::String(context.url.clone()),
+            Expr::Path => Value::String(context.path.clone()),
+            Expr::Method => Value::String(context.method.clone()),
+            Expr::Now => Value::String(datetime::format_timestamp(datetime::now_millis())),
+            Expr::Timestamp => Value::from(datetime::now_millis()),
+            Expr::Uuid => Value::String(context.rng.borrow_mut().uuid()),
+            Expr::RandomInt(min, max) => Value::from(context.rng.borrow_mut().range(*min, *max)),
+            Expr::Fake(fake) => fake.generate(&mut context.rng.borrow_mut()),
+            Expr::Index => index.map_or(Value::Null, Value::from),
+        }
+    }
+}
+
+/// A piece of a template string.
+enum Piece<'a> {
+    Text(&'a str),
+    Expr(&'a str),
+}
+
+/// Splits a string into literal text and `{{ ... }}` expressions. An
+/// unclosed `{{` is literal text.
+fn pieces(template: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        if start > 0 {
+            pieces.push(Piece::Text(&rest[..start]));
+        }
+        pieces.push(Piece::Expr(&rest[start + 2..start + 2 + length]));
+        rest = &rest[start + 4 + length..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+    pieces
+}
+
+/// Renders a value as text: strings as they are, `null` as nothing.
+fn to_text(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Renders a string, interpolating every expression as text. Invalid
+/// expressions are left as they are.
+pub fn render_str(template: &str, context: &Context) -> String {
+    render_text(template, context, None)
+}
+
+fn render_text(template: &str, context: &Context, index: Option<usize>) -> String {
+    pieces(template)
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Text(text) => text.to_string(),
+            Piece::Expr(source) => match parse_expr(source) {
+                Ok(expr) => to_text(expr.eval(context, index)),
+                Err(_) => format!("{{{{{}}}}}", source),
+            },
+        })
+        .collect()
+}
+
+/// Returns the `$repeat` count and `$item` of an object such as
+/// `{"$repeat": 3, "$item": {...}}`; the count may be a `[min, max]` range.
+fn repeat_parts(members: &serde_json::Map<String, Value>) -> Option<(&Value, &Value)> {
+    Some((members.get("$repeat")?, members.get("$item")?)).filter(|_| members.len() == 2)
+}
+
+/// Parses a `$repeat` count into an inclusive range.
+fn repeat_range(count: &Value) -> Result<(u64, u64), String> {
+    let (min, max) = match count {
+        Value::Array(bounds) if bounds.len() == 2 => (bounds[0].as_u64(), bounds[1].as_u64()),
+        count => (count.as_u64(), count.as_u64()),
+    };
+    match (min, max) {
+        (Some(min), Some(max)) if min <= max && max <= MAX_REPEAT => Ok((min, max)),
+        _ => Err(format!("\"$repeat\" expects a count or a [min, max] range from 0 to {}", MAX_REPEAT)),
+    }
+}
+
+/// Renders every string of a JSON value, keys included. A string that is a
+/// single expression becomes the expression's value, and an object
+/// `{"$repeat": n, "$item": item}` becomes an array of `n` rendered items.
+/// Fails if the `$repeat`s would generate more than `MAX_RENDERED_ITEMS`
+/// items in all.
+pub fn render_value(value: &Value, context: &Context) -> Result<Value, String> {
+    let mut items_left = MAX_RENDERED_ITEMS;
+    render(value, context, None, &mut items_left)
+}
+
+fn render(value: &Value, context: &Context, index: Option<usize>, items_left: &mut u64) -> Result<Value, String> {
+    Ok(match value {
+        Value::String(template) => match pieces(template).as_slice() {
+            [Piece::Expr(source)] => match parse_expr(source) {
+                Ok(expr) => expr.eval(context, index),
+                Err(_) => value.clone(),
+            },
+            _ => Value::String(render_text(template, context, index)),
+        },
+        Value::Array(items) => Value::Array(
+            items.iter().map(|item| render(item, context, index, items_left)).collect::<Result<_, _>>()?,
+        ),
+        Value::Object(members) => match repeat_parts(members) {
+            Some((count, item)) => match repeat_range(count) {
+                Ok((min, max)) => {
+                    let count = context.rng.borrow_mut().range(min as i64, max as i64) as u64;
+                    *items_left = items_left.checked_sub(count).ok_or_else(|| {
+                        format!("\"$repeat\" generates more than {} items in all", MAX_RENDERED_ITEMS)
+                    })?;
+                    Value::Array(
+                        (0..count as usize)
+                            .map(|index| render(item, context, Some(index), items_left))
+                            .collect::<Result<_, _>>()?,
+                    )
+                }
+                Err(_) => value.clone(),
+            },
+            None => Value::Object(
+                members
+                    .iter()
+                    .map(|(key, member)| Ok((render_text(key, context, index), render(member, context, index, items_left)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+        },
+        value => value.clone(),
+    })
+}
+
+/// Checks every expression of a template string.
+pub fn validate_str(template: &str) -> Result<(), String> {
+    for piece in pieces(template) {
+        if let Piece::Expr(source) = piece {
+            parse_expr(source)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks every expression in the strings and keys of a JSON value.
+pub fn validate_value(value: &Value) -> Result<(), String> {
+    match value {
+        Value::String(template) => validate_str(template),
+        Value::Array(items) => items.iter().try_for_each(validate_value),
+        Value::Object(members) if members.contains_key("$repeat") => match repeat_parts(members) {
+            Some((count, item)) => {
+                repeat_range(count)?;
+                validate_value(item)
+            }
+            None => Err("\"$repeat\" needs an \"$item\" and no other members".to_string()),
+        },
+        Value::Object(members) => members.iter().try_for_each(|(key, member)| {
+            validate_str(key)?;
+            validate_value(member)
+        }),
+        _ => Ok(()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web_server::types::Method;
+    use serde_json::json;
+
+    fn context() -> Context {
+        let mut headers = HashMap::new();
+        headers.insert("x-request-id".to_string(), "abc".to_string());
+        let request = Request {
+            method: Method::Post,
+            path: "/users/42".to_string(),
+            version: "1.1".to_string(),
+            headers,
+            body: r#"{"name": "Ann", "qty": 3, "items": [{"sku": "x"}]}"#.to_string(),
+            raw_body: Vec::new(),
+            raw_url: "/users/42?date=2024-03-01".to_string(),
+            queries: HashMap::from([("date".to_string(), "2024-03-01".to_string())]),
+            query_values: HashMap::new(),
+            params: HashMap::from([("id".to_string(), "42".to_string())]),
+            matches: Vec::new(),
+        };
+        Context::from_request(&request)
+    }
+
+    #[test]
+    fn test_render_value_keeps_types() {
+        let template = json!({
+            "id": "{{request.params.id}}",
+            "qty": "{{ request.body.qty }}",
+            "items": "{{request.body.items}}",
+            "greeting": "Hello {{request.body.name}}, {{request.body.qty}} items on {{request.query.date}}",
+            "first": "{{request.body.items[0].sku}}",
+            "missing": "{{request.query.page}}",
+            "{{request.headers.X-Request-Id}}": "{{request.method}} {{request.url}}"
+        });
+        assert_eq!(render_value(&template, &context()).unwrap(), json!({
+            "id": "42",
+            "qty": 3,
+            "items": [{"sku": "x"}],
+            "greeting": "Hello Ann, 3 items on 2024-03-01",
+            "first": "x",
+            "missing": null,
+            "abc": "POST /users/42?date=2024-03-01"
+        }));
+    }
+
+    #[test]
+    fn test_render_helpers() {
+        let rendered = render_value(&json!(["{{now}}", "{{timestamp}}", "{{uuid}}", "{{randomInt 5 7}}"]), &context()).unwrap();
+        assert!(datetime::parse_timestamp(rendered[0].as_str().unwrap()).is_some());
+        assert!(rendered[1].as_i64().unwrap() > 1_700_000_000_000);
+        assert_eq!(rendered[2].as_str().unwrap().len(), 36);
+        assert!((5..=7).contains(&rendered[3].as_i64().unwrap()));
+    }
+
+    #[test]
+    fn test_render_repeat_and_fake_data() {
+        let template = json!({"users": {"$repeat": 3, "$item": {
+            "id": "{{index}}",
+            "name": "{{fake.fullName}}",
+            "email": "{{fake.email}}",
+            "status": "{{fake.pick 'in review' active 1}}",
+            "tags": {"$repeat": [0, 2], "$item": "{{fake.word}}"}
+        }}});
+        let rendered = render_value(&template, &context()).unwrap();
+        let users = rendered["users"].as_array().unwrap();
+        assert_eq!(users.len(), 3);
+        for (index, user) in users.iter().enumerate() {
+            assert_eq!(user["id"], json!(index));
+            assert_eq!(user["name"].as_str().unwrap().split(' ').count(), 2);
+            assert!(user["email"].as_str().unwrap().contains('@'));
+            assert!([json!("in review"), json!("active"), json!(1)].contains(&user["status"]));
+            assert!(user["tags"].as_array().unwrap().len() <= 2);
+        }
+    }
+
+    #[test]
+    fn test_render_repeat_budget() {
+        // Each level is within MAX_REPEAT, but not the items in all.
+        let nested = json!({"$repeat": 1000, "$item": {"$repeat": 1000, "$item": "{{index}}"}});
+        assert!(validate_value(&nested).is_ok());
+        assert!(render_value(&nested, &context()).unwrap_err().contains("100000"));
+        let within = json!([{"$repeat": 50, "$item": {"$repeat": 1000, "$item": 1}}, {"$repeat": 10, "$item": 2}]);
+        assert_eq!(render_value(&within, &context()).unwrap()[0].as_array().unwrap().len(), 50);
+    }
+
+    #[test]
+    fn test_seeded_context_is_reproducible() {
+        let template = json!({"$repeat": 5, "$item": ["{{uuid}}", "{{fake.city}}", "{{fake.price 1 10}}"]});
+        let first = render_value(&template, &context().with_seed(42)).unwrap();
+        assert_eq!(first, render_value(&template, &context().with_seed(42)).unwrap());
+        assert_ne!(first, render_value(&template, &context().with_seed(43)).unwrap());
+    }
+
+    #[test]
+    fn test_render_str_leaves_unknown_and_unclosed_expressions() {
+        assert_eq!(render_str("{{nope}} {{request.params.id", &context()), "{{nope}} {{request.params.id");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate_value(&json!({"a": ["{{request.body.items[*].sku}}", "{{randomInt 1 6}}"]})).is_ok());
+        assert!(validate_value(&json!({"a": "{{request.cookies.x}}"})).is_err());
+        assert!(validate_value(&json!({"a": "{{randomInt one}}"})).is_err());
+        assert!(validate_value(&json!({"{{uuid 1}}": 1})).is_err());
+        assert!(validate_str("{{request.query}}").is_err());
+        assert!(validate_value(&json!({"$repeat": [2, 1], "$item": 1})).is_err());
+        assert!(validate_value(&json!({"$repeat": 20000, "$item": 1})).is_err());
+        assert!(validate_value(&json!({"$repeat": 2})).is_err());
+        assert!(validate_value(&json!({"$repeat": 2, "$item": "{{fake.nickname}}"})).is_err());
+        assert!(validate_str("{{fake.pick 'unclosed}}").is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! Offline fake data for response templates: names, contact details,
+//! addresses, lorem text, dates and numbers, drawn from built-in word lists.
+
+use serde_json::Value;
+
+use crate::datetime;
+use crate::random::Rng;
+
+const FIRST_NAMES: &[&str] = &[
+    "James", "Mary", "John", "Patricia", "Robert", "Jennifer", "Michael", "Linda", "David", "Elizabeth",
+    "William", "Barbara", "Richard", "Susan", "Joseph", "Jessica", "Thomas", "Sarah", "Carlos", "Karen",
+    "Daniel", "Lisa", "Matthew", "Nancy", "Anthony", "Sofia", "Mark", "Emma", "Hiroshi", "Olivia",
+    "Ahmed", "Amara", "Lukas", "Chloe", "Mateo", "Priya", "Noah", "Mia", "Wei", "Fatima",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Smith", "Johnson", "Williams", "Brown", "Jones", "Garcia", "Miller", "Davis", "Rodriguez", "Martinez",
+    "Hernandez", "Lopez", "Gonzalez", "Wilson", "Anderson", "Thomas", "Taylor", "Moore", "Jackson", "Martin",
+    "Lee", "Perez", "Thompson", "White", "Harris", "Sanchez", "Clark", "Ramirez", "Lewis", "Robinson",
+    "Walker", "Young", "Allen", "King", "Wright", "Scott", "Nguyen", "Kim", "Müller", "Tanaka",
+];
+
+const EMAIL_DOMAINS: &[&str] = &["example.com", "example.org", "example.net", "mail.test", "inbox.test"];
+
+const COMPANY_WORDS: &[&str] = &[
+    "Acme", "Globex", "Initech", "Umbrella", "Stark", "Wayne", "Hooli", "Vandelay", "Soylent", "Cyberdyne",
+    "Tyrell", "Aperture", "Oscorp", "Wonka", "Gringotts", "Dunder", "Pied Piper", "Monarch", "Nakatomi", "Zorg",
+];
+
+const COMPANY_SUFFIXES: &[&str] = &["Inc.", "LLC", "Ltd.", "Group", "Corp.", "Industries", "Labs", "Systems"];
+
+const STREET_NAMES: &[&str] = &[
+    "Main", "Oak", "Pine", "Maple", "Cedar", "Elm", "Washington", "Lake", "Hill", "Park",
+    "Sunset", "River", "Church", "Mill", "Spring", "Highland", "Forest", "Meadow", "Ridge", "Willow",
+];
+
+const STREET_SUFFIXES: &[&str] = &["Street", "Avenue", "Road", "Lane", "Drive", "Court", "Boulevard", "Way"];
+
+const CITIES: &[&str] = &[
+    "Springfield", "Riverside", "Fairview", "Madison", "Georgetown", "Franklin", "Clinton", "Salem", "Greenville", "Bristol",
+    "Oakland", "Arlington", "Burlington", "Manchester", "Lexington", "Milton", "Newport", "Ashland", "Dover", "Hudson",
+];
+
+const COUNTRIES: &[&str] = &[
+    "United States", "Canada", "Mexico", "Brazil", "Argentina", "United Kingdom", "Germany", "France", "Spain", "Italy",
+    "Netherlands", "Sweden", "Poland", "Nigeria", "Kenya", "Egypt", "India", "Japan", "South Korea", "Australia",
+];
+
+const LOREM: &[&str] = &[
+    "lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing", "elit", "sed", "do",
+    "eiusmod", "tempor", "incididunt", "ut", "labore", "et", "dolore", "magna", "aliqua", "enim",
+    "ad", "minim", "veniam", "quis", "nostrud", "exercitation", "ullamco", "laboris", "nisi", "aliquip",
+    "ex", "ea", "commodo", "consequat", "duis", "aute", "irure", "in", "reprehenderit", "voluptate",
+];
+
+/// Milliseconds in a day.
+const DAY: i64 = 86_400_000;
+
+/// A fake data generator with its arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fake {
+    FirstName,
+    LastName,
+    FullName,
+    Email,
+    Username,
+    Phone,
+    Company,
+    Street,
+    City,
+    Country,
+    ZipCode,
+    Word,
+    Words(usize),
+    Sentence,
+    Paragraph,
+    Boolean,
+    /// A date in a range of milliseconds, rendered as `YYYY-MM-DD`.
+    Date(i64, i64),
+    /// A date-time in a range of milliseconds, rendered as ISO 8601 UTC.
+    DateTime(i64, i64),
+    /// A uniform number with a number of decimals.
+    Float(f64, f64, u32),
+    /// A uniform price with two decimals.
+    Price(f64, f64),
+    /// A normally distributed number: mean and standard deviation.
+    Normal(f64, f64),
+    /// An exponentially distributed number with a mean, e.g. for latencies.
+    Exponential(f64),
+    /// One of the given values.
+    Pick(Vec<String>),
+}
+
+/// Parses the generator called `fake.<name>` with its arguments.
+pub fn parse(name: &str, args: &[String]) -> Result<Fake, String> {
+    let number = |index: usize, default: f64| -> Result<f64, String> {
+        match args.get(index) {
+            Some(arg) => arg.parse().map_err(|_| format!("\"fake.{}\" expects numbers, got \"{}\"", name, arg)),
+            None => Ok(default),
+        }
+    };
+    let date = |index: usize, default: i64| -> Result<i64, String> {
+        match args.get(index) {
+            Some(arg) => datetime::parse_timestamp(arg)
+                .ok_or_else(|| format!("\"fake.{}\" expects ISO 8601 dates, got \"{}\"", name, arg)),
+            None => Ok(default),
+        }
+    };
+    let max_args = |count: usize| {
+        if args.len() > count {
+            Err(format!("\"fake.{}\" takes at most {} arguments", name, count))
+        } else {
+            Ok(())
+        }
+    };
+    let fake = match name {
+        "firstName" => Fake::FirstName,
+        "lastName" => Fake::LastName,
+        "fullName" => Fake::FullName,
+        "email" => Fake::Email,
+        "username" => Fake::Username,
+        "phone" => Fake::Phone,
+        "company" => Fake::Company,
+        "street" => Fake::Street,
+        "city" => Fake::City,
+        "country" => Fake::Country,
+        "zipCode" => Fake::ZipCode,
+        "word" => Fake::Word,
+        "words" => {
+            max_args(1)?;
+            let count = number(0, 3.0)?;
+            if count < 0.0 || count.fract() != 0.0 {
+                return Err("\"fake.words\" expects a word count".to_string());
+            }
+            return Ok(Fake::Words(count as usize));
+        }
+        "sentence" => Fake::Sentence,
+        "paragraph" => Fake::Paragraph,
+        "boolean" => Fake::Boolean,
+        "date" | "dateTime" => {
+            max_args(2)?;
+            let now = datetime::now_millis();
+            let (from, to) = (date(0, now - 365 * DAY)?, date(1, now)?);
+            let (from, to) = (from.min(to), from.max(to));
+            return Ok(if name == "date" { Fake::Date(from, to) } else { Fake::DateTime(from, to) });
+        }
+        "float" => {
+            max_args(3)?;
+            let decimals = number(2, 2.0)?;
+            if !(0.0..=10.0).contains(&decimals) || decimals.fract() != 0.0 {
+                return Err("\"fake.float\" expects 0 to 10 decimals".to_string());
+            }
+            return Ok(Fake::Float(number(0, 0.0)?, number(1, 1.0)?, decimals as u32));
+        }
+        "price" => {
+            max_args(2)?;
+            return Ok(Fake::Price(number(0, 1.0)?, number(1, 100.0)?));
+        }
+        "normal" => {
+            max_args(2)?;
+            return Ok(Fake::Normal(number(0, 0.0)?, number(1, 1.0)?));
+        }
+        "exponential" => {
+            max_args(1)?;
+            return Ok(Fake::Exponential(number(0, 1.0)?));
+        }
+        "pick" => {
+            if args.is_empty() {
+                return Err("\"fake.pick\" needs at least one value".to_string());
+            }
+            return Ok(Fake::Pick(args.to_vec()));
+        }
+        _ => return Err(format!("unknown fake data generator \"fake.{}\"", name)),
+    };
+    max_args(0)?;
+    Ok(fake)
+}
+
+fn pick<'a>(rng: &mut Rng, items: &[&'a str]) -> &'a str {
+    items[rng.range(0, items.len() as i64 - 1) as usize]
+}
+
+/// Lowercase ASCII form of a name for user names and email addresses.
+fn slug(name: &str) -> String {
+    name.chars()
+        .filter_map(|c| match c {
+            'ü' => Some('u'),
+            c if c.is_ascii_alphanumeric() => Some(c.to_ascii_lowercase()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn sentence(rng: &mut Rng) -> String {
+    let count = rng.range(6, 12) as usize;
+    let words: Vec<&str> = (0..count).map(|_| pick(rng, LOREM)).collect();
+    let text = words.join(" ");
+    let mut chars = text.chars();
+    let first = chars.next().map(|c| c.to_ascii_uppercase()).unwrap_or_default();
+    format!("{}{}.", first, chars.as_str())
+}
+
+/// Rounds a number to a number of decimals and returns it as JSON.
+fn rounded(value: f64, decimals: u32) -> Value {
+    let factor = 10f64.powi(decimals as i32);
+    let value = (value * factor).round() / factor;
+    if decimals == 0 {
+        Value::from(value as i64)
+    } else {
+        serde_json::Number::from_f64(value).map_or(Value::Null, Value::Number)
+    }
+}
+
+impl Fake {
+    pub fn generate(&self, rng: &mut Rng) -> Value {
+        match self {
+            Fake::FirstName => pick(rng, FIRST_NAMES).into(),
+            Fake::LastName => pick(rng, LAST_NAMES).into(),
+            Fake::FullName => format!("{} {}", pick(rng, FIRST_NAMES), pick(rng, LAST_NAMES)).into(),
+            Fake::Email => {
+                let (first, last) = (pick(rng, FIRST_NAMES), pick(rng, LAST_NAMES));
+                format!("{}.{}@{}", slug(first), slug(last), pick(rng, EMAIL_DOMAINS)).into()
+            }
+            Fake::Username => format!("{}{}", slug(pick(rng, FIRST_NAMES)), rng.range(1, 999)).into(),
+            Fake::Phone => format!("+1-{}-555-{:04}", rng.range(200, 999), rng.range(0, 9999)).into(),
+            Fake::Company => format!("{} {}", pick(rng, COMPANY_WORDS), pick(rng, COMPANY_SUFFIXES)).into(),
+            Fake::Street => {
+                format!("{} {} {}", rng.range(1, 9999), pick(rng, STREET_NAMES), pick(rng, STREET_SUFFIXES)).into()
+            }
+            Fake::City => pick(rng, CITIES).into(),
+            Fake::Country => pick(rng, COUNTRIES).into(),
+            Fake::ZipCode => format!("{:05}", rng.range(501, 99950)).into(),
+            Fake::Word => pick(rng, LOREM).into(),
+            Fake::Words(count) => (0..*count).map(|_| pick(rng, LOREM)).collect::<Vec<_>>().join(" ").into(),
+            Fake::Sentence => sentence(rng).into(),
+            Fake::Paragraph => {
+                let count = rng.range(3, 6);
+                (0..count).map(|_| sentence(rng)).collect::<Vec<_>>().join(" ").into()
+            }
+            Fake::Boolean => Value::Bool(rng.range(0, 1) == 1),
+            Fake::Date(from, to) => {
+                let date = datetime::format_timestamp(rng.range(from.div_euclid(DAY), to.div_euclid(DAY)) * DAY);
+                date[..10].to_string().into()
+            }
+            Fake::DateTime(from, to) => datetime::format_timestamp(rng.range(*from / 1000, *to / 1000) * 1000).into(),
+            Fake::Float(min, max, decimals) => rounded(min + rng.next_f64() * (max - min), *decimals),
+            Fake::Price(min, max) => rounded(min + rng.next_f64() * (max - min), 2),
+            Fake::Normal(mean, deviation) => rounded(mean + deviation * rng.normal(), 4),
+            Fake::Exponential(mean) => rounded(-mean * (1.0 - rng.next_f64()).ln(), 4),
+            Fake::Pick(values) => {
+                let value = &values[rng.range(0, values.len() as i64 - 1) as usize];
+                // Numbers and booleans keep their type, like other template values.
+                serde_json::from_str::<Value>(value)
+                    .ok()
+                    .filter(|parsed| parsed.is_number() || parsed.is_boolean())
+                    .unwrap_or_else(|| Value::String(value.clone()))
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(name: &str, args: &[&str], seed: u64) -> Value {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse(name, &args).unwrap().generate(&mut Rng::new(seed))
+    }
+
+    #[test]
+    fn test_generators_are_plausible() {
+        for seed in 0..50 {
+            let email = generate("email", &[], seed);
+            let (local, domain) = email.as_str().unwrap().split_once('@').unwrap();
+            assert!(local.contains('.') && EMAIL_DOMAINS.contains(&domain));
+            assert_eq!(generate("zipCode", &[], seed).as_str().unwrap().len(), 5);
+            assert_eq!(generate("words", &["4"], seed).as_str().unwrap().split(' ').count(), 4);
+
+            let date = generate("date", &["2024-01-01", "2024-01-31"], seed);
+            assert!(date.as_str().unwrap().starts_with("2024-01-"));
+            let price = generate("price", &["10", "20"], seed).as_f64().unwrap();
+            assert!((10.0..=20.0).contains(&price));
+            assert_eq!((price * 100.0).round() / 100.0, price);
+            assert!(["a", "b"].contains(&generate("pick", &["a", "b"], seed).as_str().unwrap()));
+        }
+        assert_eq!(generate("pick", &["7"], 1), Value::from(7));
+    }
+
+    #[test]
+    fn test_normal_distribution() {
+        let mut rng = Rng::new(3);
+        let fake = Fake::Normal(100.0, 15.0);
+        let samples: Vec<f64> = (0..2000).map(|_| fake.generate(&mut rng).as_f64().unwrap()).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!((mean - 100.0).abs() < 2.0, "mean was {}", mean);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("nickname", &[]).is_err());
+        assert!(parse("email", &["x".to_string()]).is_err());
+        assert!(parse("date", &["yesterday".to_string()]).is_err());
+        assert!(parse("pick", &[]).is_err());
+        assert!(parse("words", &["-1".to_string()]).is_err());
+    }
+}
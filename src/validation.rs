@@ -0,0 +1,238 @@
+//! Checks of a submitted project configuration before it is saved. Parse
+//! errors and failed checks are reported with a JSON pointer to the
+//! offending value and its line and column in the submitted text.
+
+use crate::schema::{escape_pointer, ProjectConfig};
+
+/// An error in a submitted project configuration.
+#[derive(Debug, PartialEq)]
+pub struct ConfigError {
+    /// JSON pointer to the offending value, e.g. "/endpoints/~1users/when/0/method"
+    pub pointer: String,
+    pub message: String,
+    /// Position of the offending value in the text, both starting at 1
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Parses a project configuration and runs its checks, and returns it
+/// ready to serve, with its routes built.
+pub fn parse_config(text: &str) -> Result<ProjectConfig, ConfigError> {
+    if text.trim().is_empty() {
+        return Err(ConfigError { pointer: String::new(), message: "the configuration is empty".to_string(), line: 1, column: 1 });
+    }
+    let mut config: ProjectConfig = serde_json::from_str(text).map_err(|e| {
+        // serde_json appends the position to the message, which is reported apart.
+        let message = e.to_string();
+        let message = message.rfind(" at line ").map_or(message.as_str(), |end| &message[..end]);
+        let offset = offset_of(text, e.line(), e.column());
+        ConfigError { pointer: pointer_at(text, offset), message: message.to_string(), line: e.line(), column: e.column().max(1) }
+    })?;
+    config.validate().map_err(|e| {
+        let (pointer, message) = split_error(text, &e);
+        let (line, column) = position_of(text, &pointer);
+        ConfigError { pointer, message, line, column }
+    })?;
+    config.build_routes();
+    Ok(config)
+}
+
+/// Splits an error of [ProjectConfig::validate], such as
+/// "/endpoints/~1a/when/0/response: ...", into the pointer and the message.
+/// Keys may contain ": " too, so the longest prefix that points into the
+/// document is preferred.
+fn split_error(text: &str, error: &str) -> (String, String) {
+    let document: Option<serde_json::Value> = serde_json::from_str(text).ok();
+    let splits: Vec<usize> = error.match_indices(": ").map(|(index, _)| index).collect();
+    let index = splits.iter().rev()
+        .find(|index| document.as_ref().is_some_and(|document| document.pointer(&error[..**index]).is_some()))
+        .or(splits.first());
+    match index {
+        Some(index) => (error[..*index].to_string(), error[index + 2..].to_string()),
+        None => (String::new(), error.to_string()),
+    }
+}
+
+/// The extent of a value in the text, and where it is in the document.
+struct Span {
+    pointer: String,
+    start: usize,
+    end: usize,
+}
+
+/// Finds the extent of every value of a JSON text, as far as it can be
+/// read: the values that are not closed extend to the end of the text.
+struct Scanner<'a> {
+    text: &'a [u8],
+    position: usize,
+    spans: Vec<Span>,
+}
+
+impl Scanner<'_> {
+    fn scan(text: &str) -> Vec<Span> {
+        let mut scanner = Scanner { text: text.as_bytes(), position: 0, spans: Vec::new() };
+        scanner.value(String::new());
+        scanner.spans
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.position).is_some_and(u8::is_ascii_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn value(&mut self, pointer: String) -> Option<()> {
+        self.skip_whitespace();
+        let index = self.spans.len();
+        self.spans.push(Span { pointer: pointer.clone(), start: self.position, end: self.text.len() });
+        match self.text.get(self.position)? {
+            b'{' => {
+                self.position += 1;
+                loop {
+                    self.skip_whitespace();
+                    match self.text.get(self.position)? {
+                        b'}' => break,
+                        b',' => self.position += 1,
+                        b'"' => {
+                            let key = self.string()?;
+                            self.skip_whitespace();
+                            if self.text.get(self.position)? != &b':' {
+                                return None;
+                            }
+                            self.position += 1;
+                            self.value(format!("{}/{}", pointer, escape_pointer(&key)))?;
+                        }
+                        _ => return None,
+                    }
+                }
+                self.position += 1;
+            }
+            b'[' => {
+                self.position += 1;
+                let mut item = 0;
+                loop {
+                    self.skip_whitespace();
+                    match self.text.get(self.position)? {
+                        b']' => break,
+                        b',' => self.position += 1,
+                        _ => {
+                            self.value(format!("{}/{}", pointer, item))?;
+                            item += 1;
+                        }
+                    }
+                }
+                self.position += 1;
+            }
+            b'"' => {
+                self.string()?;
+            }
+            _ => {
+                while self.text.get(self.position).is_some_and(|byte| !b",]} \t\r\n".contains(byte)) {
+                    self.position += 1;
+                }
+            }
+        }
+        self.spans[index].end = self.position;
+        Some(())
+    }
+
+    /// Reads a string starting at its opening quote.
+    fn string(&mut self) -> Option<String> {
+        let start = self.position;
+        self.position += 1;
+        loop {
+            match self.text.get(self.position)? {
+                b'\\' => self.position += 2,
+                b'"' => break,
+                _ => self.position += 1,
+            }
+        }
+        self.position += 1;
+        serde_json::from_slice(&self.text[start..self.position]).ok()
+    }
+}
+
+/// Returns the byte offset of a line and column, both starting at 1.
+fn offset_of(text: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = text.split_inclusive('\n').take(line.saturating_sub(1)).map(str::len).sum();
+    (line_start + column.saturating_sub(1)).min(text.len())
+}
+
+/// Returns the pointer of the innermost value at a byte offset.
+fn pointer_at(text: &str, offset: usize) -> String {
+    Scanner::scan(text)
+        .into_iter()
+        .rev()
+        .find(|span| span.start <= offset && offset < span.end)
+        .map(|span| span.pointer)
+        .unwrap_or_default()
+}
+
+/// Returns the line and column of the value at a pointer, or of its
+/// closest ancestor in the text, e.g. the condition of a missing response.
+fn position_of(text: &str, pointer: &str) -> (usize, usize) {
+    let spans = Scanner::scan(text);
+    let mut pointer = pointer;
+    let offset = loop {
+        if let Some(span) = spans.iter().find(|span| span.pointer == pointer) {
+            break span.start;
+        }
+        match pointer.rfind('/') {
+            Some(parent) => pointer = &pointer[..parent],
+            None => break 0,
+        }
+    };
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = offset - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    (line, column)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_errors() {
+        let text = "{\n  \"description\": \"x\",\n  \"endpoints\": {\n    \"/a\": {\"when\": [{\"method\": \"GET\", \"request\": {\"queries\": {\"q\": {\"operator\": \"nope\", \"value\": 1}}}}]}\n  }\n}";
+        let error = parse_config(text).unwrap_err();
+        assert_eq!(error.pointer, "/endpoints/~1a/when/0/request/queries/q/operator");
+        assert!(error.message.starts_with("unknown variant `nope`"));
+        assert_eq!(error.line, 4);
+
+        let error = parse_config("{\"description\": \"x\", \"endpoints\": {\"/a\": {\"when\": [{\"request\": {}}]}}}").unwrap_err();
+        assert_eq!(error.pointer, "/endpoints/~1a/when/0");
+        assert_eq!(error.message, "missing field `method`");
+
+        let error = parse_config("{\"description\": \"x\", \"endpoints\": {").unwrap_err();
+        assert_eq!(error.pointer, "/endpoints");
+        assert_eq!(error.message, "EOF while parsing an object");
+        assert_eq!(parse_config("  ").unwrap_err().message, "the configuration is empty");
+    }
+
+    #[test]
+    fn test_check_errors() {
+        let text = "{\n  \"description\": \"x\",\n  \"endpoints\": {\n    \"/a: b\": {\"when\": [\n      {\"method\": \"FETCH\", \"request\": {}, \"response\": {\"status\": 200, \"headers\": {}}},\n      {\"method\": \"GET\", \"request\": {}, \"response\": {\"status\": 700, \"headers\": {}}}\n    ]}\n  }\n}";
+        let error = parse_config(text).unwrap_err();
+        assert_eq!(error.pointer, "/endpoints/~1a: b/when/0/method");
+        assert_eq!(error.message, "unknown HTTP method \"FETCH\"");
+        assert_eq!((error.line, error.column), (5, 18));
+
+        let error = parse_config(&text.replace("FETCH", "GET")).unwrap_err();
+        assert_eq!(error.pointer, "/endpoints/~1a: b/when/1/response/status");
+        assert_eq!(error.message, "expected a status code from 100 to 599");
+        assert_eq!(error.line, 6);
+
+        let text = text.replace("FETCH", "GET").replace("700", "201");
+        let config = parse_config(&text).unwrap();
+        assert_eq!(config.routes.len(), 1);
+
+        // Only a query can repeat, so only a query rule compares a list.
+        let lists = "{\"description\": \"x\", \"endpoints\": {\"/a\": {\"when\": [{\"method\": \"GET\", \"request\": {\"queries\": {\"tag\": {\"operator\": \"is\", \"value\": [\"a\", \"b\"]}}}, \"response\": {\"status\": 200, \"headers\": {}}}]}}}";
+        assert!(parse_config(lists).is_ok());
+        let error = parse_config(&lists.replace("queries", "form")).unwrap_err();
+        assert_eq!(error.pointer, "/endpoints/~1a/when/0/request/form/tag");
+        assert_eq!(error.message, "expected a string or number as value");
+    }
+}
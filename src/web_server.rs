@@ -1,8 +1,6 @@
 use std::{
   collections::HashMap,
-  io::Write,
-  net::{TcpListener, TcpStream},
-  sync::{Arc, Mutex},
+  sync::Arc,
   time::Duration,
   sync::atomic::{AtomicBool, Ordering}
 };
@@ -11,12 +9,17 @@ mod helpers;
 
 pub mod types;
 
-use types::{Request, Response};
+pub use helpers::{extract_queries, header_value, percent_decode, percent_decode_path};
 
-use self::types::{Method, Nested, RequestOption, RequestPathPattern};
+use types::{Fault, Request, Response};
+
+use self::types::{Method, Nested, RequestOption, RequestPath, RequestPathPattern};
 
 // multiple threads are not needed, as tokio handles concurrency internally
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::runtime::Runtime;
+use tokio::sync::Semaphore;
 use tokio::task;
 
 pub struct Listener {
@@ -25,30 +28,34 @@ pub struct Listener {
   handler: Handler,
 }
 
-type Handler = Box<dyn Fn(Request) -> Response + Send + 'static>;
+type Handler = Box<dyn Fn(Request) -> Response + Send + Sync + 'static>;
 
 
 pub struct Server {
-  // max_connections: usize, // TODO: remove this
-  connection_handler: Arc<Mutex<ConnectionHandler>>,
+  connection_handler: ConnectionHandler,
+  max_connections: usize,
   running: Arc<AtomicBool>,
 }
 
 
-#[allow(dead_code)]
 pub struct ServerConf {
+  /// Maximum number of connections served at the same time. Further
+  /// connections wait in the accept backlog until a slot frees up.
   pub max_connections: usize,
+  /// How long an idle persistent connection is kept open.
+  pub keep_alive_timeout: Duration,
+  /// Largest request body accepted, in bytes. A request announcing a
+  /// larger body is answered with 413 and its connection is closed.
+  pub max_body_size: usize,
 }
 
 
 impl Server {
 
-  #[allow(unused_variables)]
   pub fn new(conf: ServerConf) -> Server {
     Server {
-      // max_connections: conf.max_connections,
-      // TODO properly set the max_connections
-      connection_handler: Arc::new(Mutex::new(ConnectionHandler::new())),
+      connection_handler: ConnectionHandler::new(conf.keep_alive_timeout, conf.max_body_size),
+      max_connections: conf.max_connections.max(1),
       running: Arc::new(AtomicBool::new(true)),
     }
   }
@@ -58,18 +65,32 @@ impl Server {
   //   self.running.store(false, Ordering::SeqCst);
   // }
 
-  pub fn listen(&self, addr: String) {
+  /// Accepts connections until the server is stopped. The listeners are
+  /// frozen at this point and shared by all connections without locking.
+  pub fn listen(self, addr: String) {
+    self.listen_on(std::net::TcpListener::bind(addr).unwrap());
+  }
+
+  /// Serves the connections of a listener that is already bound, e.g. to
+  /// port 0 by a caller that needs to know the port first.
+  pub fn listen_on(self, listener: std::net::TcpListener) {
     let rt = Runtime::new().unwrap();
+    let connection_handler = Arc::new(self.connection_handler);
+    let connection_slots = Arc::new(Semaphore::new(self.max_connections));
 
     rt.block_on(async {
-      let listener = TcpListener::bind(addr).unwrap();
-      
+      listener.set_nonblocking(true).unwrap();
+      let listener = TcpListener::from_std(listener).unwrap();
+
       while self.running.load(Ordering::SeqCst) {
-        if let Ok((stream, _)) = listener.accept() {
-          let connection_handler = self.connection_handler.clone();
+        // Wait for a free slot before accepting, so that at most
+        // `max_connections` connections are served at once.
+        let permit = connection_slots.clone().acquire_owned().await.unwrap();
+        if let Ok((stream, _)) = listener.accept().await {
+          let connection_handler = connection_handler.clone();
           task::spawn(async move {
-            let connection_handler = connection_handler.lock().unwrap();
-            connection_handler.handle_connection(stream);
+            ConnectionHandler::handle_connection(connection_handler, stream).await;
+            drop(permit);
           });
         }
       }
@@ -78,11 +99,9 @@ impl Server {
 
   pub fn request<F>(&mut self, request_handler: F, option: RequestOption)
   where
-    F: Fn(Request) -> Response + Send + 'static,
+    F: Fn(Request) -> Response + Send + Sync + 'static,
   {
-    let mut connection_handler = self.connection_handler.lock().unwrap();
-
-    connection_handler.listeners.push(Listener {
+    self.connection_handler.listeners.push(Listener {
       method: option.method,
       path: option.path,
       handler: Box::new(request_handler),
@@ -91,7 +110,7 @@ impl Server {
 
   pub fn get<F>(&mut self, path: &str, request_handler: F)
   where
-    F: Fn(Request) -> Response + Send + 'static,
+    F: Fn(Request) -> Response + Send + Sync + 'static,
   {
     self.request(
       request_handler,
@@ -104,7 +123,7 @@ impl Server {
 
   pub fn post<F>(&mut self, path: &str, request_handler: F)
   where
-    F: Fn(Request) -> Response + Send + 'static,
+    F: Fn(Request) -> Response + Send + Sync + 'static,
   {
     self.request(
       request_handler,
@@ -117,7 +136,7 @@ impl Server {
 
   pub fn put<F>(&mut self, path: &str, request_handler: F)
   where
-    F: Fn(Request) -> Response + Send + 'static,
+    F: Fn(Request) -> Response + Send + Sync + 'static,
   {
     self.request(
       request_handler,
@@ -128,27 +147,22 @@ impl Server {
     );
   }
 
+  pub fn delete<F>(&mut self, path: &str, request_handler: F)
+  where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+  {
+    self.request(
+      request_handler,
+      RequestOption {
+        path: RequestPathPattern::Exact(String::from(path)),
+        method: Method::Delete,
+      },
+    );
+  }
+
   #[cfg(test)]
   pub fn handle_request(&self, request: &Request) -> Response {
-    let connection_handler = self.connection_handler.lock().unwrap();
-    
-    for listener in &connection_handler.listeners {
-        if let Some(parsed_path) = helpers::parse_request_path(&listener.path, &request.path) {
-            if listener.method == request.method {
-                let mut request = request.clone();
-                request.path = parsed_path.path;
-                request.queries = parsed_path.queries;
-                request.params = parsed_path.params;
-                request.matches = parsed_path.matches;
-                return (listener.handler)(request);
-            }
-        }
-    }
-    
-    // Return 404 if no matching route is found
-    let mut body = Nested::new();
-    body.insert_string("error".to_string(), "Not Found".to_string());
-    Response::json(404, body, None)
+    self.connection_handler.route(request.clone())
   }
 
   #[cfg(test)]
@@ -157,9 +171,12 @@ impl Server {
       method: method.clone(),
       path: path.to_string(),
       headers: headers.unwrap_or(HashMap::new()),
+      raw_body: body.as_deref().unwrap_or_default().as_bytes().to_vec(),
       body: body.unwrap_or("".to_string()),
       version: "1.1".to_string(),
+      raw_url: path.to_string(),
       queries: HashMap::new(),
+      query_values: HashMap::new(),
       params: HashMap::new(),
       matches: Vec::new(),
     };
@@ -176,11 +193,7 @@ impl Response {
       String::from("application/json"),
     );
 
-    Response {
-      status,
-      body: helpers::stringify_nested(&body),
-      headers,
-    }
+    Response::new(status, helpers::stringify_nested(&body), headers)
   }
 
   pub fn ok(body: String, headers: Option<HashMap<String, String>>) -> Response {
@@ -190,78 +203,344 @@ impl Response {
       headers.insert(String::from("Content-Type"), String::from("text/plain"));
     }
 
-    Response {
-      status: 200,
-      body,
-      headers,
-    }
+    Response::new(200, body, headers)
   }
 }
 
+/// What the server sends for `Fault::Malformed`: no valid status line,
+/// headers or body.
+const MALFORMED_RESPONSE: &[u8] = b"HTTP/1.1 OK 200\r\nContent-Length: banana\r\n\x00\xff\xfe\r\n\r\n";
+
 struct ConnectionHandler {
   listeners: Vec<Listener>,
+  keep_alive_timeout: Duration,
+  max_body_size: usize,
 }
 
 impl ConnectionHandler {
-  pub fn new() -> ConnectionHandler {
+  pub fn new(keep_alive_timeout: Duration, max_body_size: usize) -> ConnectionHandler {
     ConnectionHandler {
       listeners: Vec::new(),
+      keep_alive_timeout,
+      max_body_size,
     }
   }
 
-  fn dispatch_request(&self, request: Request) -> (u16, String, String) {
-    for listener in self.listeners.iter() {
-      if let Some(parsed_path) = 
-        helpers::parse_request_path(
-          &listener.path,
-          &request.path
-        ) {
-        if listener.method == request.method {
-          let mut request = request;
-          request.path = parsed_path.path;
-          request.queries = parsed_path.queries;
-          request.params = parsed_path.params;
-          request.matches = parsed_path.matches;
-
-          let response = (listener.handler)(request);
-          let mut response_headers = String::new();
-
-          if !response.headers.is_empty() {
-            for (key, value) in response.headers.iter() {
-              response_headers.push_str(&format!("{}: {}\r\n", key, value));
-            }
-          }
-
-          return (response.status, response.body, response_headers);
+  /// Finds the listener for a request. A HEAD request without a dedicated
+  /// listener is served by the GET listener of the same path.
+  fn find_listener(&self, request: &Request) -> Option<(&Listener, RequestPath)> {
+    let find = |method: &Method| {
+      self.listeners.iter().find_map(|listener| {
+        if &listener.method != method {
+          return None;
         }
+        helpers::parse_request_path(&listener.path, &request.path)
+          .map(|parsed_path| (listener, parsed_path))
+      })
+    };
+    find(&request.method).or_else(|| match request.method {
+      Method::Head => find(&Method::Get),
+      _ => None,
+    })
+  }
+
+  /// Routes a request to its listener and returns the listener's response.
+  fn route(&self, request: Request) -> Response {
+    let is_head = request.method == Method::Head;
+    let mut response = match self.find_listener(&request) {
+      Some((listener, parsed_path)) => {
+        let mut request = request;
+        request.path = parsed_path.path;
+        request.queries = parsed_path.queries;
+        request.query_values = parsed_path.query_values;
+        request.params = parsed_path.params;
+        request.matches = parsed_path.matches;
+        (listener.handler)(request)
+      }
+      None => {
+        let mut body = Nested::new();
+        body.insert_string("error".to_string(), "Not Found".to_string());
+        Response::json(404, body, None)
       }
+    };
+
+    // A HEAD response carries the headers of the equivalent GET, including
+    // its Content-Length, but never a body.
+    if is_head {
+      response
+        .headers
+        .entry(String::from("Content-Length"))
+        .or_insert_with(|| response.body.len().to_string());
+      response.body.clear();
+    }
+    response
+  }
+
+  /// Routes a request and renders the response head. The returned flag tells
+  /// whether the connection may serve further requests.
+  fn dispatch_request(&self, request: Request) -> (u16, String, String, bool, Duration, Option<Fault>) {
+    let client_keep_alive = helpers::is_keep_alive(&request);
+    let mut response = self.route(request);
+    // A listener may also ask for the connection to be closed.
+    // After a broken response the connection cannot be reused either.
+    let keep_alive = client_keep_alive
+      && !helpers::header_value(&response.headers, "Connection")
+        .is_some_and(|value| value.eq_ignore_ascii_case("close"))
+      && matches!(response.fault, None | Some(Fault::Trickle(_)));
+    response.headers.retain(|key, _| !key.eq_ignore_ascii_case("Connection"));
+    if let Some(Fault::ContentLength(offset)) = response.fault {
+      let length = (response.body.len() as i64).saturating_add(offset).max(0);
+      response.headers.retain(|key, _| !key.eq_ignore_ascii_case("Content-Length"));
+      response.headers.insert(String::from("Content-Length"), length.to_string());
+    }
+
+    let mut response_headers = String::new();
+    for (key, value) in response.headers.iter() {
+      response_headers.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    if !response.headers.contains_key("Content-Length") {
+      response_headers.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
     }
-    
-    (404, String::new(), String::new())
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    response_headers.push_str(&format!("Connection: {}\r\n", connection));
+    (response.status, response.body, response_headers, keep_alive, response.delay, response.fault)
   }
 
-  pub fn handle_connection(&self, mut stream: TcpStream) {
-    // Set read timeout to prevent hanging
-    stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
-    
-    let result = (|| {
-        let request = helpers::parse_tcp_stream(&mut stream)?;
-        let (response_status, response_body, response_headers) = 
-            self.dispatch_request(request);
-        let length = response_body.len();
-        let response = format!(
-            "HTTP/1.1 {response_status}\r\n{response_headers}Content-Length: {length}\r\n\r\n{response_body}"
-        );
-        stream.write_all(response.as_bytes())?;
-        stream.flush()?;
+  /// Serves requests from a connection until either side closes it or it
+  /// stays idle for longer than the keep-alive timeout. Pipelined requests
+  /// are answered in the order they were received.
+  pub async fn handle_connection(connection_handler: Arc<ConnectionHandler>, stream: TcpStream) {
+    let keep_alive_timeout = connection_handler.keep_alive_timeout;
+    let max_body_size = connection_handler.max_body_size;
+    let result = async {
+        let (reader, mut writer) = stream.into_split();
+        // The reader is kept across requests so that bytes of pipelined
+        // requests buffered with an earlier one are not lost.
+        let mut reader = BufReader::new(reader);
+        loop {
+            // Bound the idle time between two requests on a persistent connection.
+            let request = match tokio::time::timeout(
+                keep_alive_timeout,
+                helpers::parse_request(&mut reader, max_body_size),
+            ).await {
+                // The body is left unread, so the connection cannot serve
+                // another request.
+                Ok(Err(e)) if e.kind() == std::io::ErrorKind::FileTooLarge => {
+                    let body = format!("Request body larger than {} bytes", max_body_size);
+                    let response = format!(
+                        "HTTP/1.1 413\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(), body
+                    );
+                    writer.write_all(response.as_bytes()).await?;
+                    writer.flush().await?;
+                    break;
+                }
+                Ok(request) => request?,
+                Err(_) => break,
+            };
+            let Some(request) = request else { break };
+
+            // Handlers are synchronous and may block (file access, upstream
+            // calls), so they run on the blocking pool instead of the reactor.
+            let handler = connection_handler.clone();
+            let (response_status, response_body, response_headers, keep_alive, delay, fault) =
+                task::spawn_blocking(move || handler.dispatch_request(request))
+                    .await
+                    .map_err(std::io::Error::other)?;
+
+            // Delays are timers, so a slow condition holds only its own connection.
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            let mut response = format!(
+                "HTTP/1.1 {response_status}\r\n{response_headers}\r\n{response_body}"
+            ).into_bytes();
+            match fault {
+                None | Some(Fault::ContentLength(_)) => writer.write_all(&response).await?,
+                Some(Fault::Reset) => {
+                    // Closing with a zero linger time sends RST instead of FIN.
+                    writer.as_ref().set_linger(Some(Duration::ZERO))?;
+                    break;
+                }
+                Some(Fault::Close) => break,
+                Some(Fault::Truncate(bytes)) => {
+                    response.truncate(response.len() - response_body.len() + bytes.min(response_body.len()));
+                    writer.write_all(&response).await?;
+                }
+                Some(Fault::Trickle(bytes_per_sec)) => {
+                    // Write a chunk every 100ms, so slow rates are not bursty.
+                    let chunk_size = (bytes_per_sec / 10).max(1) as usize;
+                    let interval = Duration::from_secs_f64(chunk_size as f64 / bytes_per_sec.max(1) as f64);
+                    for chunk in response.chunks(chunk_size) {
+                        writer.write_all(chunk).await?;
+                        writer.flush().await?;
+                        tokio::time::sleep(interval).await;
+                    }
+                }
+                Some(Fault::Malformed) => writer.write_all(MALFORMED_RESPONSE).await?,
+            }
+            writer.flush().await?;
+            if !keep_alive {
+                break;
+            }
+        }
         Ok::<_, std::io::Error>(())
-    })();
+    }.await;
 
     if let Err(e) = result {
         eprintln!("Connection error: {}", e);
     }
-    
-    // Ensure stream is properly closed
-    drop(stream);
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::{Read, Write};
+  use std::time::Instant;
+
+  fn spawn_test_server(max_connections: usize) -> std::net::SocketAddr {
+    let mut server = Server::new(ServerConf {
+      max_connections,
+      keep_alive_timeout: Duration::from_millis(500),
+      max_body_size: 1024,
+    });
+    server.get("/hello", |_| Response::ok("hello".to_string(), None));
+    server.get("/slow", |_| {
+      Response::ok("slow".to_string(), None).with_delay(Duration::from_millis(300))
+    });
+    let faults = [
+      ("/reset", Fault::Reset),
+      ("/close", Fault::Close),
+      ("/truncate", Fault::Truncate(3)),
+      ("/content-length", Fault::ContentLength(5)),
+      ("/trickle", Fault::Trickle(100)),
+      ("/malformed", Fault::Malformed),
+    ];
+    for (path, fault) in faults {
+      server.get(path, move |_| Response::ok("0123456789".to_string(), None).with_fault(Some(fault.clone())));
+    }
+    // Connections wait in the backlog of the bound listener until the server accepts them.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || server.listen_on(listener));
+    addr
+  }
+
+  fn get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream
+      .write_all(format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path).as_bytes())
+      .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+  }
+
+  #[test]
+  fn test_keep_alive_serves_pipelined_requests() {
+    let addr = spawn_test_server(10);
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream
+      .write_all(b"GET /hello HTTP/1.1\r\n\r\nGET /hello HTTP/1.1\r\n\r\nGET /hello HTTP/1.1\r\nConnection: close\r\n\r\n")
+      .unwrap();
+
+    // The server closes the connection after the third response.
+    let mut responses = String::new();
+    stream.read_to_string(&mut responses).unwrap();
+    assert_eq!(responses.matches("HTTP/1.1 200").count(), 3);
+    assert_eq!(responses.matches("Connection: keep-alive").count(), 2);
+    assert_eq!(responses.matches("Connection: close").count(), 1);
+  }
+
+  #[test]
+  fn test_body_too_large_is_rejected() {
+    let addr = spawn_test_server(10);
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream
+      .write_all(b"GET /hello HTTP/1.1\r\nContent-Length: 100000000000\r\n\r\nGET /hello HTTP/1.1\r\n\r\n")
+      .unwrap();
+
+    // The server answers 413 and closes the connection without serving the next request.
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 413"));
+    assert!(response.contains("Connection: close"));
+    assert!(!response.contains("HTTP/1.1 200"));
+    assert!(get(addr, "/hello").starts_with("HTTP/1.1 200"));
+  }
+
+  #[test]
+  fn test_idle_connection_is_closed_after_timeout() {
+    let addr = spawn_test_server(10);
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /hello HTTP/1.1\r\n\r\n").unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let mut responses = String::new();
+    stream.read_to_string(&mut responses).unwrap();
+    assert_eq!(responses.matches("HTTP/1.1 200").count(), 1);
+  }
+
+  #[test]
+  fn test_delay_does_not_block_other_connections() {
+    let addr = spawn_test_server(10);
+    let slow = std::thread::spawn(move || get(addr, "/slow"));
+    std::thread::sleep(Duration::from_millis(50));
+
+    let started = Instant::now();
+    assert!(get(addr, "/hello").ends_with("hello"));
+    assert!(started.elapsed() < Duration::from_millis(250));
+    assert!(slow.join().unwrap().ends_with("slow"));
+  }
+
+  #[test]
+  fn test_faults() {
+    let addr = spawn_test_server(10);
+    assert_eq!(get(addr, "/close"), "");
+    assert!(get(addr, "/truncate").ends_with("\r\n\r\n012"));
+    let response = get(addr, "/content-length");
+    assert!(response.contains("Content-Length: 15\r\n") && response.ends_with("0123456789"));
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /malformed HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    assert_eq!(response, MALFORMED_RESPONSE);
+
+    // The keep-alive request is not read: the fault ends the connection first.
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /reset HTTP/1.1\r\n\r\nGET /hello HTTP/1.1\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    match stream.read_to_end(&mut response) {
+      Ok(_) => assert!(response.is_empty()),
+      Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+    }
+  }
+
+  #[test]
+  fn test_trickle_fault_slows_the_response() {
+    let addr = spawn_test_server(10);
+    let started = Instant::now();
+    let response = get(addr, "/trickle");
+    assert!(response.starts_with("HTTP/1.1 200") && response.ends_with("0123456789"));
+    // About 70 bytes at 100 bytes per second.
+    assert!(started.elapsed() >= Duration::from_millis(500));
+  }
+
+  #[test]
+  fn test_max_connections_limits_concurrent_connections() {
+    let addr = spawn_test_server(1);
+    // Hold the only slot with an idle keep-alive connection.
+    let mut idle = std::net::TcpStream::connect(addr).unwrap();
+    idle.write_all(b"GET /hello HTTP/1.1\r\n\r\n").unwrap();
+    let mut buffer = [0; 64];
+    let _ = idle.read(&mut buffer).unwrap();
+
+    // The next connection is served only once the idle one times out.
+    let started = Instant::now();
+    assert!(get(addr, "/hello").ends_with("hello"));
+    assert!(started.elapsed() >= Duration::from_millis(300));
   }
 }
@@ -2,10 +2,11 @@ use regex::Regex;
 
 use std::{
   collections::HashMap,
-  io::{BufRead, BufReader, Error as IoError, Read},
-  net::TcpStream,
+  io::{Error as IoError, ErrorKind},
 };
 
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
 use super::types::{Nested, NestedValue, Request, RequestPath, RequestPathPattern, Method};
 
 
@@ -42,23 +43,85 @@ fn stringfy_nested_value(nested: &NestedValue) -> String {
 }
 
 
-/// Extracts queries from a query string.
-fn extract_queries(query_string: &str) -> HashMap<String, String> {
-  query_string
-  .split('&')
-  .filter_map(|pair| {
-      let mut parts = pair.split('=');
-      match (parts.next(), parts.next()) {
-          (Some(key), Some(value)) => Some((key.to_string(), value.to_string())),
-          _ => None
+/// Decodes `%XX` escapes. With `plus_as_space`, `+` decodes to a space as in
+/// `application/x-www-form-urlencoded` data. Malformed escapes are kept as is,
+/// and invalid UTF-8 is replaced rather than rejected.
+pub fn percent_decode(input: &str, plus_as_space: bool) -> String {
+  let bytes = input.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      // Checked byte by byte: a multibyte character after `%` is no escape.
+      b'%' if i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() => {
+        decoded.push(hex_value(bytes[i + 1]) << 4 | hex_value(bytes[i + 2]));
+        i += 3;
+        continue;
       }
-  })
-  .collect()
+      b'+' if plus_as_space => decoded.push(b' '),
+      byte => decoded.push(byte),
+    }
+    i += 1;
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
 }
 
 
-/// Splits request path into path and query parameters
-fn split_path_and_queries(request_path: &str) -> (&str, HashMap<String, String>) {
+/// Returns the value of an ASCII hex digit.
+fn hex_value(digit: u8) -> u8 {
+  match digit {
+    b'0'..=b'9' => digit - b'0',
+    b'a'..=b'f' => digit - b'a' + 10,
+    _ => digit - b'A' + 10,
+  }
+}
+
+
+/// Decodes a request path, except for encoded slashes (`%2F`), which stay
+/// encoded so that they cannot change how the path splits into segments.
+pub fn percent_decode_path(path: &str) -> String {
+  path
+    .split('/')
+    .map(|segment| {
+      segment
+        .split("%2F")
+        .flat_map(|part| part.split("%2f"))
+        .map(|part| percent_decode(part, false))
+        .collect::<Vec<_>>()
+        .join("%2F")
+    })
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+
+/// Extracts queries from a query string. Keys and values are percent-decoded,
+/// a key without `=` has an empty value, and repeated keys keep every value
+/// in order of appearance.
+pub fn extract_queries(query_string: &str) -> HashMap<String, Vec<String>> {
+  let mut queries: HashMap<String, Vec<String>> = HashMap::new();
+  for pair in query_string.split('&').filter(|pair| !pair.is_empty()) {
+    let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+    queries
+      .entry(percent_decode(key, true))
+      .or_default()
+      .push(percent_decode(value, true));
+  }
+  queries
+}
+
+
+/// Keeps the first value of each query, for matchers that expect one value.
+fn first_query_values(query_values: &HashMap<String, Vec<String>>) -> HashMap<String, String> {
+  query_values
+    .iter()
+    .filter_map(|(key, values)| values.first().map(|value| (key.clone(), value.clone())))
+    .collect()
+}
+
+
+/// Splits request path into path and query parameters, keeping all values of repeated keys
+fn split_path_and_query_values(request_path: &str) -> (&str, HashMap<String, Vec<String>>) {
     match request_path.find('?') {
       None => (request_path, HashMap::new()),
       Some(query_start) => {
@@ -70,6 +133,23 @@ fn split_path_and_queries(request_path: &str) -> (&str, HashMap<String, String>)
 }
 
 
+/// Splits request path into path and query parameters
+#[cfg(test)]
+fn split_path_and_queries(request_path: &str) -> (&str, HashMap<String, String>) {
+    let (path, query_values) = split_path_and_query_values(request_path);
+    (path, first_query_values(&query_values))
+}
+
+
+/// Removes the trailing slash of a path, except for the root path.
+fn trim_trailing_slash(path: &str) -> &str {
+  match path.trim_end_matches('/') {
+    "" => "/",
+    trimmed => trimmed,
+  }
+}
+
+
 /// Compares the number of slashes in a pattern and a path.
 fn compare_slash_counts(pattern: &str, path: &str) -> bool {
   let number_of_slashes_in_pattern = pattern.matches('/').count();
@@ -79,11 +159,14 @@ fn compare_slash_counts(pattern: &str, path: &str) -> bool {
 
 
 /// Handles exact path matching with parameters
-fn handle_exact_path(pattern: &str, path: &str, queries: &HashMap<String, String>) -> Option<RequestPath> {
+fn handle_exact_path(pattern: &str, path: &str, queries: &HashMap<String, Vec<String>>) -> Option<RequestPath> {
   if !queries.is_empty() {
     // exact match does not allow queries
     return None;
   }
+  // "/projects/foo/" is the same path as "/projects/foo"
+  let pattern = trim_trailing_slash(pattern);
+  let path = trim_trailing_slash(path);
   // count the number of slashes "/" in the pattern and path
   // if the number of slashes is not the same, then the path does not match the pattern => return None
   if !compare_slash_counts(pattern, path) {
@@ -96,8 +179,8 @@ fn handle_exact_path(pattern: &str, path: &str, queries: &HashMap<String, String
   }
   let mut params = HashMap::new();
   for (pattern, request) in pattern_segments.iter().zip(path_segments.iter()) {
-      if pattern.starts_with(':') {
-          params.insert(pattern[1..].to_string(), request.to_string());
+      if let Some(name) = pattern.strip_prefix(':') {
+          params.insert(name.to_string(), percent_decode(request, false));
       } else if pattern != request {
           return None;
       }
@@ -105,6 +188,7 @@ fn handle_exact_path(pattern: &str, path: &str, queries: &HashMap<String, String
   Some(RequestPath {
       path: path.to_string(),
       queries: HashMap::new(),
+      query_values: HashMap::new(),
       params,
       matches: Vec::new(),
   })
@@ -123,20 +207,21 @@ fn construct_matches(regex: &Regex, full_path: &str) -> Option<Vec<String>> {
 }
 
 /// Handles regex pattern matching
-fn handle_regex_path(pattern: &str, full_path: &str, path: &str, queries: &HashMap<String, String>) -> Option<RequestPath> {
+fn handle_regex_path(pattern: &str, full_path: &str, path: &str, queries: &HashMap<String, Vec<String>>) -> Option<RequestPath> {
     // from main.rs
-    // currently we only support one level of API path
-    // let r = r"^/projects/(\w+)((/\w+)+)(\?(\w+=\w+)(&\w+=\w+)*)?$";  // <-- pattern
+    // let r = r"^/projects/([^/?#]+)(/[^?#]*)(?:\?.*)?$";  // <-- pattern
     // explain: 
     // 1. /projects/ - matches the exact path
-    // 2. (\w+) - matches the project name (matches[0])
-    // 3. (/\w+)+ - matches the API path (with starting slash, e.g. "/api/v1") (matches[1])
-    // 4. (\?(\w+=\w+)(&\w+=\w+)*)? - matches the queries (matches[3])
+    // 2. ([^/?#]+) - matches the raw project name (matches[0])
+    // 3. (/[^?#]*) - matches the raw API path (with starting slash, e.g. "/api/v1") (matches[1])
+    // 4. (?:\?.*)? - skips the query string, which is parsed separately
+    // The matches are returned raw; percent-decoding is up to the handler.
     let regexp = Regex::new(pattern).ok()?;
     let matches = construct_matches(&regexp, full_path)?;
     Some(RequestPath {
         path: path.to_string(),
-        queries: queries.clone(),
+        queries: first_query_values(queries),
+        query_values: queries.clone(),
         params: HashMap::new(),
         matches,
     })
@@ -151,7 +236,7 @@ pub fn parse_request_path(
     request_path: &str,
 ) -> Option<RequestPath> {
     let (path, queries) = 
-      split_path_and_queries(request_path);
+      split_path_and_query_values(request_path);
     match path_pattern {
         RequestPathPattern::Exact(pattern) => 
           handle_exact_path(pattern, path, &queries),
@@ -161,62 +246,106 @@ pub fn parse_request_path(
 }
 
 
-pub fn parse_tcp_stream(stream: &mut TcpStream) -> Result<Request, IoError> {
-  let mut buf_reader = BufReader::new(stream);
+/// Returns the value of a header, looking its name up case-insensitively.
+pub fn header_value<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+  headers
+    .iter()
+    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+    .map(|(_, value)| value.as_str())
+}
+
+
+/// Tells whether the connection may be reused after answering a request.
+/// HTTP/1.1 connections persist unless either side sends `Connection: close`;
+/// HTTP/1.0 connections persist only when the client asks for `keep-alive`.
+pub fn is_keep_alive(request: &Request) -> bool {
+  let connection = header_value(&request.headers, "Connection")
+    .map(|value| value.to_ascii_lowercase());
+  match connection.as_deref() {
+    Some(value) if value.split(',').any(|token| token.trim() == "close") => false,
+    Some(value) if value.split(',').any(|token| token.trim() == "keep-alive") => true,
+    _ => request.version != "HTTP/1.0",
+  }
+}
+
+
+/// Reads one request from a buffered reader.
+/// Returns `Ok(None)` when the peer closed the connection before sending
+/// anything, so that callers can read pipelined requests in a loop, and an
+/// error of kind `FileTooLarge` when the body is larger than `max_body_size`
+/// bytes, before reading it.
+pub async fn parse_request<R: AsyncBufRead + Unpin>(buf_reader: &mut R, max_body_size: usize) -> Result<Option<Request>, IoError> {
   let mut start_line = String::new();
-  buf_reader.read_line(&mut start_line)?;
+  // Tolerate empty lines between pipelined requests (RFC 9112, section 2.2).
+  while start_line.trim().is_empty() {
+    start_line.clear();
+    if buf_reader.read_line(&mut start_line).await? == 0 {
+      return Ok(None);
+    }
+  }
 
+  let invalid = |message: &str| IoError::new(ErrorKind::InvalidData, message.to_string());
   let mut start_line_parts = start_line.split_whitespace();
-  let method = start_line_parts.next().unwrap().to_owned();
-  let path = start_line_parts.next().unwrap().to_owned();
-  let version = start_line_parts.next().unwrap().to_owned();
+  let method: Method = start_line_parts
+    .next()
+    .ok_or_else(|| invalid("Missing request method"))?
+    .parse()
+    .map_err(|e: String| IoError::new(ErrorKind::InvalidInput, e))?;
+  let path = start_line_parts
+    .next()
+    .ok_or_else(|| invalid("Missing request target"))?
+    .to_owned();
+  let version = start_line_parts
+    .next()
+    .ok_or_else(|| invalid("Missing HTTP version"))?
+    .to_owned();
 
   // Read the headers.
   let mut headers = HashMap::new();
   loop {
     let mut line = String::new();
-    buf_reader.read_line(&mut line)?;
+    if buf_reader.read_line(&mut line).await? == 0 {
+      return Err(IoError::new(ErrorKind::UnexpectedEof, "Connection closed inside headers"));
+    }
     if line.trim().is_empty() {
       break;
     }
     if let Some(pos) = line.find(':') {
-      let key = line[..pos].trim().to_owned();
+      // Header names are case-insensitive (RFC 9110, section 5.1).
+      let key = line[..pos].trim().to_ascii_lowercase();
       let value = line[pos + 1..].trim().to_owned();
       headers.insert(key, value);
     }
   }
 
-  // Read the body.
-  let mut body = String::new();
-  if method == "POST" || method == "PUT" {
-    let content_length = headers
-      .get("Content-Length")
-      .and_then(|v| v.parse::<usize>().ok())
-      .unwrap_or(0);
-
-    if content_length > 0 {
-      let mut buffer = vec![0; content_length];
-      buf_reader.read_exact(&mut buffer)?;
-      body = String::from_utf8(buffer).unwrap();
-    }
+  // Read the body. Any method may carry one (e.g. PATCH or DELETE), so rely
+  // on Content-Length alone rather than on the method.
+  let content_length = header_value(&headers, "Content-Length")
+    .and_then(|v| v.parse::<usize>().ok())
+    .unwrap_or(0);
+  if content_length > max_body_size {
+    return Err(IoError::new(ErrorKind::FileTooLarge, "Request body too large"));
   }
 
-  Ok(Request {
-    path,
+  // Bodies may be binary (e.g. file uploads), so keep the bytes and decode
+  // them leniently for the text-based matchers.
+  let mut raw_body = vec![0; content_length];
+  buf_reader.read_exact(&mut raw_body).await?;
+  let body = String::from_utf8_lossy(&raw_body).into_owned();
+
+  Ok(Some(Request {
+    path: path.clone(),
     version,
-    method: match method.as_str() {
-        "GET" => Method::Get,
-        "POST" => Method::Post,
-        "PUT" => Method::Put,
-        //"DELETE" => Method::Delete,
-        _ => Method::Get, // Default case, adjust as needed
-    },
+    method,
     headers,
     body,
+    raw_body,
+    raw_url: path,
     queries: HashMap::new(),
+    query_values: HashMap::new(),
     params: HashMap::new(),
     matches: Vec::new(),
-  })
+  }))
 }
 
 
@@ -339,4 +468,103 @@ mod tests {
       );
     assert!(result.is_none());
   }
+
+  #[tokio::test]
+  async fn test_parse_request_pipelined() {
+    let raw = "GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n\
+      POST /second HTTP/1.1\r\ncontent-length: 5\r\n\r\nhello\
+      DELETE /third HTTP/1.1\r\nConnection: close\r\n\r\n";
+    let mut reader = raw.as_bytes();
+
+    let first = parse_request(&mut reader, 1024).await.unwrap().unwrap();
+    assert_eq!(first.method, Method::Get);
+    assert_eq!(first.path, "/first");
+
+    let second = parse_request(&mut reader, 1024).await.unwrap().unwrap();
+    assert_eq!(second.method, Method::Post);
+    assert_eq!(second.headers.get("content-length").unwrap(), "5");
+    assert_eq!(second.body, "hello");
+    assert!(is_keep_alive(&second));
+
+    let third = parse_request(&mut reader, 1024).await.unwrap().unwrap();
+    assert_eq!(third.method, Method::Delete);
+    assert!(!is_keep_alive(&third));
+
+    assert!(parse_request(&mut reader, 1024).await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn test_parse_request_binary_body() {
+    let raw = b"POST /upload HTTP/1.1\r\nContent-Length: 4\r\n\r\n\x89P\xffG";
+    let mut reader = &raw[..];
+    let request = parse_request(&mut reader, 1024).await.unwrap().unwrap();
+    assert_eq!(request.raw_body, b"\x89P\xffG");
+    assert_eq!(request.body, "\u{fffd}P\u{fffd}G");
+  }
+
+  #[tokio::test]
+  async fn test_parse_request_body_too_large() {
+    let raw = "DELETE /x HTTP/1.1\r\nContent-Length: 100000000000\r\n\r\n";
+    let mut reader = raw.as_bytes();
+    let error = parse_request(&mut reader, 1024).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::FileTooLarge);
+
+    let raw = "POST /x HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody";
+    let mut reader = raw.as_bytes();
+    assert_eq!(parse_request(&mut reader, 4).await.unwrap().unwrap().body, "body");
+  }
+
+  #[tokio::test]
+  async fn test_is_keep_alive_http_1_0() {
+    let raw = "GET / HTTP/1.0\r\n\r\nGET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n";
+    let mut reader = raw.as_bytes();
+    assert!(!is_keep_alive(&parse_request(&mut reader, 1024).await.unwrap().unwrap()));
+    assert!(is_keep_alive(&parse_request(&mut reader, 1024).await.unwrap().unwrap()));
+  }
+
+  #[test]
+  fn test_split_path_and_queries_decodes_values() {
+    let (path, queries) =
+      split_path_and_queries("/api/search?q=hello%20world&tags=a,b&name=John+Doe&empty=&flag");
+    assert_eq!(path, "/api/search");
+    assert_eq!(queries.get("q").unwrap(), "hello world");
+    assert_eq!(queries.get("tags").unwrap(), "a,b");
+    assert_eq!(queries.get("name").unwrap(), "John Doe");
+    assert_eq!(queries.get("empty").unwrap(), "");
+    assert_eq!(queries.get("flag").unwrap(), "");
+  }
+
+  #[test]
+  fn test_repeated_query_keys_are_kept_as_lists() {
+    let result = parse_request_path(
+      &RequestPathPattern::Match(String::from(r"^/items(?:\?.*)?$")),
+      "/items?id=1&id=2&id=3",
+    ).unwrap();
+    assert_eq!(result.queries.get("id").unwrap(), "1");
+    assert_eq!(result.query_values.get("id").unwrap(), &vec!["1", "2", "3"]);
+  }
+
+  #[test]
+  fn test_exact_path_ignores_trailing_slash() {
+    let result = parse_request_path(
+      &RequestPathPattern::Exact(String::from("/projects/:name")),
+      "/projects/my.project-1/",
+    );
+    assert_eq!(result.unwrap().params.get("name").unwrap(), "my.project-1");
+  }
+
+  #[test]
+  fn test_percent_decode_path_keeps_encoded_slashes() {
+    assert_eq!(percent_decode_path("/api/caf%C3%A9/a%2Fb/x+y"), "/api/café/a%2Fb/x+y");
+    assert_eq!(percent_decode("100%", false), "100%");
+  }
+
+  #[test]
+  fn test_percent_decode_multibyte_after_percent() {
+    assert_eq!(percent_decode_path("/projects/x/a%aé"), "/projects/x/a%aé");
+    assert_eq!(percent_decode("%é1", true), "%é1");
+    assert_eq!(percent_decode("a%é", false), "a%é");
+    assert_eq!(extract_queries("q=%é1&r=%41%e9")["q"], vec!["%é1"]);
+    assert_eq!(percent_decode("%41%4a%4A", false), "AJJ");
+  }
 }
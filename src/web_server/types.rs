@@ -1,10 +1,13 @@
 use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
 use serde::{Serialize, Deserialize};
 
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct RequestPath {
   pub path: String,
   pub queries: HashMap<String, String>,
+  pub query_values: HashMap<String, Vec<String>>,
   pub params: HashMap<String, String>,
   pub matches: Vec<String>,
 }
@@ -17,8 +20,16 @@ pub struct Request {
   pub path: String,
   pub version: String,
   pub headers: HashMap<String, String>,
+  /// The body decoded as UTF-8, with invalid sequences replaced.
   pub body: String,
+  /// The body bytes as received, e.g. for binary file uploads.
+  pub raw_body: Vec<u8>,
+  /// The request target exactly as received, e.g. `/projects/p/a%20b?x=1&x=2`.
+  pub raw_url: String,
+  /// Percent-decoded queries; the first value wins for repeated keys.
   pub queries: HashMap<String, String>,
+  /// Percent-decoded queries with every value of repeated keys.
+  pub query_values: HashMap<String, Vec<String>>,
   pub params: HashMap<String, String>,
   pub matches: Vec<String>,
 }
@@ -26,20 +37,58 @@ pub struct Request {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Method {
   Get,
+  Head,
   Post,
   Put,
+  Delete,
+  Patch,
+  Options,
+  Trace,
+  Connect,
 }
 
 impl Method {
+  /// All methods defined by RFC 9110 and RFC 5789, in a stable order.
+  pub const ALL: [Method; 9] = [
+    Method::Get,
+    Method::Head,
+    Method::Post,
+    Method::Put,
+    Method::Delete,
+    Method::Patch,
+    Method::Options,
+    Method::Trace,
+    Method::Connect,
+  ];
+
   pub fn to_string(&self) -> String {
     match self {
       Method::Get => String::from("GET"),
+      Method::Head => String::from("HEAD"),
       Method::Post => String::from("POST"),
       Method::Put => String::from("PUT"),
+      Method::Delete => String::from("DELETE"),
+      Method::Patch => String::from("PATCH"),
+      Method::Options => String::from("OPTIONS"),
+      Method::Trace => String::from("TRACE"),
+      Method::Connect => String::from("CONNECT"),
     }
   }
 }
 
+impl FromStr for Method {
+  type Err = String;
+
+  /// Parses a method token case-insensitively, e.g. "GET" or "delete".
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Method::ALL
+      .iter()
+      .find(|method| method.to_string().eq_ignore_ascii_case(s))
+      .cloned()
+      .ok_or_else(|| format!("Unsupported HTTP method: {}", s))
+  }
+}
+
 #[derive(Clone, Debug)]
 pub enum RequestPathPattern {
   Exact(String),
@@ -57,9 +106,52 @@ pub struct Response {
   pub status: u16,
   pub body: String,
   pub headers: HashMap<String, String>,
+  /// Time to wait before the response is written. The server waits on a
+  /// timer, so handlers never have to sleep themselves.
+  pub delay: Duration,
+  /// Failure to simulate on the connection instead of writing the response
+  /// as is.
+  pub fault: Option<Fault>,
+}
+
+/// A connection-level failure, for testing how clients cope with broken servers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+  /// Reset the connection (TCP RST) without responding.
+  Reset,
+  /// Close the connection without responding.
+  Close,
+  /// Send the head and only the first bytes of the body, then close.
+  Truncate(usize),
+  /// Send a Content-Length that is off by the given number of bytes, then close.
+  ContentLength(i64),
+  /// Send the response at the given number of bytes per second.
+  Trickle(u64),
+  /// Send bytes that are not an HTTP response, then close.
+  Malformed,
 }
 
 impl Response {
+  pub fn new(status: u16, body: String, headers: HashMap<String, String>) -> Response {
+    Response {
+      status,
+      body,
+      headers,
+      delay: Duration::ZERO,
+      fault: None,
+    }
+  }
+
+  pub fn with_delay(mut self, delay: Duration) -> Response {
+    self.delay = delay;
+    self
+  }
+
+  pub fn with_fault(mut self, fault: Option<Fault>) -> Response {
+    self.fault = fault;
+    self
+  }
+
   pub fn html(body: String) -> Response {
     let mut headers = HashMap::new();
     headers.insert(
@@ -67,11 +159,7 @@ impl Response {
       String::from("text/html"),
     );
     
-    Response {
-      status: 200,
-      body,
-      headers,
-    }
+    Response::new(200, body, headers)
   }
 }
 